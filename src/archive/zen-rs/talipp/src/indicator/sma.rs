@@ -23,6 +23,11 @@ impl SMA {
     pub fn last(&self) -> f32 {
         *self.queue.back().unwrap_or(&0.0)
     }
+
+    // 已有 period 个值时 ma 才是完整的均线
+    pub fn is_full(&self) -> bool {
+        self.queue.len() >= self.period as usize
+    }
 }
 
 impl Indicator for SMA {
@@ -0,0 +1,3 @@
+DROP TABLE IF EXISTS drawing_templates;
+DROP TABLE IF EXISTS study_templates;
+DROP TABLE IF EXISTS charts;
@@ -0,0 +1,29 @@
+-- TradingView save/load adapter storage
+CREATE TABLE IF NOT EXISTS charts (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    client TEXT NOT NULL,
+    user TEXT NOT NULL,
+    name TEXT NOT NULL,
+    symbol TEXT NOT NULL,
+    resolution TEXT NOT NULL,
+    content TEXT NOT NULL,
+    timestamp BIGINT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS charts_owner ON charts (client, user);
+
+CREATE TABLE IF NOT EXISTS study_templates (
+    client TEXT NOT NULL,
+    user TEXT NOT NULL,
+    name TEXT NOT NULL,
+    content TEXT NOT NULL,
+    PRIMARY KEY (client, user, name)
+);
+
+CREATE TABLE IF NOT EXISTS drawing_templates (
+    client TEXT NOT NULL,
+    user TEXT NOT NULL,
+    tool TEXT NOT NULL,
+    name TEXT NOT NULL,
+    content TEXT NOT NULL,
+    PRIMARY KEY (client, user, tool, name)
+);
@@ -0,0 +1,15 @@
+-- low code dashboard，每次修改保存为一个新版本
+CREATE TABLE IF NOT EXISTS dashboards (
+    id INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    name TEXT NOT NULL,
+    version INTEGER NOT NULL,
+    updated BIGINT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS dashboard_versions (
+    dashboard_id INTEGER NOT NULL,
+    version INTEGER NOT NULL,
+    content TEXT NOT NULL,
+    created BIGINT NOT NULL,
+    PRIMARY KEY (dashboard_id, version)
+);
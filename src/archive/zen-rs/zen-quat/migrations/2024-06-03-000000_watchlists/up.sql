@@ -0,0 +1,8 @@
+-- 每个用户的自选列表，symbols 为换行分隔的代码
+CREATE TABLE IF NOT EXISTS watchlists (
+    user TEXT NOT NULL,
+    name TEXT NOT NULL,
+    symbols TEXT NOT NULL,
+    updated BIGINT NOT NULL,
+    PRIMARY KEY (user, name)
+);
@@ -1,10 +1,13 @@
-use actix::{Actor, AsyncContext, ContextFutureSpawner, Handler, StreamHandler, WrapFuture};
+use actix::{
+    Actor, ActorFutureExt, AsyncContext, ContextFutureSpawner, Handler, StreamHandler, WrapFuture,
+};
 use std::cell::RefCell;
 use std::cmp::max;
 use std::collections::HashMap;
-use std::ops::{Deref, DerefMut, Sub};
+use std::ops::{DerefMut, Sub};
+use std::rc::Rc;
 use std::string::ToString;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
 use actix_web::web::Json;
@@ -21,17 +24,23 @@ use tws_rs::contracts::{
 };
 use zen_core::objects::enums::{Direction, Freq};
 
-use crate::api::jsonrpc::types::error::code::ErrorCode;
-use crate::api::jsonrpc::types::request::RpcRequest;
-use crate::api::jsonrpc::types::response::{RpcPayload, RpcResponse};
+use crate::api::jsonrpc::router::{first_param, Router};
+use crate::api::jsonrpc::types::error::code::{ErrorCode, CALL_EXECUTION_FAILED_CODE};
+use crate::api::jsonrpc::types::error::object::ErrorObject;
+use crate::api::jsonrpc::types::request::RpcNotification;
+use crate::api::jsonrpc::types::response::RpcResponse;
 use crate::api::params::{
-    BiInfo, Config, Exchange, HistoryRequest, HistoryResponse, LibrarySymbolInfo, OptionPriceItem,
-    OptionPriceRequest, SearchRequest, SearchSymbolResultItem, SymbolRequest, ZenBiDetail,
-    ZenRequest, ZenResponse,
+    BiInfo, Config, Exchange, HistoryRequest, HistoryResponse, LibrarySymbolInfo, Mark, MarkColor,
+    MarksRequest, OptionPriceItem, OptionPriceRequest, SearchRequest, SearchSymbolResultItem,
+    SymbolRequest, TimescaleMark, ZenRequest, ZenResponse,
 };
+use crate::api::subscribe::{bi_details, unfinished_bi, Subscription, PUSH_INTERVAL};
+use crate::auth::AuthUser;
 use crate::broker::ib::IB;
-use crate::broker::mixed::MixedBroker;
+use crate::broker::mixed::Mixed;
+use crate::broker::shared::SharedBroker;
 use crate::db::models::Symbol;
+use crate::pkg::stock_scan::{self, screener_contracts, ScanRequest};
 use crate::schema::symbols::dsl::symbols;
 use crate::schema::symbols::{screener, symbol};
 use actix_web_actors::ws;
@@ -39,216 +48,171 @@ use diesel_tracing::sqlite::InstrumentedSqliteConnection;
 use serde_json::{json, Value};
 use tokio_util::bytes::Buf;
 
+pub(crate) mod dashboard;
+pub(crate) mod health;
 mod jsonrpc;
 mod params;
+pub(crate) mod storage;
+mod subscribe;
+pub(crate) mod watchlist;
 
 /// Define HTTP actor
 struct APIEndpointWs {
-    broker: Arc<MixedBroker>,
+    state: WsState,
+    router: Rc<Router<WsState>>,
+}
+
+// 一个 websocket 连接的状态，交给每个 JSON-RPC 方法
+#[derive(Clone)]
+struct WsState {
+    broker: SharedBroker,
+    // 按 symbol:resolution 索引的订阅，在 broker 线程上与 Zen 比较
+    subscriptions: Arc<Mutex<HashMap<String, Subscription>>>,
 }
 
 impl Actor for APIEndpointWs {
     type Context = ws::WebsocketContext<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        ctx.run_interval(PUSH_INTERVAL, |act, ctx| act.push(ctx));
+    }
+
+    // 连接断开时释放该连接的全部订阅
+    fn stopped(&mut self, _: &mut Self::Context) {
+        let subscriptions: Vec<_> = self
+            .state
+            .subscriptions
+            .lock()
+            .unwrap()
+            .drain()
+            .map(|(_, sub)| sub)
+            .collect();
+        for sub in &subscriptions {
+            drop(subscribe::release(&self.state.broker, sub));
+        }
+    }
+}
+
+// websocket 上的 JSON-RPC 方法，新方法在这里注册
+fn ws_router() -> Router<WsState> {
+    Router::new()
+        .method("say_hello", |_, _| async { Ok(json!("Hello World")) })
+        .method("history", |state: WsState, params| async move {
+            let req = first_param::<HistoryRequest>(&params)?;
+            let use_local = req.use_local.unwrap_or(false);
+            history_bars(&state.broker, use_local, req)
+                .await
+                .map(|rsp| json!(rsp))
+        })
+        .method("elements", |state: WsState, params| async move {
+            let req = first_param::<ZenRequest>(&params)?;
+            let use_local = req.use_local.unwrap_or(false);
+            elements(&state.broker, use_local, req)
+                .await
+                .map(|rsp| json!(rsp))
+                .map_err(|e| ErrorObject::new(CALL_EXECUTION_FAILED_CODE, e))
+        })
+        .method("subscribe", subscribe::subscribe)
+        .method("unsubscribe", subscribe::unsubscribe)
+        .method("resync", subscribe::resync)
 }
 
 impl APIEndpointWs {
-    async fn history_ws(broker: Arc<MixedBroker>, params: HistoryRequest) -> HistoryResponse {
-        let symbol_ = params.symbol;
-        let contract = Contract::auto_stock(symbol_.as_str());
-        let freq = IB::freq_map().get(&params.resolution).unwrap().clone();
-
-        let rs = broker
-            .borrow()
-            .try_subscribe(
-                params.use_local.unwrap_or(false),
-                &contract,
-                freq,
-                params.from,
-                params.to,
-                params.countback as isize,
-                false,
-            )
-            .await;
-        if rs.is_err() {
-            return HistoryResponse {
-                s: "error".to_string(),
-                errmsg: Some(format!("error in get_bars: {}", rs.unwrap_err())),
-                t: None,
-                c: None,
-                h: None,
-                l: None,
-                v: None,
-                o: None,
-            };
+    // 推送各订阅的增量，正在更新的 Zen 留到下一轮；没有订阅时不往 broker 线程投递任务
+    fn push(&mut self, ctx: &mut ws::WebsocketContext<Self>) {
+        if self.state.subscriptions.lock().unwrap().is_empty() {
+            return;
         }
-        let from = OffsetDateTime::from_unix_timestamp(params.from).unwrap();
-        let to = OffsetDateTime::from_unix_timestamp(params.to).unwrap();
-        let mut index: isize = -1;
-
-        let (mut o, mut c, mut h, mut l) = (vec![], vec![], vec![], vec![]);
-        let (mut t, mut v) = (vec![], vec![]);
-        let zen = broker
-            .borrow()
-            .get_czsc(params.use_local.unwrap_or(false), &contract, freq);
-        let zen = zen.read().await;
-        if params.countback > 0 {
-            for (idx, bar) in zen.czsc.bars_raw.iter().enumerate() {
-                if bar.borrow().dt <= to {
-                    index = idx as isize;
-                } else {
-                    break;
-                }
-            }
-            for idx in max(index + 1 - (params.countback as isize), 0)..(index + 1) {
-                let bar = &zen.czsc.bars_raw[idx as usize];
-                o.push(bar.borrow().open);
-                c.push(bar.borrow().close);
-                h.push(bar.borrow().high);
-                l.push(bar.borrow().low);
-                t.push(bar.borrow().dt.unix_timestamp());
-                v.push(bar.borrow().vol);
-            }
-        } else {
-            for bar in &zen.czsc.bars_raw {
-                if bar.borrow().dt < from {
-                    continue;
+        let subscriptions = self.state.subscriptions.clone();
+        self.state
+            .broker
+            .run(move |broker| async move {
+                let mut updates = vec![];
+                let mut subscriptions = subscriptions.lock().unwrap();
+                for (key, sub) in subscriptions.iter_mut() {
+                    let zen = broker.get_czsc(sub.use_local, &sub.contract, sub.freq);
+                    let Ok(zen) = zen.try_read() else {
+                        continue;
+                    };
+                    let events = sub.diff(&zen);
+                    if events.is_empty() {
+                        continue;
+                    }
+                    updates.push(
+                        RpcNotification::new(
+                            "zen_update",
+                            json!({"subscription": key, "seq": sub.seq, "events": events}),
+                        )
+                        .dump(),
+                    );
                 }
-                if bar.borrow().dt > to {
-                    break;
+                updates
+            })
+            .into_actor(self)
+            .map(|updates, _, ctx| {
+                for update in updates.unwrap_or_default() {
+                    ctx.text(update);
                 }
-                o.push(bar.borrow().open);
-                c.push(bar.borrow().close);
-                h.push(bar.borrow().high);
-                l.push(bar.borrow().low);
-                t.push(bar.borrow().dt.unix_timestamp());
-                v.push(bar.borrow().vol);
-            }
-        }
-        return HistoryResponse {
-            s: "ok".to_string(),
-            errmsg: None,
-            t: Some(t),
-            c: Some(c),
-            h: Some(h),
-            l: Some(l),
-            v: Some(v),
-            o: Some(o),
-        };
+            })
+            .spawn(ctx);
     }
 }
 
-impl Handler<RpcResponse> for APIEndpointWs {
-    type Result = ();
-
-    fn handle(&mut self, msg: RpcResponse, ctx: &mut Self::Context) {
-        ctx.text(msg.dump());
+fn history_error(errmsg: String) -> HistoryResponse {
+    HistoryResponse {
+        s: "error".to_string(),
+        errmsg: Some(errmsg),
+        t: None,
+        c: None,
+        h: None,
+        l: None,
+        v: None,
+        o: None,
     }
 }
 
-/// Handler for ws::Message message
-impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for APIEndpointWs {
-    fn handle(&mut self, msg: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
-        match msg {
-            Ok(ws::Message::Ping(msg)) => {
-                debug!("msg {:?}", msg);
-                ctx.pong(&*msg)
-            }
-            Ok(ws::Message::Text(text)) => {
-                let Ok(RpcRequest {
-                    jsonrpc,
-                    method,
-                    params,
-                    id,
-                }) = serde_json::from_slice(text.as_ref())
-                else {
-                    return ctx.text(
-                        RpcResponse {
-                            payload: ErrorCode::ParseError.into(),
-                            ..Default::default()
-                        }
-                        .dump(),
-                    );
-                };
-                let payload = match method.as_str() {
-                    "say_hello" => {
-                        let payload = RpcPayload::Result(json!("Hello World"));
-                        ctx.text(
-                            RpcResponse {
-                                jsonrpc,
-                                payload,
-                                id,
-                            }
-                            .dump(),
-                        );
-                    }
-                    "history" => {
-                        let recipient = ctx.address().recipient();
-                        let req = params.unwrap()[0].clone();
-                        let broker = self.broker.clone();
-                        let future = async move {
-                            let rsp = APIEndpointWs::history_ws(
-                                broker,
-                                serde_json::from_value(req).unwrap(),
-                            )
-                            .await;
-                            recipient
-                                .send(RpcResponse {
-                                    jsonrpc,
-                                    payload: RpcPayload::Result(json!(rsp)),
-                                    id,
-                                })
-                                .await
-                                .expect("TODO: panic message");
-                        };
-                        future.into_actor(self).spawn(ctx);
-                    }
-                    _ => {}
-                };
-            }
-            Ok(ws::Message::Binary(bin)) => {
-                debug!("bin {:?}", bin);
-                ctx.binary(bin)
-            }
-            _ => (),
-        }
-    }
+// 校验级别及时间范围，客户端传入的参数不合法时返回 InvalidParams
+fn history_range(
+    params: &HistoryRequest,
+) -> std::result::Result<(Freq, OffsetDateTime, OffsetDateTime), ErrorObject> {
+    let invalid = |msg: String| ErrorObject::new(ErrorCode::InvalidParams, msg);
+    let freq = IB::freq_map()
+        .get(&params.resolution)
+        .cloned()
+        .ok_or_else(|| invalid(format!("unknown resolution {}", params.resolution)))?;
+    let ts = |t: i64| {
+        OffsetDateTime::from_unix_timestamp(t)
+            .map_err(|e| invalid(format!("invalid timestamp {}: {}", t, e)))
+    };
+    Ok((freq, ts(params.from)?, ts(params.to)?))
 }
 
-#[get("/ws")]
-async fn websocket(
-    req: HttpRequest,
-    stream: web::Payload,
-    z: web::Data<MixedBroker>,
-) -> Result<HttpResponse, Error> {
-    let resp = ws::start(
-        APIEndpointWs {
-            broker: z.deref().clone(),
-        },
-        &req,
-        stream,
-    );
-    debug!("{:?}", resp);
-    resp
+// K线历史，HTTP 与 websocket 共用
+async fn history_bars(
+    broker: &SharedBroker,
+    use_local: bool,
+    params: HistoryRequest,
+) -> std::result::Result<HistoryResponse, ErrorObject> {
+    let (freq, from, to) = history_range(&params)?;
+    Ok(broker
+        .run(move |z| async move { load_history(&z, use_local, params, freq, from, to).await })
+        .await
+        .unwrap_or_else(|e| history_error(format!("error in get_bars: {}", e))))
 }
 
-#[get("/datafeed/udf/history")]
-pub(super) async fn history(
-    req: HttpRequest,
-    web::Query(params): web::Query<HistoryRequest>,
-    z: web::Data<MixedBroker>,
-) -> Result<impl Responder> {
+// 在 broker 线程上执行
+async fn load_history(
+    z: &Mixed,
+    use_local: bool,
+    params: HistoryRequest,
+    freq: Freq,
+    from: OffsetDateTime,
+    to: OffsetDateTime,
+) -> HistoryResponse {
     let symbol_ = params.symbol;
     let contract = Contract::auto_stock(symbol_.as_str());
-    let freq = IB::freq_map().get(&params.resolution).unwrap().clone();
 
-    let use_local = req
-        .headers()
-        .get("Realtime")
-        .map(|x| x.to_str().unwrap() == "false")
-        .unwrap_or(false);
     let rs = z
-        .get_ref()
-        .borrow()
         .try_subscribe(
             use_local,
             &contract,
@@ -259,25 +223,14 @@ pub(super) async fn history(
             false,
         )
         .await;
-    if rs.is_err() {
-        return Ok(Json(HistoryResponse {
-            s: "error".to_string(),
-            errmsg: Some(format!("error in get_bars: {}", rs.unwrap_err())),
-            t: None,
-            c: None,
-            h: None,
-            l: None,
-            v: None,
-            o: None,
-        }));
+    if let Err(e) = rs {
+        return history_error(format!("error in get_bars: {}", e));
     }
-    let from = OffsetDateTime::from_unix_timestamp(params.from).unwrap();
-    let to = OffsetDateTime::from_unix_timestamp(params.to).unwrap();
     let mut index: isize = -1;
 
     let (mut o, mut c, mut h, mut l) = (vec![], vec![], vec![], vec![]);
     let (mut t, mut v) = (vec![], vec![]);
-    let zen = z.borrow().get_czsc(use_local, &contract, freq);
+    let zen = z.get_czsc(use_local, &contract, freq);
     let zen = zen.read().await;
     if params.countback > 0 {
         for (idx, bar) in zen.czsc.bars_raw.iter().enumerate() {
@@ -312,7 +265,7 @@ pub(super) async fn history(
             v.push(bar.borrow().vol);
         }
     }
-    Ok(Json(HistoryResponse {
+    HistoryResponse {
         s: "ok".to_string(),
         errmsg: None,
         t: Some(t),
@@ -321,14 +274,88 @@ pub(super) async fn history(
         l: Some(l),
         v: Some(v),
         o: Some(o),
-    }))
+    }
+}
+
+impl Handler<RpcResponse> for APIEndpointWs {
+    type Result = ();
+
+    fn handle(&mut self, msg: RpcResponse, ctx: &mut Self::Context) {
+        ctx.text(msg.dump());
+    }
+}
+
+/// Handler for ws::Message message
+impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for APIEndpointWs {
+    fn handle(&mut self, msg: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
+        match msg {
+            Ok(ws::Message::Ping(msg)) => {
+                debug!("msg {:?}", msg);
+                ctx.pong(&*msg)
+            }
+            Ok(ws::Message::Text(text)) => {
+                self.router
+                    .handle(self.state.clone(), text.as_ref())
+                    .into_actor(self)
+                    .map(|reply, _, ctx| {
+                        if let Some(reply) = reply {
+                            ctx.text(reply);
+                        }
+                    })
+                    .spawn(ctx);
+            }
+            Ok(ws::Message::Binary(bin)) => {
+                debug!("bin {:?}", bin);
+                ctx.binary(bin)
+            }
+            _ => (),
+        }
+    }
+}
+
+#[get("/ws")]
+async fn websocket(
+    req: HttpRequest,
+    stream: web::Payload,
+    broker: web::Data<SharedBroker>,
+) -> Result<HttpResponse, Error> {
+    let resp = ws::start(
+        APIEndpointWs {
+            state: WsState {
+                broker: broker.get_ref().clone(),
+                subscriptions: Default::default(),
+            },
+            router: Rc::new(ws_router()),
+        },
+        &req,
+        stream,
+    );
+    debug!("{:?}", resp);
+    resp
+}
+
+#[get("/datafeed/udf/history")]
+pub(super) async fn history(
+    req: HttpRequest,
+    web::Query(params): web::Query<HistoryRequest>,
+    broker: web::Data<SharedBroker>,
+) -> Result<impl Responder> {
+    let use_local = req
+        .headers()
+        .get("Realtime")
+        .map(|x| x.to_str().unwrap() == "false")
+        .unwrap_or(false);
+    let rsp = history_bars(&broker, use_local, params)
+        .await
+        .map_err(|e| error::ErrorBadRequest(e.message().to_string()))?;
+    Ok(Json(rsp))
 }
 
 #[get("/datafeed/udf/search")]
 async fn search_symbol(
     req: HttpRequest,
     web::Query(params): web::Query<SearchRequest>,
-    z: web::Data<MixedBroker>,
+    broker: web::Data<SharedBroker>,
     conn: web::Data<RefCell<InstrumentedSqliteConnection>>,
 ) -> Result<impl Responder> {
     use crate::schema::symbols::dsl::*;
@@ -367,108 +394,117 @@ async fn search_symbol(
         .unwrap_or(false);
 
     if (params.query.contains("TSLA ") || params.query.contains("SPY ")) && !use_local {
-        let contract = Contract::stock(params.query.split(" ").next().unwrap());
-        let rs = z
-            .get_ref()
-            .borrow()
-            .try_subscribe(
-                false,
-                &contract,
-                Freq::D,
-                OffsetDateTime::now_utc()
-                    .sub(Duration::from_secs(60 * 60))
-                    .unix_timestamp(),
-                OffsetDateTime::now_utc().unix_timestamp(),
-                100,
-                false,
-            )
-            .await;
-        if rs.is_err() {
-            return Err(error::ErrorInternalServerError(rs.unwrap_err()));
-        }
+        let query = params.query.clone();
+        let options = broker
+            .run(move |z| async move { option_symbols(&z, &query).await })
+            .await
+            .and_then(|rs| rs)
+            .map_err(error::ErrorInternalServerError)?;
+        obj.extend(options);
+    }
+    Ok(Json(obj))
+}
 
-        let last_price = {
-            let zen = z.borrow().get_czsc(false, &contract, Freq::F60);
-            let zen = zen.read().await;
-            zen.czsc
-                .bars_raw
-                .last()
-                .map(|x| x.borrow().close)
-                .unwrap_or(0.0)
-        };
-        info!("last_price {}", last_price);
-
-        let client = &z.borrow().ib.borrow().client.read().await.clone();
-        let client = client.borrow();
-        let client_ref = client.as_ref().unwrap();
-        let contracts = contract_details(client_ref, &contract).await.unwrap();
-
-        let params = timeout(
-            Duration::from_secs(4),
-            sec_def_opt(
-                client_ref,
-                &ReqSecDefOptParams {
-                    underlying_symbol: contracts[0].contract.symbol.clone(),
-                    fut_fop_exchange: "".to_string(),
-                    underlying_sec_type: contracts[0].contract.security_type.to_string(),
-                    underlying_con_id: contracts[0].contract.contract_id,
-                },
-            ),
-        )
-        .await
-        .unwrap_or(Ok(vec![]))
-        .unwrap_or(vec![]);
-        let params = params
-            .iter()
-            .filter(|x| x.exchange == "SMART")
-            .collect::<Vec<_>>();
+// 搜索 TSLA、SPY 时列出最近到期、现价附近的期权，在 broker 线程上执行
+async fn option_symbols(
+    z: &Mixed,
+    query: &str,
+) -> std::result::Result<Vec<SearchSymbolResultItem>, tws_rs::Error> {
+    let mut obj = vec![];
+    let contract = Contract::stock(query.split(" ").next().unwrap());
+    z.try_subscribe(
+        false,
+        &contract,
+        Freq::D,
+        OffsetDateTime::now_utc()
+            .sub(Duration::from_secs(60 * 60))
+            .unix_timestamp(),
+        OffsetDateTime::now_utc().unix_timestamp(),
+        100,
+        false,
+    )
+    .await?;
 
-        let formatter = format_description::parse("[year][month][day]").unwrap();
+    let last_price = {
+        let zen = z.get_czsc(false, &contract, Freq::F60);
+        let zen = zen.read().await;
+        zen.czsc
+            .bars_raw
+            .last()
+            .map(|x| x.borrow().close)
+            .unwrap_or(0.0)
+    };
+    info!("last_price {}", last_price);
+
+    let client = z.ib.client().await?;
+    let client_ref = client.as_ref();
+    let contracts = contract_details(client_ref, &contract).await.unwrap();
+
+    let params = timeout(
+        Duration::from_secs(4),
+        sec_def_opt(
+            client_ref,
+            &ReqSecDefOptParams {
+                underlying_symbol: contracts[0].contract.symbol.clone(),
+                fut_fop_exchange: "".to_string(),
+                underlying_sec_type: contracts[0].contract.security_type.to_string(),
+                underlying_con_id: contracts[0].contract.contract_id,
+            },
+        ),
+    )
+    .await
+    .unwrap_or(Ok(vec![]))
+    .unwrap_or(vec![]);
+    let params = params
+        .iter()
+        .filter(|x| x.exchange == "SMART")
+        .collect::<Vec<_>>();
 
-        let expirations = params[0].expirations.clone();
-        let mut expirations = expirations
-            .iter()
-            .filter(|x| (**x) >= OffsetDateTime::now_utc().format(&formatter).unwrap())
-            .collect::<Vec<_>>();
-        expirations.sort();
-
-        for expiration in expirations.iter().take(1) {
-            for strike in &params[0].strikes {
-                for right in ["P", "C"] {
-                    let gap = if params[0].trading_class == "TSLA" {
-                        250
-                    } else {
-                        100
-                    };
-                    if *strike > last_price as f64 - 10.0
-                        && *strike < last_price as f64 + 10.0
-                        && ((*strike * 100.0) as i64 % gap == 0)
-                    {
-                        let option = Contract::option(
-                            params[0].trading_class.as_str(),
-                            expiration.as_str(),
-                            *strike,
-                            right,
-                            params[0].multiplier.as_str(),
-                        );
-                        let option = contract_details(client_ref, &option).await;
-                        if option.is_ok() {
-                            let option = &option.unwrap()[0];
-                            obj.push(SearchSymbolResultItem {
-                                symbol: option.contract.local_symbol.clone(),
-                                full_name: option.contract.local_symbol.clone(),
-                                description: option.contract.local_symbol.clone(),
-                                exchange: option.contract.exchange.clone(),
-                                ticker: format!("option:{}", option.contract.local_symbol),
-                                r#type: "option".to_string(),
-                            });
-                        }
+    let formatter = format_description::parse("[year][month][day]").unwrap();
+
+    let expirations = params[0].expirations.clone();
+    let mut expirations = expirations
+        .iter()
+        .filter(|x| (**x) >= OffsetDateTime::now_utc().format(&formatter).unwrap())
+        .collect::<Vec<_>>();
+    expirations.sort();
+
+    for expiration in expirations.iter().take(1) {
+        for strike in &params[0].strikes {
+            for right in ["P", "C"] {
+                let gap = if params[0].trading_class == "TSLA" {
+                    250
+                } else {
+                    100
+                };
+                if *strike > last_price as f64 - 10.0
+                    && *strike < last_price as f64 + 10.0
+                    && ((*strike * 100.0) as i64 % gap == 0)
+                {
+                    let option = Contract::option(
+                        params[0].trading_class.as_str(),
+                        expiration.as_str(),
+                        *strike,
+                        right,
+                        params[0].multiplier.as_str(),
+                    );
+                    let option = contract_details(client_ref, &option).await;
+                    if option.is_ok() {
+                        let option = &option.unwrap()[0];
+                        obj.push(SearchSymbolResultItem {
+                            symbol: option.contract.local_symbol.clone(),
+                            full_name: option.contract.local_symbol.clone(),
+                            description: option.contract.local_symbol.clone(),
+                            exchange: option.contract.exchange.clone(),
+                            ticker: format!("option:{}", option.contract.local_symbol),
+                            r#type: "option".to_string(),
+                        });
                     }
                 }
             }
         }
     }
-    Ok(Json(obj))
+    Ok(obj)
 }
 
 #[get("/datafeed/udf/symbols")]
@@ -575,9 +611,9 @@ pub(crate) async fn config() -> Result<impl Responder> {
         ),
         units: None,
         currency_codes: None,
-        supports_marks: false,
+        supports_marks: true,
         supports_time: false,
-        supports_timescale_marks: false,
+        supports_timescale_marks: true,
         exchanges: Some(vec![
             Exchange {
                 name: "US".to_string(),
@@ -601,31 +637,158 @@ pub(crate) async fn config() -> Result<impl Responder> {
     }))
 }
 
-#[post("/zen/elements")]
-pub(crate) async fn zen_element(
+// 买卖点标在K线上：买点绿色、卖点红色，推笔产生的未确认买卖点为空心
+#[get("/datafeed/udf/marks")]
+pub(crate) async fn marks(
     req: HttpRequest,
-    Json(params): Json<ZenRequest>,
-    z: web::Data<MixedBroker>,
+    web::Query(params): web::Query<MarksRequest>,
+    broker: web::Data<SharedBroker>,
 ) -> Result<impl Responder> {
-    //debug!("zen_element {:?}", params);
-    let symbol_ = params.symbol;
-    let contract = Contract::auto_stock(symbol_.as_str());
-    let freq = IB::freq_map().get(&params.resolution).unwrap().clone();
+    let contract = Contract::auto_stock(params.symbol.as_str());
+    let Some(freq) = IB::freq_map().get(&params.resolution).cloned() else {
+        return Err(error::ErrorBadRequest(format!(
+            "unknown resolution {}",
+            params.resolution
+        )));
+    };
     let use_local = req
         .headers()
         .get("Realtime")
         .map(|x| x.to_str().unwrap() == "false")
         .unwrap_or(false);
-    let rs = z
-        .get_ref()
-        .borrow()
+    let result = broker
+        .run(move |z| async move {
+            let zen = z.get_czsc(use_local, &contract, freq);
+            let zen = zen.read().await;
+
+            let mut result = vec![];
+            for bc in &zen.beichi_processor.beichi_tracker {
+                if bc.dt < params.from || bc.dt > params.to {
+                    continue;
+                }
+                let color = if bc.direction == Direction::Up {
+                    "#f23645"
+                } else {
+                    "#089981"
+                };
+                let bc_type = bc
+                    .bc_type
+                    .iter()
+                    .map(|t| t.label())
+                    .collect::<Vec<_>>()
+                    .join(" / ");
+                result.push(Mark {
+                    id: format!("{}-{}", bc.zs2.left, bc.zs2.right),
+                    time: bc.dt,
+                    color: MarkColor {
+                        border: color.to_string(),
+                        background: if bc.fake_bi { "#ffffff" } else { color }.to_string(),
+                    },
+                    text: format!(
+                        "{} {}{}<br>{:.3}",
+                        bc.r#type.label(),
+                        bc_type,
+                        if bc.fake_bi { " (推笔)" } else { "" },
+                        bc.price
+                    ),
+                    // 用完整简写，只取首字符时 1B 与 1S 无法区分
+                    label: bc.r#type.label().to_string(),
+                    label_font_color: if bc.fake_bi { color } else { "#ffffff" }.to_string(),
+                    min_size: 14,
+                });
+            }
+            result
+        })
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+    Ok(Json(result))
+}
+
+// 信号历史标在时间轴上，同一时间的信号合并为一个标记
+#[get("/datafeed/udf/timescale_marks")]
+pub(crate) async fn timescale_marks(
+    req: HttpRequest,
+    web::Query(params): web::Query<MarksRequest>,
+    broker: web::Data<SharedBroker>,
+) -> Result<impl Responder> {
+    let contract = Contract::auto_stock(params.symbol.as_str());
+    let Some(freq) = IB::freq_map().get(&params.resolution).cloned() else {
+        return Err(error::ErrorBadRequest(format!(
+            "unknown resolution {}",
+            params.resolution
+        )));
+    };
+    let use_local = req
+        .headers()
+        .get("Realtime")
+        .map(|x| x.to_str().unwrap() == "false")
+        .unwrap_or(false);
+    let result = broker
+        .run(move |z| async move {
+            let zen = z.get_czsc(use_local, &contract, freq);
+            let zen = zen.read().await;
+
+            let mut result: Vec<TimescaleMark> = vec![];
+            for signal in &zen.signal_history {
+                let Some(time) = signal.dt.map(|dt| dt.unix_timestamp()) else {
+                    continue;
+                };
+                if time < params.from || time > params.to {
+                    continue;
+                }
+                let tooltip = format!("{} {}", signal.key(), signal.value());
+                if let Some(mark) = result.last_mut().filter(|m| m.time == time) {
+                    if !mark.tooltip.contains(&tooltip) {
+                        mark.tooltip.push(tooltip);
+                    }
+                    continue;
+                }
+                let bottom = signal.value.0 == "底";
+                result.push(TimescaleMark {
+                    id: format!("{}-{}", time, result.len()),
+                    time,
+                    color: if bottom { "#089981" } else { "#f23645" }.to_string(),
+                    label: if bottom { "B" } else { "S" }.to_string(),
+                    tooltip: vec![tooltip],
+                });
+            }
+            result
+        })
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+    Ok(Json(result))
+}
+
+// 笔及买卖点，HTTP 与 websocket 共用
+async fn elements(
+    broker: &SharedBroker,
+    use_local: bool,
+    params: ZenRequest,
+) -> std::result::Result<ZenResponse, String> {
+    broker
+        .run(move |z| async move { load_elements(&z, use_local, params).await })
+        .await
+        .map_err(|e| e.to_string())
+        .and_then(|rs| rs)
+}
+
+// 在 broker 线程上执行
+async fn load_elements(
+    broker: &Mixed,
+    use_local: bool,
+    params: ZenRequest,
+) -> std::result::Result<ZenResponse, String> {
+    let contract = Contract::auto_stock(params.symbol.as_str());
+    let freq = IB::freq_map()
+        .get(&params.resolution)
+        .cloned()
+        .ok_or_else(|| format!("unknown resolution {}", params.resolution))?;
+    broker
         .try_subscribe(use_local, &contract, freq, params.from, params.to, 0, false)
-        .await;
-    let zen = z.borrow().get_czsc(use_local, &contract, freq);
+        .await
+        .map_err(|e| e.to_string())?;
+    let zen = broker.get_czsc(use_local, &contract, freq);
     let zen = zen.read().await;
-    if rs.is_err() {
-        return Err(error::ErrorInternalServerError(rs.unwrap_err()));
-    }
 
     let mut resp = ZenResponse {
         bi: BiInfo {
@@ -635,64 +798,9 @@ pub(crate) async fn zen_element(
         beichi: vec![],
         bar_beichi: vec![],
     };
-    let mut last_dir = None;
-    for bi in &zen.czsc.bi_list {
-        if bi.fx_b.dt.unix_timestamp() < params.from {
-            continue;
-        }
-        last_dir = Some(bi.direction.clone());
-        resp.bi.finished.push(ZenBiDetail {
-            direction: String::from(bi.direction.as_str()),
-            end: if bi.direction == Direction::Down {
-                bi.low()
-            } else {
-                bi.high()
-            },
-            end_ts: bi.fx_b.dt.unix_timestamp(),
-            start: if bi.direction == Direction::Down {
-                bi.high()
-            } else {
-                bi.low()
-            },
-            start_ts: bi.fx_a.dt.unix_timestamp(),
-        })
-    }
-    match last_dir {
-        Some(Direction::Up) => {
-            let bar = zen
-                .czsc
-                .bars_ubi
-                .iter()
-                .skip(1)
-                .min_by(|a, b| a.low.partial_cmp(&b.low).unwrap())
-                .map(|a| a.clone())
-                .unwrap();
-            resp.bi.unfinished.push(ZenBiDetail {
-                direction: String::from(Direction::Down.as_str()),
-                end: bar.low,
-                end_ts: bar.dt.unix_timestamp(),
-                start: zen.czsc.bars_ubi[1].high,
-                start_ts: zen.czsc.bars_ubi[1].dt.unix_timestamp(),
-            });
-        }
-        Some(Direction::Down) => {
-            let bar = zen
-                .czsc
-                .bars_ubi
-                .iter()
-                .skip(1)
-                .max_by(|a, b| a.high.partial_cmp(&b.high).unwrap())
-                .map(|a| a.clone())
-                .unwrap();
-            resp.bi.unfinished.push(ZenBiDetail {
-                direction: String::from(Direction::Up.as_str()),
-                end: bar.high,
-                end_ts: bar.dt.unix_timestamp(),
-                start: zen.czsc.bars_ubi[1].low,
-                start_ts: zen.czsc.bars_ubi[1].dt.unix_timestamp(),
-            });
-        }
-        _ => {}
+    resp.bi.finished = bi_details(&zen, params.from);
+    if !resp.bi.finished.is_empty() {
+        resp.bi.unfinished.extend(unfinished_bi(&zen));
     }
 
     resp.beichi.push(vec![]);
@@ -702,24 +810,79 @@ pub(crate) async fn zen_element(
         }
         resp.beichi[0].push(bc.clone());
     }
-    Ok(Json(resp))
+    Ok(resp)
+}
+
+#[post("/zen/elements")]
+pub(crate) async fn zen_element(
+    req: HttpRequest,
+    Json(params): Json<ZenRequest>,
+    broker: web::Data<SharedBroker>,
+) -> Result<impl Responder> {
+    //debug!("zen_element {:?}", params);
+    let use_local = req
+        .headers()
+        .get("Realtime")
+        .map(|x| x.to_str().unwrap() == "false")
+        .unwrap_or(false);
+    elements(&broker, use_local, params)
+        .await
+        .map(Json)
+        .map_err(error::ErrorInternalServerError)
+}
+
+// 选股：按 watchlist 或 symbols 表过滤出品种，用本地K线打分排序
+#[get("/scan")]
+pub(crate) async fn scan(
+    web::Query(params): web::Query<ScanRequest>,
+    auth: web::ReqData<AuthUser>,
+    broker: web::Data<SharedBroker>,
+    conn: web::Data<RefCell<InstrumentedSqliteConnection>>,
+) -> Result<impl Responder> {
+    let contracts = match &params.watchlist {
+        // 只能扫描当前用户自己的 watchlist
+        Some(name) => watchlist::watchlist_contracts(conn.borrow_mut().deref_mut(), &auth.0, name)?,
+        None => screener_contracts(
+            conn.borrow_mut().deref_mut(),
+            params.screener.as_deref(),
+            params.exchange.as_deref(),
+        )
+        .map_err(error::ErrorBadRequest)?,
+    };
+    let rsp = broker
+        .run(move |z| async move {
+            stock_scan::scan(&z, &contracts, &params, stock_scan::MAX_REQUEST_SYMBOLS).await
+        })
+        .await
+        .map_err(error::ErrorInternalServerError)?
+        .map_err(error::ErrorBadRequest)?;
+    Ok(Json(rsp))
 }
 
 #[post("/ma/option_price")]
 async fn option_price(
     web::Json(params): web::Json<OptionPriceRequest>,
-    z: web::Data<MixedBroker>,
+    broker: web::Data<SharedBroker>,
 ) -> Result<impl Responder> {
     if params.option.is_empty() {
         return Err(error::ErrorInternalServerError("option empty"));
     }
+    let result = broker
+        .run(move |z| async move { option_prices(&z, params).await })
+        .await
+        .and_then(|rs| rs)
+        .map_err(error::ErrorInternalServerError)?;
+    Ok(Json(result))
+}
+
+// 在 broker 线程上执行
+async fn option_prices(
+    z: &Mixed,
+    params: OptionPriceRequest,
+) -> std::result::Result<Vec<OptionPriceItem>, tws_rs::Error> {
     let mut result = vec![];
-    {
-        z.get_ref().borrow().ib.borrow().connect().await;
-    }
-    let client = &z.borrow().ib.borrow().client.read().await.clone();
-    let client = client.borrow();
-    let client_ref = client.as_ref().unwrap();
+    let client = z.ib.client().await?;
+    let client_ref = client.as_ref();
 
     let option = Contract {
         local_symbol: params.option.clone(),
@@ -762,7 +925,7 @@ async fn option_price(
         for ma in &params.ma {
             let freq = IB::freq_map().get(&interval.to_string()).unwrap().clone();
             let contract = Contract::auto_stock(params.symbol.as_str());
-            let zen = z.borrow().get_czsc(false, &contract, freq);
+            let zen = z.get_czsc(false, &contract, freq);
             let zen = zen.read().await;
             let smas = zen
                 .czsc
@@ -784,5 +947,33 @@ async fn option_price(
         }
     }
 
-    Ok(Json(result))
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(resolution: &str, from: i64, to: i64) -> HistoryRequest {
+        HistoryRequest {
+            symbol: "AAPL".to_string(),
+            resolution: resolution.to_string(),
+            from,
+            to,
+            countback: 0,
+            use_local: None,
+        }
+    }
+
+    #[test]
+    fn history_range_rejects_invalid_params() {
+        let (freq, from, to) = history_range(&request("1D", 0, 86400)).unwrap();
+        assert_eq!(freq, Freq::D);
+        assert_eq!((from.unix_timestamp(), to.unix_timestamp()), (0, 86400));
+
+        for req in [request("7", 0, 86400), request("1D", 0, i64::MAX)] {
+            let e = history_range(&req).unwrap_err();
+            assert_eq!(e.code(), ErrorCode::InvalidParams.code());
+        }
+    }
 }
@@ -0,0 +1,625 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::ops::DerefMut;
+use std::time::Duration;
+
+use actix_web::web::Json;
+use actix_web::{delete, error, get, post, put, web, Responder, Result};
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+use diesel_tracing::sqlite::InstrumentedSqliteConnection;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use time::OffsetDateTime;
+use tws_rs::contracts::Contract;
+use zen_core::objects::trade::Signal;
+
+use crate::broker::ib::IB;
+use crate::broker::mixed::Mixed;
+use crate::broker::shared::SharedBroker;
+use crate::broker::zen::{Zen, SMA_PERIODS};
+use crate::calculate::beichi::buy_sell_point::BSPoint;
+use crate::calculate::zen_cache::SMATrackerCache;
+use crate::db::last_insert_rowid;
+use crate::db::models::{Dashboard, DashboardVersion, NewDashboard};
+
+// 每个 dashboard 最多的面板数
+const MAX_PANELS: usize = 64;
+// evaluate 未指定 from 时取最近 90 天的K线
+const DEFAULT_LOOKBACK: Duration = Duration::from_secs(90 * 24 * 60 * 60);
+
+type Conn = web::Data<RefCell<InstrumentedSqliteConnection>>;
+
+// 一个面板：品种、周期、图表上的指标及关注的信号
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(super) struct Panel {
+    pub symbol: String,
+    pub resolution: String,
+    #[serde(default)]
+    pub studies: Vec<String>,
+    // 信号格式与 matcher 配置相同，全部满足时面板为 matched
+    #[serde(default)]
+    pub signal_filters: Vec<Signal>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub(super) struct DashboardDoc {
+    pub name: String,
+    pub panels: Vec<Panel>,
+}
+
+impl DashboardDoc {
+    fn validate(&self) -> Result<()> {
+        if self.name.trim().is_empty() {
+            return Err(error::ErrorBadRequest("dashboard name is empty"));
+        }
+        if self.panels.len() > MAX_PANELS {
+            return Err(error::ErrorBadRequest(format!(
+                "too many panels, at most {}",
+                MAX_PANELS
+            )));
+        }
+        for panel in &self.panels {
+            if !IB::freq_map().contains_key(&panel.resolution) {
+                return Err(error::ErrorBadRequest(format!(
+                    "unknown resolution {}",
+                    panel.resolution
+                )));
+            }
+            if let Some(study) = panel.studies.iter().find(|s| Study::parse(s).is_none()) {
+                return Err(error::ErrorBadRequest(format!("unknown study {}", study)));
+            }
+        }
+        Ok(())
+    }
+}
+
+// 面板上可计算的指标：MA{n} 为 Zen 跟踪的均线，MACD 为最后一根K线的 MACD 柱
+#[derive(Debug, PartialEq)]
+enum Study {
+    Ma(isize),
+    Macd,
+}
+
+impl Study {
+    fn parse(name: &str) -> Option<Self> {
+        if name == "MACD" {
+            return Some(Study::Macd);
+        }
+        name.strip_prefix("MA")
+            .and_then(|p| p.parse().ok())
+            .filter(|p| SMA_PERIODS.contains(p))
+            .map(Study::Ma)
+    }
+
+    // K线不足一个周期时均线没有值
+    fn value(&self, zen: &Zen) -> Option<f32> {
+        match self {
+            Study::Ma(period) => zen
+                .czsc
+                .cache
+                .get::<SMATrackerCache>()?
+                .store
+                .get(period)
+                .filter(|sma| sma.is_full())
+                .map(|sma| sma.ma()),
+            Study::Macd => zen
+                .czsc
+                .bars_raw
+                .last()
+                .map(|bar| bar.borrow().macd_4_9_9.2),
+        }
+    }
+}
+
+// 更新时带上所基于的版本，已被他人修改则返回 409
+#[derive(Deserialize, Debug)]
+pub(super) struct UpdateDashboard {
+    pub version: i32,
+    #[serde(flatten)]
+    pub doc: DashboardDoc,
+}
+
+#[derive(Deserialize, Debug)]
+pub(super) struct VersionQuery {
+    pub version: Option<i32>,
+}
+
+#[derive(Deserialize, Debug)]
+pub(super) struct EvaluateQuery {
+    pub version: Option<i32>,
+    pub from: Option<i64>,
+    pub use_local: Option<bool>,
+}
+
+#[derive(Serialize, Debug)]
+pub(super) struct SignalState {
+    #[serde(flatten)]
+    pub signal: Signal,
+    pub dt: Option<i64>,
+}
+
+#[derive(Serialize, Debug)]
+pub(super) struct StudyState {
+    pub name: String,
+    pub value: Option<f32>,
+}
+
+#[derive(Serialize, Debug)]
+pub(super) struct PanelState {
+    pub symbol: String,
+    pub resolution: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub last_time: Option<i64>,
+    pub last_price: Option<f32>,
+    pub studies: Vec<StudyState>,
+    // 每个 key 最近一次的信号
+    pub signals: Vec<SignalState>,
+    pub matched: bool,
+    pub last_point: Option<BSPoint>,
+}
+
+impl PanelState {
+    fn new(panel: &Panel) -> Self {
+        Self {
+            symbol: panel.symbol.clone(),
+            resolution: panel.resolution.clone(),
+            error: None,
+            last_time: None,
+            last_price: None,
+            studies: vec![],
+            signals: vec![],
+            matched: false,
+            last_point: None,
+        }
+    }
+
+    fn evaluate(&mut self, panel: &Panel, zen: &Zen) {
+        if let Some(bar) = zen.czsc.bars_raw.last() {
+            let bar = bar.borrow();
+            self.last_time = Some(bar.dt.unix_timestamp());
+            self.last_price = Some(bar.close);
+        }
+        self.studies = panel
+            .studies
+            .iter()
+            .map(|name| StudyState {
+                name: name.clone(),
+                value: Study::parse(name).and_then(|s| s.value(zen)),
+            })
+            .collect();
+
+        let mut latest: HashMap<String, &Signal> = HashMap::new();
+        for signal in &zen.signal_history {
+            latest.insert(signal.key(), signal);
+        }
+        self.matched = panel
+            .signal_filters
+            .iter()
+            .all(|f| latest.get(&f.key()).map(|s| f.is_match(s)).unwrap_or(false));
+        let mut signals: Vec<_> = latest.into_values().collect();
+        signals.sort_by_key(|s| s.dt);
+        self.signals = signals
+            .into_iter()
+            .map(|s| SignalState {
+                signal: s.clone(),
+                dt: s.dt.map(|dt| dt.unix_timestamp()),
+            })
+            .collect();
+        self.last_point = zen.beichi_processor.beichi_tracker.last().cloned();
+    }
+}
+
+fn db_error(e: DieselError) -> error::Error {
+    match e {
+        DieselError::NotFound => error::ErrorNotFound("dashboard not found"),
+        e => error::ErrorInternalServerError(e),
+    }
+}
+
+fn now() -> i64 {
+    OffsetDateTime::now_utc().unix_timestamp()
+}
+
+fn load_version(
+    conn: &mut InstrumentedSqliteConnection,
+    id: i32,
+    version: Option<i32>,
+) -> QueryResult<(Dashboard, DashboardDoc, DashboardVersion)> {
+    use crate::schema::dashboard_versions::dsl as v;
+    use crate::schema::dashboards::dsl as d;
+
+    let dashboard = d::dashboards
+        .filter(d::id.eq(id))
+        .select(Dashboard::as_select())
+        .first(conn)?;
+    let saved = v::dashboard_versions
+        .filter(v::dashboard_id.eq(id))
+        .filter(v::version.eq(version.unwrap_or(dashboard.version)))
+        .select(DashboardVersion::as_select())
+        .first(conn)?;
+    let doc = serde_json::from_str(&saved.content)
+        .map_err(|e| DieselError::DeserializationError(Box::new(e)))?;
+    Ok((dashboard, doc, saved))
+}
+
+#[get("/dashboards")]
+pub(crate) async fn list_dashboards(conn: Conn) -> Result<impl Responder> {
+    use crate::schema::dashboards::dsl::*;
+
+    let rs = dashboards
+        .order(updated.desc())
+        .select(Dashboard::as_select())
+        .load(conn.borrow_mut().deref_mut())
+        .map_err(db_error)?;
+    Ok(Json(
+        rs.into_iter()
+            .map(
+                |d| json!({"id": d.id, "name": d.name, "version": d.version, "updated": d.updated}),
+            )
+            .collect::<Vec<_>>(),
+    ))
+}
+
+#[post("/dashboards")]
+pub(crate) async fn create_dashboard(
+    Json(doc): Json<DashboardDoc>,
+    conn: Conn,
+) -> Result<impl Responder> {
+    use crate::schema::dashboard_versions::dsl as v;
+    use crate::schema::dashboards::dsl as d;
+
+    doc.validate()?;
+    let content = serde_json::to_string(&doc)?;
+    let ts = now();
+    let new_id = conn
+        .borrow_mut()
+        .deref_mut()
+        .transaction(|conn| {
+            diesel::insert_into(d::dashboards)
+                .values(NewDashboard {
+                    name: &doc.name,
+                    version: 1,
+                    updated: ts,
+                })
+                .execute(conn)?;
+            let new_id = diesel::select(last_insert_rowid()).get_result::<i32>(conn)?;
+            diesel::insert_into(v::dashboard_versions)
+                .values(DashboardVersion {
+                    dashboard_id: new_id,
+                    version: 1,
+                    content,
+                    created: ts,
+                })
+                .execute(conn)?;
+            Ok::<_, DieselError>(new_id)
+        })
+        .map_err(db_error)?;
+    Ok(Json(json!({"id": new_id, "version": 1})))
+}
+
+// 默认取最新版本，?version= 取历史版本
+#[get("/dashboards/{id}")]
+pub(crate) async fn get_dashboard(
+    path: web::Path<i32>,
+    web::Query(params): web::Query<VersionQuery>,
+    conn: Conn,
+) -> Result<impl Responder> {
+    let (dashboard, doc, saved) = load_version(
+        conn.borrow_mut().deref_mut(),
+        path.into_inner(),
+        params.version,
+    )
+    .map_err(db_error)?;
+    Ok(Json(json!({
+        "id": dashboard.id,
+        "name": doc.name,
+        "version": saved.version,
+        "latest_version": dashboard.version,
+        "updated": saved.created,
+        "panels": doc.panels,
+    })))
+}
+
+#[get("/dashboards/{id}/versions")]
+pub(crate) async fn dashboard_versions(path: web::Path<i32>, conn: Conn) -> Result<impl Responder> {
+    use crate::schema::dashboard_versions::dsl::*;
+
+    let rs = dashboard_versions
+        .filter(dashboard_id.eq(path.into_inner()))
+        .order(version.desc())
+        .select((version, created))
+        .load::<(i32, i64)>(conn.borrow_mut().deref_mut())
+        .map_err(db_error)?;
+    if rs.is_empty() {
+        return Err(db_error(DieselError::NotFound));
+    }
+    Ok(Json(
+        rs.into_iter()
+            .map(|(v, c)| json!({"version": v, "created": c}))
+            .collect::<Vec<_>>(),
+    ))
+}
+
+#[put("/dashboards/{id}")]
+pub(crate) async fn update_dashboard(
+    path: web::Path<i32>,
+    Json(req): Json<UpdateDashboard>,
+    conn: Conn,
+) -> Result<impl Responder> {
+    use crate::schema::dashboard_versions::dsl as v;
+    use crate::schema::dashboards::dsl as d;
+
+    req.doc.validate()?;
+    let dashboard_id = path.into_inner();
+    let content = serde_json::to_string(&req.doc)?;
+    let ts = now();
+    let next = req.version + 1;
+    let rs = conn
+        .borrow_mut()
+        .deref_mut()
+        .transaction(|conn| {
+            let n = diesel::update(
+                d::dashboards
+                    .filter(d::id.eq(dashboard_id))
+                    .filter(d::version.eq(req.version)),
+            )
+            .set((
+                d::name.eq(&req.doc.name),
+                d::version.eq(next),
+                d::updated.eq(ts),
+            ))
+            .execute(conn)?;
+            if n == 0 {
+                // 区分不存在与版本冲突
+                let latest = d::dashboards
+                    .filter(d::id.eq(dashboard_id))
+                    .select(d::version)
+                    .first::<i32>(conn)?;
+                return Ok(Err(latest));
+            }
+            diesel::insert_into(v::dashboard_versions)
+                .values(DashboardVersion {
+                    dashboard_id,
+                    version: next,
+                    content,
+                    created: ts,
+                })
+                .execute(conn)?;
+            Ok::<_, DieselError>(Ok(next))
+        })
+        .map_err(db_error)?;
+    match rs {
+        Ok(version) => Ok(Json(json!({"id": dashboard_id, "version": version}))),
+        Err(latest) => Err(error::ErrorConflict(format!(
+            "dashboard was modified, latest version is {}",
+            latest
+        ))),
+    }
+}
+
+#[delete("/dashboards/{id}")]
+pub(crate) async fn delete_dashboard(path: web::Path<i32>, conn: Conn) -> Result<impl Responder> {
+    use crate::schema::dashboard_versions::dsl as v;
+    use crate::schema::dashboards::dsl as d;
+
+    let dashboard_id = path.into_inner();
+    conn.borrow_mut()
+        .deref_mut()
+        .transaction(|conn| {
+            diesel::delete(v::dashboard_versions.filter(v::dashboard_id.eq(dashboard_id)))
+                .execute(conn)?;
+            match diesel::delete(d::dashboards.filter(d::id.eq(dashboard_id))).execute(conn)? {
+                0 => Err(DieselError::NotFound),
+                _ => Ok(()),
+            }
+        })
+        .map_err(db_error)?;
+    Ok(Json(json!({"id": dashboard_id})))
+}
+
+// 在服务端计算 dashboard 每个面板当前的信号状态，单个面板出错不影响其它面板
+#[get("/dashboards/{id}/evaluate")]
+pub(crate) async fn evaluate_dashboard(
+    path: web::Path<i32>,
+    web::Query(params): web::Query<EvaluateQuery>,
+    conn: Conn,
+    broker: web::Data<SharedBroker>,
+) -> Result<impl Responder> {
+    let (dashboard, doc, saved) = load_version(
+        conn.borrow_mut().deref_mut(),
+        path.into_inner(),
+        params.version,
+    )
+    .map_err(db_error)?;
+    let use_local = params.use_local.unwrap_or(false);
+    let to = now();
+    let from = params
+        .from
+        .unwrap_or(to - DEFAULT_LOOKBACK.as_secs() as i64);
+
+    let DashboardDoc { name, panels } = doc;
+    let panels = broker
+        .run(move |z| async move { evaluate_panels(&z, &panels, use_local, from, to).await })
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+    Ok(Json(json!({
+        "id": dashboard.id,
+        "name": name,
+        "version": saved.version,
+        "evaluated": to,
+        "panels": panels,
+    })))
+}
+
+// 在 broker 线程上执行
+async fn evaluate_panels(
+    z: &Mixed,
+    panels: &[Panel],
+    use_local: bool,
+    from: i64,
+    to: i64,
+) -> Vec<PanelState> {
+    let mut states = vec![];
+    for panel in panels {
+        let mut state = PanelState::new(panel);
+        let contract = Contract::auto_stock(panel.symbol.as_str());
+        let Some(freq) = IB::freq_map().get(&panel.resolution).cloned() else {
+            state.error = Some(format!("unknown resolution {}", panel.resolution));
+            states.push(state);
+            continue;
+        };
+        let rs = z
+            .try_subscribe(use_local, &contract, freq, from, to, 0, false)
+            .await;
+        match rs {
+            Ok(_) => {
+                let zen = z.get_czsc(use_local, &contract, freq);
+                let zen = zen.read().await;
+                state.evaluate(panel, &zen);
+            }
+            Err(e) => state.error = Some(e.to_string()),
+        }
+        states.push(state);
+    }
+    states
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::http::StatusCode;
+    use actix_web::{test, App};
+    use serde_json::Value;
+
+    use super::*;
+    use crate::broker::zen::tests::zen;
+    use crate::db::connect;
+
+    fn signal(value: &str) -> Signal {
+        let other = || "other".to_string();
+        Signal {
+            key: ("日线".to_string(), "D1".to_string(), "背驰".to_string()),
+            value: (value.to_string(), other(), other()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn parse_study() {
+        assert_eq!(Study::parse("MA30"), Some(Study::Ma(30)));
+        assert_eq!(Study::parse("MACD"), Some(Study::Macd));
+        // 只支持 Zen 跟踪的均线
+        assert_eq!(Study::parse("MA7"), None);
+        assert_eq!(Study::parse("RSI"), None);
+    }
+
+    #[actix_web::test]
+    async fn dashboards_are_versioned() {
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(RefCell::new(connect(":memory:"))))
+                .service(create_dashboard)
+                .service(dashboard_versions)
+                .service(get_dashboard)
+                .service(update_dashboard),
+        )
+        .await;
+        let doc = |name: &str, study: &str| {
+            json!({
+                "name": name,
+                "panels": [{"symbol": "AAPL", "resolution": "1D", "studies": [study]}],
+            })
+        };
+
+        let req = test::TestRequest::post()
+            .uri("/dashboards")
+            .set_json(doc("a", "RSI"))
+            .to_request();
+        assert_eq!(
+            test::call_service(&app, req).await.status(),
+            StatusCode::BAD_REQUEST
+        );
+
+        let req = test::TestRequest::post()
+            .uri("/dashboards")
+            .set_json(doc("a", "MA30"))
+            .to_request();
+        let rsp: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(rsp, json!({"id": 1, "version": 1}));
+
+        let mut update = doc("b", "MACD");
+        update["version"] = json!(1);
+        let req = test::TestRequest::put()
+            .uri("/dashboards/1")
+            .set_json(&update)
+            .to_request();
+        let rsp: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(rsp, json!({"id": 1, "version": 2}));
+
+        // 基于旧版本的修改冲突
+        let req = test::TestRequest::put()
+            .uri("/dashboards/1")
+            .set_json(&update)
+            .to_request();
+        assert_eq!(
+            test::call_service(&app, req).await.status(),
+            StatusCode::CONFLICT
+        );
+        let req = test::TestRequest::put()
+            .uri("/dashboards/2")
+            .set_json(&update)
+            .to_request();
+        assert_eq!(
+            test::call_service(&app, req).await.status(),
+            StatusCode::NOT_FOUND
+        );
+
+        let req = test::TestRequest::get().uri("/dashboards/1").to_request();
+        let rsp: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(
+            (rsp["name"].clone(), rsp["version"].clone()),
+            (json!("b"), json!(2))
+        );
+        let req = test::TestRequest::get()
+            .uri("/dashboards/1?version=1")
+            .to_request();
+        let rsp: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(rsp["name"], "a");
+        assert_eq!(rsp["latest_version"], 2);
+        assert_eq!(rsp["panels"][0]["studies"], json!(["MA30"]));
+
+        let req = test::TestRequest::get()
+            .uri("/dashboards/1/versions")
+            .to_request();
+        let rsp: Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(rsp.as_array().unwrap().len(), 2);
+        assert_eq!(rsp[0]["version"], 2);
+    }
+
+    #[test]
+    fn evaluate_panel() {
+        let prices: Vec<f32> = (0..20).map(|i| 10.0 + i as f32).collect();
+        let mut zen = zen(&prices);
+        zen.signal_history.push(signal("一买"));
+        let mut panel = Panel {
+            symbol: "AAPL".to_string(),
+            resolution: "1D".to_string(),
+            studies: vec!["MA15".to_string(), "MA30".to_string(), "MACD".to_string()],
+            signal_filters: vec![signal("一买")],
+        };
+        let mut state = PanelState::new(&panel);
+        state.evaluate(&panel, &zen);
+        assert_eq!(state.last_price, Some(29.0));
+        // 最近 15 根收盘价 15..=29 的均值，K线不足 30 根时 MA30 没有值
+        assert_eq!(state.studies[0].value, Some(22.0));
+        assert_eq!(state.studies[1].value, None);
+        assert!(state.studies[2].value.is_some());
+        assert_eq!(state.signals.len(), 1);
+        assert!(state.matched);
+
+        panel.signal_filters = vec![signal("二买")];
+        state.evaluate(&panel, &zen);
+        assert!(!state.matched);
+    }
+}
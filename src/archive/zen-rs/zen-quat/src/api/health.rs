@@ -0,0 +1,69 @@
+use std::cell::RefCell;
+use std::ops::DerefMut;
+use std::time::Duration;
+
+use actix_web::http::StatusCode;
+use actix_web::{get, web, HttpResponse, Responder};
+use diesel::RunQueryDsl;
+use diesel_tracing::sqlite::InstrumentedSqliteConnection;
+use serde_json::json;
+use tokio::time::timeout;
+use tracing::debug;
+
+use crate::broker::shared::SharedBroker;
+use crate::metrics;
+
+type Conn = web::Data<RefCell<InstrumentedSqliteConnection>>;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+
+fn database_ok(conn: &Conn) -> bool {
+    diesel::sql_query("SELECT 1")
+        .execute(conn.borrow_mut().deref_mut())
+        .map_err(|e| debug!("database check error {}", e))
+        .is_ok()
+}
+
+fn status(ok: bool, database: bool, tws: bool) -> HttpResponse {
+    let code = if ok {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    HttpResponse::build(code).json(json!({"database": database, "tws": tws}))
+}
+
+// Prometheus 抓取
+#[get("/metrics")]
+pub(crate) async fn scrape(broker: web::Data<SharedBroker>) -> impl Responder {
+    let zens = broker
+        .run(|z| async move { z.zen_counts() })
+        .await
+        .unwrap_or_default();
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(metrics::render(&zens))
+}
+
+// 进程存活且数据库可用，TWS 断开时只用本地数据仍可服务
+#[get("/healthz")]
+pub(crate) async fn healthz(conn: Conn) -> impl Responder {
+    let database = database_ok(&conn);
+    status(database, database, metrics::tws_connected())
+}
+
+// 数据库和 TWS 均可用，TWS 未连接时先尝试连接
+#[get("/readyz")]
+pub(crate) async fn readyz(broker: web::Data<SharedBroker>, conn: Conn) -> impl Responder {
+    let database = database_ok(&conn);
+    if !metrics::tws_connected() {
+        let rs = broker
+            .run(|z| async move { timeout(CONNECT_TIMEOUT, z.ib.connect()).await })
+            .await;
+        if let Ok(Ok(Err(e))) = rs {
+            debug!("tws connect error {}", e);
+        }
+    }
+    let tws = metrics::tws_connected();
+    status(database && tws, database, tws)
+}
@@ -0,0 +1,217 @@
+use std::collections::HashMap;
+use std::future::Future;
+
+use futures_util::future::{join_all, FutureExt, LocalBoxFuture};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use super::types::error::code::ErrorCode;
+use super::types::error::object::ErrorObject;
+use super::types::id::Id;
+use super::types::params::Params;
+use super::types::request::RpcRequest;
+use super::types::response::{RpcPayload, RpcResponse};
+use super::types::JSONRPC_VERSION;
+
+/// The outcome of a method call, serialized as `result` or `error`.
+pub type MethodResult = Result<Value, ErrorObject>;
+
+type Method<S> = Box<dyn Fn(S, Params) -> LocalBoxFuture<'static, MethodResult>>;
+
+/// Dispatches requests to registered methods. `S` is the per-connection state handed to every call.
+pub struct Router<S> {
+    methods: HashMap<&'static str, Method<S>>,
+}
+
+impl<S: Clone + 'static> Router<S> {
+    pub fn new() -> Self {
+        Self {
+            methods: HashMap::new(),
+        }
+    }
+
+    /// Registers a method, replacing any handler previously registered under the same name.
+    pub fn method<F, Fut>(mut self, name: &'static str, f: F) -> Self
+    where
+        F: Fn(S, Params) -> Fut + 'static,
+        Fut: Future<Output = MethodResult> + 'static,
+    {
+        self.methods.insert(
+            name,
+            Box::new(move |state, params| f(state, params).boxed_local()),
+        );
+        self
+    }
+
+    /// Calls a method by name, failing with `Method not found` if it is not registered.
+    pub fn call(
+        &self,
+        state: S,
+        method: &str,
+        params: Params,
+    ) -> LocalBoxFuture<'static, MethodResult> {
+        match self.methods.get(method) {
+            Some(f) => f(state, params),
+            None => async { Err(ErrorCode::MethodNotFound.into()) }.boxed_local(),
+        }
+    }
+
+    /// Handles one request object. Resolves to `None` for notifications, which MUST NOT be answered.
+    fn handle_value(&self, state: S, value: Value) -> LocalBoxFuture<'static, Option<RpcResponse>> {
+        let req: RpcRequest = match serde_json::from_value(value) {
+            Ok(req) => req,
+            Err(_) => {
+                return async { Some(error_response(ErrorCode::InvalidRequest.into())) }
+                    .boxed_local()
+            }
+        };
+        if req.jsonrpc != JSONRPC_VERSION {
+            let id = req.id.unwrap_or(Id::Null);
+            return async move {
+                Some(RpcResponse {
+                    payload: ErrorCode::InvalidRequest.into(),
+                    id,
+                    ..Default::default()
+                })
+            }
+            .boxed_local();
+        }
+        let call = self.call(state, &req.method, req.params);
+        let id = req.id;
+        async move {
+            let payload = match call.await {
+                Ok(value) => RpcPayload::Result(value),
+                Err(e) => RpcPayload::Error(e),
+            };
+            id.map(|id| RpcResponse {
+                payload,
+                id,
+                ..Default::default()
+            })
+        }
+        .boxed_local()
+    }
+
+    /// Handles a raw message, which may be a single request or a batch. Resolves to the serialized reply, or `None`
+    /// when there is nothing to send back (notifications only).
+    pub fn handle(&self, state: S, text: &[u8]) -> LocalBoxFuture<'static, Option<String>> {
+        let value: Value = match serde_json::from_slice(text) {
+            Ok(value) => value,
+            Err(_) => {
+                let reply = error_response(ErrorCode::ParseError.into()).dump();
+                return async move { Some(reply) }.boxed_local();
+            }
+        };
+        match value {
+            Value::Array(items) if items.is_empty() => {
+                let reply = error_response(ErrorCode::InvalidRequest.into()).dump();
+                async move { Some(reply) }.boxed_local()
+            }
+            Value::Array(items) => {
+                let calls: Vec<_> = items
+                    .into_iter()
+                    .map(|item| self.handle_value(state.clone(), item))
+                    .collect();
+                async move {
+                    let responses: Vec<RpcResponse> =
+                        join_all(calls).await.into_iter().flatten().collect();
+                    if responses.is_empty() {
+                        None
+                    } else {
+                        Some(serde_json::to_string(&responses).expect("Should never failed"))
+                    }
+                }
+                .boxed_local()
+            }
+            value => self
+                .handle_value(state, value)
+                .map(|rsp| rsp.map(|r| r.dump()))
+                .boxed_local(),
+        }
+    }
+}
+
+fn error_response(error: ErrorObject) -> RpcResponse {
+    RpcResponse {
+        payload: error.into(),
+        ..Default::default()
+    }
+}
+
+/// Deserializes the first positional parameter, failing with `Invalid params`.
+pub fn first_param<T: DeserializeOwned>(params: &Params) -> Result<T, ErrorObject> {
+    let value = params
+        .as_ref()
+        .and_then(|p| p.first())
+        .ok_or_else(|| ErrorObject::from(ErrorCode::InvalidParams))?;
+    serde_json::from_value(value.clone())
+        .map_err(|e| ErrorObject::new(ErrorCode::InvalidParams, format!("Invalid params: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn router() -> Router<()> {
+        Router::new().method(
+            "echo",
+            |_, params| async move { first_param::<Value>(&params) },
+        )
+    }
+
+    fn handle(text: &str) -> Option<Value> {
+        router()
+            .handle((), text.as_bytes())
+            .now_or_never()
+            .unwrap()
+            .map(|s| serde_json::from_str(&s).unwrap())
+    }
+
+    #[test]
+    fn errors_and_notifications() {
+        assert_eq!(
+            handle(r#"{"jsonrpc":"2.0","method":"echo","params":[1],"id":1}"#),
+            Some(json!({"jsonrpc":"2.0","result":1,"id":1}))
+        );
+        assert_eq!(
+            handle(r#"{"jsonrpc":"2.0","method":"echo","params":[1]}"#),
+            None
+        );
+        assert_eq!(
+            handle(r#"{"jsonrpc":"2.0","method":"echo""#).unwrap()["error"]["code"],
+            -32700
+        );
+        assert_eq!(handle("[]").unwrap()["error"]["code"], -32600);
+        assert_eq!(
+            handle(r#"{"jsonrpc":"2.0","method":"nope","id":"a"}"#).unwrap()["error"]["code"],
+            -32601
+        );
+        assert_eq!(
+            handle(r#"{"jsonrpc":"2.0","method":"echo","id":null}"#).unwrap(),
+            json!({"jsonrpc":"2.0","error":{"code":-32602,"message":"Invalid params"},"id":null})
+        );
+    }
+
+    #[test]
+    fn batch() {
+        let rsp = handle(
+            r#"[{"jsonrpc":"2.0","method":"echo","params":["a"],"id":1},
+                {"jsonrpc":"2.0","method":"echo","params":["b"]},
+                {"foo":"bar"}]"#,
+        )
+        .unwrap();
+        assert_eq!(
+            rsp,
+            json!([
+                {"jsonrpc":"2.0","result":"a","id":1},
+                {"jsonrpc":"2.0","error":{"code":-32600,"message":"Invalid request"},"id":null}
+            ])
+        );
+        assert_eq!(
+            handle(r#"[{"jsonrpc":"2.0","method":"echo","params":[1]}]"#),
+            None
+        );
+    }
+}
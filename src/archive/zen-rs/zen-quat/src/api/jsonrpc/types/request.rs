@@ -1,5 +1,7 @@
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::Value;
 
+use super::JSONRPC_VERSION;
 use super::{id::Id, params::Params};
 
 /// A rpc call is represented by sending a Request object to a Server.
@@ -20,7 +22,20 @@ pub struct RpcRequest {
     /// An identifier established by the Client that MUST contain a String, Number, or NULL value if included. If it is
     /// not included it is assumed to be a notification. The value SHOULD normally not be Null [1] and Numbers SHOULD
     /// NOT contain fractional parts.
-    pub id: Id,
+    #[serde(
+        default,
+        deserialize_with = "deserialize_id",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub id: Option<Id>,
+}
+
+/// A missing "id" means a notification, while `"id": null` is still a request that expects a response.
+fn deserialize_id<'de, D>(deserializer: D) -> Result<Option<Id>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Id::deserialize(deserializer).map(Some)
 }
 
 impl RpcRequest {
@@ -29,3 +44,32 @@ impl RpcRequest {
         serde_json::to_string(self).expect("Should never failed")
     }
 }
+
+/// A Notification is a Request object without an "id" member. The Server uses it to push subscription updates, and the
+/// Client MUST NOT reply to it.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RpcNotification {
+    /// A String specifying the version of the JSON-RPC protocol. MUST be exactly "2.0".
+    pub jsonrpc: String,
+
+    /// A String containing the name of the method to be invoked.
+    pub method: String,
+
+    /// A Structured value that holds the parameter values.
+    pub params: Value,
+}
+
+impl RpcNotification {
+    pub fn new(method: impl Into<String>, params: Value) -> Self {
+        Self {
+            jsonrpc: JSONRPC_VERSION.into(),
+            method: method.into(),
+            params,
+        }
+    }
+
+    /// Prints out the value as JSON string.
+    pub fn dump(&self) -> String {
+        serde_json::to_string(self).expect("Should never failed")
+    }
+}
@@ -218,6 +218,41 @@ pub(crate) struct Config {
     pub supports_group_request: bool,
 }
 
+#[derive(Deserialize, Debug)]
+pub(super) struct MarksRequest {
+    pub symbol: String,
+    pub from: i64,
+    pub to: i64,
+    pub resolution: String,
+}
+
+#[derive(Serialize, Debug)]
+pub(super) struct MarkColor {
+    pub border: String,
+    pub background: String,
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub(super) struct Mark {
+    pub id: String,
+    pub time: i64,
+    pub color: MarkColor,
+    pub text: String,
+    pub label: String,
+    pub label_font_color: String,
+    pub min_size: u32,
+}
+
+#[derive(Serialize, Debug)]
+pub(super) struct TimescaleMark {
+    pub id: String,
+    pub time: i64,
+    pub color: String,
+    pub label: String,
+    pub tooltip: Vec<String>,
+}
+
 #[derive(Deserialize, Debug)]
 pub(super) struct MacdConfig {
     fast: u32,
@@ -231,9 +266,10 @@ pub(super) struct ZenRequest {
     pub(crate) to: i64,
     pub(crate) resolution: String,
     pub(crate) macd_config: Vec<MacdConfig>,
+    pub(crate) use_local: Option<bool>,
 }
 
-#[derive(Serialize, Debug)]
+#[derive(Serialize, Debug, Clone, PartialEq)]
 pub(super) struct ZenBiDetail {
     pub direction: String,
     pub end: f32,
@@ -0,0 +1,542 @@
+use std::cell::RefCell;
+use std::ops::DerefMut;
+
+use actix_web::web::Json;
+use actix_web::{delete, get, post, web, Responder, Result};
+use diesel::prelude::*;
+use diesel::result::Error as DieselError;
+use diesel::sql_types::Text;
+use diesel_tracing::sqlite::InstrumentedSqliteConnection;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use time::OffsetDateTime;
+
+use crate::auth::AuthUser;
+use crate::db::last_insert_rowid;
+use crate::db::models::{Chart, DrawingTemplate, NewChart, StudyTemplate};
+
+// 图表布局可能很大，表单默认 16K 的限制不够
+pub(crate) const MAX_CONTENT_SIZE: usize = 16 * 1024 * 1024;
+
+type Conn = web::Data<RefCell<InstrumentedSqliteConnection>>;
+
+// TradingView save/load adapter 的查询参数，图表库传来的 user 不可信，
+// 数据按认证得到的用户区分
+#[derive(Deserialize, Debug)]
+pub(super) struct StorageQuery {
+    pub client: String,
+    pub chart: Option<i32>,
+    pub template: Option<String>,
+    pub tool: Option<String>,
+    pub name: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+pub(super) struct ChartForm {
+    pub name: String,
+    pub content: String,
+    pub symbol: String,
+    pub resolution: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub(super) struct TemplateForm {
+    pub name: Option<String>,
+    pub content: String,
+}
+
+#[derive(Serialize, Debug, Default)]
+pub(super) struct StorageResponse {
+    pub status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub data: Option<Value>,
+}
+
+impl StorageResponse {
+    fn ok() -> Self {
+        Self {
+            status: "ok",
+            ..Default::default()
+        }
+    }
+
+    fn data(data: Value) -> Self {
+        Self {
+            data: Some(data),
+            ..Self::ok()
+        }
+    }
+
+    fn error(message: impl Into<String>) -> Self {
+        Self {
+            status: "error",
+            message: Some(message.into()),
+            ..Default::default()
+        }
+    }
+}
+
+// 数据库错误也按 adapter 的格式返回，图表库会把 message 显示给用户
+fn reply(rs: QueryResult<StorageResponse>, what: &str) -> Result<Json<StorageResponse>> {
+    Ok(Json(match rs {
+        Ok(rsp) => rsp,
+        Err(DieselError::NotFound) => StorageResponse::error(format!("{} not found", what)),
+        Err(e) => StorageResponse::error(e.to_string()),
+    }))
+}
+
+fn missing(param: &str) -> Result<Json<StorageResponse>> {
+    Ok(Json(StorageResponse::error(format!(
+        "missing parameter {}",
+        param
+    ))))
+}
+
+// 改为按认证用户区分之前，数据存在图表库传来的 user_id（如 zen_user_id）下，
+// 把 from 的图表和模板转给 to；to 已有同名模板时保留 to 的，返回转移的行数
+pub(crate) fn reassign_user(
+    conn: &mut InstrumentedSqliteConnection,
+    from: &str,
+    to: &str,
+) -> QueryResult<usize> {
+    use crate::schema::charts::dsl::*;
+
+    conn.transaction(|conn| {
+        let mut n = diesel::update(charts.filter(user.eq(from)))
+            .set(user.eq(to))
+            .execute(conn)?;
+        for table in ["study_templates", "drawing_templates"] {
+            n += diesel::sql_query(format!(
+                "UPDATE OR IGNORE {} SET user = ? WHERE user = ?",
+                table
+            ))
+            .bind::<Text, _>(to)
+            .bind::<Text, _>(from)
+            .execute(conn)?;
+        }
+        Ok(n)
+    })
+}
+
+#[get("/storage/1.1/charts")]
+pub(crate) async fn get_charts(
+    web::Query(params): web::Query<StorageQuery>,
+    auth: web::ReqData<AuthUser>,
+    conn: Conn,
+) -> Result<impl Responder> {
+    use crate::schema::charts::dsl::*;
+
+    let mut conn = conn.borrow_mut();
+    let query = charts
+        .filter(client.eq(&params.client))
+        .filter(user.eq(&auth.0));
+    let rs = match params.chart {
+        Some(chart_id) => query
+            .filter(id.eq(chart_id))
+            .select(Chart::as_select())
+            .first(conn.deref_mut())
+            .map(|c| {
+                StorageResponse::data(json!({
+                    "id": c.id,
+                    "name": c.name,
+                    "timestamp": c.timestamp,
+                    "content": c.content,
+                }))
+            }),
+        None => query
+            .order(timestamp.desc())
+            .select((id, name, timestamp, symbol, resolution))
+            .load::<(i32, String, i64, String, String)>(conn.deref_mut())
+            .map(|rows| {
+                StorageResponse::data(json!(rows
+                    .into_iter()
+                    .map(|(i, n, t, s, r)| json!({
+                        "id": i,
+                        "name": n,
+                        "timestamp": t,
+                        "symbol": s,
+                        "resolution": r,
+                    }))
+                    .collect::<Vec<_>>()))
+            }),
+    };
+    reply(rs, "chart")
+}
+
+// 不带 chart 时新建，带 chart 时覆盖已有图表
+#[post("/storage/1.1/charts")]
+pub(crate) async fn save_chart(
+    web::Query(params): web::Query<StorageQuery>,
+    auth: web::ReqData<AuthUser>,
+    web::Form(form): web::Form<ChartForm>,
+    conn: Conn,
+) -> Result<impl Responder> {
+    use crate::schema::charts::dsl::*;
+
+    let mut conn = conn.borrow_mut();
+    let now = OffsetDateTime::now_utc().unix_timestamp();
+    let rs = match params.chart {
+        Some(chart_id) => diesel::update(
+            charts
+                .filter(client.eq(&params.client))
+                .filter(user.eq(&auth.0))
+                .filter(id.eq(chart_id)),
+        )
+        .set((
+            name.eq(&form.name),
+            symbol.eq(&form.symbol),
+            resolution.eq(&form.resolution),
+            content.eq(&form.content),
+            timestamp.eq(now),
+        ))
+        .execute(conn.deref_mut())
+        .and_then(|n| match n {
+            0 => Err(DieselError::NotFound),
+            _ => Ok(StorageResponse::ok()),
+        }),
+        None => conn.deref_mut().transaction(|conn| {
+            diesel::insert_into(charts)
+                .values(NewChart {
+                    client: &params.client,
+                    user: &auth.0,
+                    name: &form.name,
+                    symbol: &form.symbol,
+                    resolution: &form.resolution,
+                    content: &form.content,
+                    timestamp: now,
+                })
+                .execute(conn)?;
+            diesel::select(last_insert_rowid())
+                .get_result::<i32>(conn)
+                .map(|new_id| StorageResponse {
+                    id: Some(new_id),
+                    ..StorageResponse::ok()
+                })
+        }),
+    };
+    reply(rs, "chart")
+}
+
+#[delete("/storage/1.1/charts")]
+pub(crate) async fn delete_chart(
+    web::Query(params): web::Query<StorageQuery>,
+    auth: web::ReqData<AuthUser>,
+    conn: Conn,
+) -> Result<impl Responder> {
+    use crate::schema::charts::dsl::*;
+
+    let Some(chart_id) = params.chart else {
+        return missing("chart");
+    };
+    let rs = diesel::delete(
+        charts
+            .filter(client.eq(&params.client))
+            .filter(user.eq(&auth.0))
+            .filter(id.eq(chart_id)),
+    )
+    .execute(conn.borrow_mut().deref_mut())
+    .and_then(|n| match n {
+        0 => Err(DieselError::NotFound),
+        _ => Ok(StorageResponse::ok()),
+    });
+    reply(rs, "chart")
+}
+
+#[get("/storage/1.1/study_templates")]
+pub(crate) async fn get_study_templates(
+    web::Query(params): web::Query<StorageQuery>,
+    auth: web::ReqData<AuthUser>,
+    conn: Conn,
+) -> Result<impl Responder> {
+    use crate::schema::study_templates::dsl::*;
+
+    let mut conn = conn.borrow_mut();
+    let query = study_templates
+        .filter(client.eq(&params.client))
+        .filter(user.eq(&auth.0));
+    let rs = match &params.template {
+        Some(template) => query
+            .filter(name.eq(template))
+            .select(StudyTemplate::as_select())
+            .first(conn.deref_mut())
+            .map(|t| StorageResponse::data(json!({"name": t.name, "content": t.content}))),
+        None => query
+            .order(name.asc())
+            .select(name)
+            .load::<String>(conn.deref_mut())
+            .map(|names| {
+                StorageResponse::data(json!(names
+                    .into_iter()
+                    .map(|n| json!({ "name": n }))
+                    .collect::<Vec<_>>()))
+            }),
+    };
+    reply(rs, "study template")
+}
+
+// 同名模板直接覆盖
+#[post("/storage/1.1/study_templates")]
+pub(crate) async fn save_study_template(
+    web::Query(params): web::Query<StorageQuery>,
+    auth: web::ReqData<AuthUser>,
+    web::Form(form): web::Form<TemplateForm>,
+    conn: Conn,
+) -> Result<impl Responder> {
+    use crate::schema::study_templates::dsl::*;
+
+    let Some(template) = form.name.or(params.template) else {
+        return missing("name");
+    };
+    let rs = diesel::replace_into(study_templates)
+        .values(StudyTemplate {
+            client: params.client,
+            user: auth.0.clone(),
+            name: template,
+            content: form.content,
+        })
+        .execute(conn.borrow_mut().deref_mut())
+        .map(|_| StorageResponse::ok());
+    reply(rs, "study template")
+}
+
+#[delete("/storage/1.1/study_templates")]
+pub(crate) async fn delete_study_template(
+    web::Query(params): web::Query<StorageQuery>,
+    auth: web::ReqData<AuthUser>,
+    conn: Conn,
+) -> Result<impl Responder> {
+    use crate::schema::study_templates::dsl::*;
+
+    let Some(template) = &params.template else {
+        return missing("template");
+    };
+    let rs = diesel::delete(
+        study_templates
+            .filter(client.eq(&params.client))
+            .filter(user.eq(&auth.0))
+            .filter(name.eq(template)),
+    )
+    .execute(conn.borrow_mut().deref_mut())
+    .and_then(|n| match n {
+        0 => Err(DieselError::NotFound),
+        _ => Ok(StorageResponse::ok()),
+    });
+    reply(rs, "study template")
+}
+
+// 画线模板按工具区分，不带 name 时返回该工具下的模板名
+#[get("/storage/1.1/drawing_templates")]
+pub(crate) async fn get_drawing_templates(
+    web::Query(params): web::Query<StorageQuery>,
+    auth: web::ReqData<AuthUser>,
+    conn: Conn,
+) -> Result<impl Responder> {
+    use crate::schema::drawing_templates::dsl::*;
+
+    let Some(drawing_tool) = &params.tool else {
+        return missing("tool");
+    };
+    let mut conn = conn.borrow_mut();
+    let query = drawing_templates
+        .filter(client.eq(&params.client))
+        .filter(user.eq(&auth.0))
+        .filter(tool.eq(drawing_tool));
+    let rs = match &params.name {
+        Some(template) => query
+            .filter(name.eq(template))
+            .select(DrawingTemplate::as_select())
+            .first(conn.deref_mut())
+            .map(|t| StorageResponse::data(json!({"name": t.name, "content": t.content}))),
+        None => query
+            .order(name.asc())
+            .select(name)
+            .load::<String>(conn.deref_mut())
+            .map(|names| StorageResponse::data(json!(names))),
+    };
+    reply(rs, "drawing template")
+}
+
+#[post("/storage/1.1/drawing_templates")]
+pub(crate) async fn save_drawing_template(
+    web::Query(params): web::Query<StorageQuery>,
+    auth: web::ReqData<AuthUser>,
+    web::Form(form): web::Form<TemplateForm>,
+    conn: Conn,
+) -> Result<impl Responder> {
+    use crate::schema::drawing_templates::dsl::*;
+
+    let Some(drawing_tool) = params.tool else {
+        return missing("tool");
+    };
+    let Some(template) = params.name.or(form.name) else {
+        return missing("name");
+    };
+    let rs = diesel::replace_into(drawing_templates)
+        .values(DrawingTemplate {
+            client: params.client,
+            user: auth.0.clone(),
+            tool: drawing_tool,
+            name: template,
+            content: form.content,
+        })
+        .execute(conn.borrow_mut().deref_mut())
+        .map(|_| StorageResponse::ok());
+    reply(rs, "drawing template")
+}
+
+#[delete("/storage/1.1/drawing_templates")]
+pub(crate) async fn delete_drawing_template(
+    web::Query(params): web::Query<StorageQuery>,
+    auth: web::ReqData<AuthUser>,
+    conn: Conn,
+) -> Result<impl Responder> {
+    use crate::schema::drawing_templates::dsl::*;
+
+    let (Some(drawing_tool), Some(template)) = (&params.tool, &params.name) else {
+        return missing("tool and name");
+    };
+    let rs = diesel::delete(
+        drawing_templates
+            .filter(client.eq(&params.client))
+            .filter(user.eq(&auth.0))
+            .filter(tool.eq(drawing_tool))
+            .filter(name.eq(template)),
+    )
+    .execute(conn.borrow_mut().deref_mut())
+    .and_then(|n| match n {
+        0 => Err(DieselError::NotFound),
+        _ => Ok(StorageResponse::ok()),
+    });
+    reply(rs, "drawing template")
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::middleware::from_fn;
+    use actix_web::{test, App};
+
+    use super::*;
+    use crate::auth::{authenticate, LOCAL_USER};
+    use crate::db::connect;
+
+    fn form(chart_name: &str) -> [(&'static str, &str); 4] {
+        [
+            ("name", chart_name),
+            ("content", "{}"),
+            ("symbol", "AAPL"),
+            ("resolution", "1D"),
+        ]
+    }
+
+    #[actix_web::test]
+    async fn charts_are_saved_per_user() {
+        let conn = web::Data::new(RefCell::new(connect(":memory:")));
+        diesel::insert_into(crate::schema::charts::table)
+            .values(NewChart {
+                client: "tv",
+                user: "other",
+                name: "other",
+                symbol: "AAPL",
+                resolution: "1D",
+                content: "{}",
+                timestamp: 0,
+            })
+            .execute(conn.borrow_mut().deref_mut())
+            .unwrap();
+        let app = test::init_service(
+            App::new()
+                .wrap(from_fn(authenticate))
+                .app_data(conn.clone())
+                .service(get_charts)
+                .service(save_chart)
+                .service(delete_chart),
+        )
+        .await;
+        let call = |req: test::TestRequest| {
+            test::call_and_read_body_json::<_, _, Value>(&app, req.to_request())
+        };
+
+        let rsp = call(
+            test::TestRequest::post()
+                .uri("/storage/1.1/charts?client=tv")
+                .set_form(form("a")),
+        )
+        .await;
+        assert_eq!(rsp["status"], "ok");
+        assert_eq!(rsp["id"], 2);
+
+        // 其它用户的图表既看不到也改不了
+        let rsp = call(test::TestRequest::get().uri("/storage/1.1/charts?client=tv")).await;
+        assert_eq!(rsp["data"].as_array().unwrap().len(), 1);
+        assert_eq!(rsp["data"][0]["name"], "a");
+        let rsp = call(
+            test::TestRequest::post()
+                .uri("/storage/1.1/charts?client=tv&chart=1")
+                .set_form(form("b")),
+        )
+        .await;
+        assert_eq!(rsp["status"], "error");
+
+        let rsp = call(
+            test::TestRequest::post()
+                .uri("/storage/1.1/charts?client=tv&chart=2")
+                .set_form(form("b")),
+        )
+        .await;
+        assert_eq!(rsp["status"], "ok");
+        let rsp = call(test::TestRequest::get().uri("/storage/1.1/charts?client=tv&chart=2")).await;
+        assert_eq!(rsp["data"]["name"], "b");
+
+        let rsp =
+            call(test::TestRequest::delete().uri("/storage/1.1/charts?client=tv&chart=2")).await;
+        assert_eq!(rsp["status"], "ok");
+        let rsp = call(test::TestRequest::get().uri("/storage/1.1/charts?client=tv")).await;
+        assert_eq!(rsp["data"], json!([]));
+
+        // 旧数据转给当前用户后可见
+        let n = reassign_user(conn.borrow_mut().deref_mut(), "other", LOCAL_USER).unwrap();
+        assert_eq!(n, 1);
+        let rsp = call(test::TestRequest::get().uri("/storage/1.1/charts?client=tv")).await;
+        assert_eq!(rsp["data"][0]["name"], "other");
+    }
+
+    #[test]
+    fn reassign_keeps_existing_templates() {
+        use crate::schema::study_templates::dsl::*;
+
+        let mut conn = connect(":memory:");
+        let template = |owner: &str, template_name: &str| StudyTemplate {
+            client: "tv".to_string(),
+            user: owner.to_string(),
+            name: template_name.to_string(),
+            content: owner.to_string(),
+        };
+        diesel::insert_into(study_templates)
+            .values(vec![
+                template("old", "a"),
+                template("old", "b"),
+                template("new", "b"),
+            ])
+            .execute(&mut conn)
+            .unwrap();
+
+        assert_eq!(reassign_user(&mut conn, "old", "new").unwrap(), 1);
+        let rows = study_templates
+            .filter(user.eq("new"))
+            .order(name.asc())
+            .select((name, content))
+            .load::<(String, String)>(&mut conn)
+            .unwrap();
+        assert_eq!(
+            rows,
+            vec![
+                ("a".to_string(), "old".to_string()),
+                ("b".to_string(), "new".to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,453 @@
+use std::collections::hash_map::Entry;
+use std::future::Future;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use time::OffsetDateTime;
+use tracing::error;
+use tws_rs::contracts::Contract;
+use zen_core::objects::enums::{Direction, Freq};
+
+use super::WsState;
+use crate::api::jsonrpc::router::{first_param, MethodResult};
+use crate::api::jsonrpc::types::error::code::{
+    ErrorCode, CALL_EXECUTION_FAILED_CODE, TOO_MANY_SUBSCRIPTIONS_CODE, TOO_MANY_SUBSCRIPTIONS_MSG,
+};
+use crate::api::jsonrpc::types::error::object::ErrorObject;
+use crate::api::jsonrpc::types::params::Params;
+use crate::api::params::ZenBiDetail;
+use crate::broker::ib::IB;
+use crate::broker::shared::SharedBroker;
+use crate::broker::zen::Zen;
+use crate::calculate::beichi::buy_sell_point::BSPoint;
+
+// 订阅的推送间隔
+pub(super) const PUSH_INTERVAL: Duration = Duration::from_secs(1);
+// 每个连接最多订阅数
+pub(super) const MAX_SUBSCRIPTIONS: usize = 32;
+
+#[derive(Deserialize, Debug)]
+pub(super) struct SubscribeRequest {
+    pub symbol: String,
+    pub resolution: String,
+    pub from: Option<i64>,
+    pub use_local: Option<bool>,
+}
+
+impl SubscribeRequest {
+    pub fn key(&self) -> String {
+        format!("{}:{}", self.symbol, self.resolution)
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub(super) struct BarItem {
+    pub t: i64,
+    pub o: f32,
+    pub c: f32,
+    pub h: f32,
+    pub l: f32,
+    pub v: f32,
+}
+
+// 推送给客户端的增量
+#[derive(Serialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub(super) enum ZenEvent {
+    // new 为 false 时表示最后一根K线更新
+    Bar { bar: BarItem, new: bool },
+    BiConfirmed { bi: ZenBiDetail },
+    BiRemoved { bi: ZenBiDetail },
+    UnfinishedBi { bi: Option<ZenBiDetail> },
+    BsPointAdded { point: BSPoint },
+    BsPointUpdated { point: BSPoint },
+    BsPointInvalidated { point: BSPoint },
+}
+
+pub(super) fn bi_details(zen: &Zen, from: i64) -> Vec<ZenBiDetail> {
+    zen.czsc
+        .bi_list
+        .iter()
+        .filter(|bi| bi.fx_b.dt.unix_timestamp() >= from)
+        .map(|bi| ZenBiDetail {
+            direction: String::from(bi.direction.as_str()),
+            end: if bi.direction == Direction::Down {
+                bi.low()
+            } else {
+                bi.high()
+            },
+            end_ts: bi.fx_b.dt.unix_timestamp(),
+            start: if bi.direction == Direction::Down {
+                bi.high()
+            } else {
+                bi.low()
+            },
+            start_ts: bi.fx_a.dt.unix_timestamp(),
+        })
+        .collect()
+}
+
+// 最后一笔之后尚未完成的笔
+pub(super) fn unfinished_bi(zen: &Zen) -> Option<ZenBiDetail> {
+    let czsc = &zen.czsc;
+    let start = czsc.bars_ubi.get(1)?;
+    match czsc.bi_list.last()?.direction {
+        Direction::Up => {
+            let bar = czsc
+                .bars_ubi
+                .iter()
+                .skip(1)
+                .min_by(|a, b| a.low.partial_cmp(&b.low).unwrap())?;
+            Some(ZenBiDetail {
+                direction: String::from(Direction::Down.as_str()),
+                end: bar.low,
+                end_ts: bar.dt.unix_timestamp(),
+                start: start.high,
+                start_ts: start.dt.unix_timestamp(),
+            })
+        }
+        Direction::Down => {
+            let bar = czsc
+                .bars_ubi
+                .iter()
+                .skip(1)
+                .max_by(|a, b| a.high.partial_cmp(&b.high).unwrap())?;
+            Some(ZenBiDetail {
+                direction: String::from(Direction::Up.as_str()),
+                end: bar.high,
+                end_ts: bar.dt.unix_timestamp(),
+                start: start.low,
+                start_ts: start.dt.unix_timestamp(),
+            })
+        }
+    }
+}
+
+// 某个订阅最近一次推送时的状态，seq 每推送一次加一，客户端发现跳号时调用 resync 取全量快照
+pub(super) struct Subscription {
+    pub contract: Contract,
+    pub freq: Freq,
+    pub use_local: bool,
+    pub seq: u64,
+    from: i64,
+    last_bar: Option<BarItem>,
+    bis: Vec<ZenBiDetail>,
+    unfinished: Option<ZenBiDetail>,
+    points: Vec<(BSPoint, Value)>,
+}
+
+impl Subscription {
+    pub fn new(contract: Contract, freq: Freq, use_local: bool, from: i64) -> Self {
+        Self {
+            contract,
+            freq,
+            use_local,
+            seq: 0,
+            from,
+            last_bar: None,
+            bis: vec![],
+            unfinished: None,
+            points: vec![],
+        }
+    }
+
+    fn capture_points(zen: &Zen, from: i64) -> Vec<(BSPoint, Value)> {
+        zen.beichi_processor
+            .beichi_tracker
+            .iter()
+            .filter(|bc| bc.macd_b_dt >= from)
+            .map(|bc| (bc.clone(), json!(bc)))
+            .collect()
+    }
+
+    fn bar_item(zen: &Zen, index: usize) -> BarItem {
+        let bar = zen.czsc.bars_raw[index].borrow();
+        BarItem {
+            t: bar.dt.unix_timestamp(),
+            o: bar.open,
+            c: bar.close,
+            h: bar.high,
+            l: bar.low,
+            v: bar.vol,
+        }
+    }
+
+    // 全量快照，客户端据此重建后按 seq 接收增量
+    pub fn snapshot(&mut self, zen: &Zen) -> Value {
+        self.last_bar = zen
+            .czsc
+            .bars_raw
+            .len()
+            .checked_sub(1)
+            .map(|i| Self::bar_item(zen, i));
+        self.bis = bi_details(zen, self.from);
+        self.unfinished = unfinished_bi(zen);
+        self.points = Self::capture_points(zen, self.from);
+        json!({
+            "seq": self.seq,
+            "bar": self.last_bar,
+            "bi": {"finished": self.bis, "unfinished": self.unfinished},
+            "beichi": self.points.iter().map(|(_, v)| v).collect::<Vec<_>>(),
+        })
+    }
+
+    // 与上次推送相比的增量，有增量时 seq 加一
+    pub fn diff(&mut self, zen: &Zen) -> Vec<ZenEvent> {
+        let mut events = vec![];
+
+        let bars = &zen.czsc.bars_raw;
+        let last_t = self.last_bar.as_ref().map(|b| b.t).unwrap_or(i64::MIN);
+        let start = bars
+            .iter()
+            .rposition(|b| b.borrow().dt.unix_timestamp() <= last_t)
+            .unwrap_or(0);
+        for i in start..bars.len() {
+            let bar = Self::bar_item(zen, i);
+            if bar.t < last_t || self.last_bar.as_ref() == Some(&bar) {
+                continue;
+            }
+            events.push(ZenEvent::Bar {
+                new: bar.t > last_t,
+                bar: bar.clone(),
+            });
+            self.last_bar = Some(bar);
+        }
+
+        // 笔列表过长时会从头部裁剪，裁剪掉的笔不算撤销
+        let bis = bi_details(zen, self.from);
+        let first = bis.first().map(|b| b.start_ts).unwrap_or(i64::MAX);
+        for bi in &self.bis {
+            if bi.start_ts >= first && !bis.contains(bi) {
+                events.push(ZenEvent::BiRemoved { bi: bi.clone() });
+            }
+        }
+        for bi in &bis {
+            if !self.bis.contains(bi) {
+                events.push(ZenEvent::BiConfirmed { bi: bi.clone() });
+            }
+        }
+        self.bis = bis;
+
+        let unfinished = unfinished_bi(zen);
+        if unfinished != self.unfinished {
+            events.push(ZenEvent::UnfinishedBi {
+                bi: unfinished.clone(),
+            });
+            self.unfinished = unfinished;
+        }
+
+        // 买卖点超过 100 个时从头部裁剪，与笔一样，裁剪掉的不算撤销
+        let points = Self::capture_points(zen, self.from);
+        let key = |bc: &BSPoint| (bc.zs2.left, bc.zs2.right);
+        let kept = points
+            .first()
+            .and_then(|(first, _)| {
+                self.points
+                    .iter()
+                    .position(|(old, _)| key(old) == key(first))
+            })
+            .unwrap_or(0);
+        for (old, _) in &self.points[kept..] {
+            if !points.iter().any(|(bc, _)| key(bc) == key(old)) {
+                events.push(ZenEvent::BsPointInvalidated { point: old.clone() });
+            }
+        }
+        for (bc, value) in &points {
+            match self.points.iter().find(|(old, _)| key(old) == key(bc)) {
+                None => events.push(ZenEvent::BsPointAdded { point: bc.clone() }),
+                Some((_, old)) if old != value => {
+                    events.push(ZenEvent::BsPointUpdated { point: bc.clone() })
+                }
+                _ => {}
+            }
+        }
+        self.points = points;
+
+        if !events.is_empty() {
+            self.seq += 1;
+        }
+        events
+    }
+}
+
+// 订阅后先返回全量快照，之后按 seq 推送 zen_update 通知
+pub(super) async fn subscribe(state: WsState, params: Params) -> MethodResult {
+    let req = first_param::<SubscribeRequest>(&params)?;
+    let key = req.key();
+    let freq = IB::freq_map()
+        .get(&req.resolution)
+        .cloned()
+        .ok_or_else(|| ErrorObject::from(ErrorCode::InvalidParams))?;
+    {
+        let subscriptions = state.subscriptions.lock().unwrap();
+        if !subscriptions.contains_key(&key) && subscriptions.len() >= MAX_SUBSCRIPTIONS {
+            return Err(ErrorObject::new(
+                TOO_MANY_SUBSCRIPTIONS_CODE,
+                TOO_MANY_SUBSCRIPTIONS_MSG,
+            ));
+        }
+    }
+
+    let contract = Contract::auto_stock(req.symbol.as_str());
+    let use_local = req.use_local.unwrap_or(false);
+    let from = req.from.unwrap_or(0);
+    let subscriptions = state.subscriptions.clone();
+    state
+        .broker
+        .run(move |broker| async move {
+            let failed =
+                |e: tws_rs::Error| ErrorObject::new(CALL_EXECUTION_FAILED_CODE, e.to_string());
+            // 每次请求都先计数，本连接已订阅时最后退还
+            let to = OffsetDateTime::now_utc().unix_timestamp();
+            if let Err(e) = broker.watch(use_local, &contract, freq, from, to).await {
+                broker.unwatch(use_local, &contract, freq).await.ok();
+                return Err(failed(e));
+            }
+            let zen = broker.get_czsc(use_local, &contract, freq);
+            let zen = zen.read().await;
+
+            // 等待期间不持有锁
+            let (snapshot, subscribed) = {
+                let mut subscriptions = subscriptions.lock().unwrap();
+                match subscriptions.entry(key.clone()) {
+                    Entry::Occupied(e) => (e.into_mut().snapshot(&zen), true),
+                    Entry::Vacant(e) => {
+                        let sub = Subscription::new(contract.clone(), freq, use_local, from);
+                        (e.insert(sub).snapshot(&zen), false)
+                    }
+                }
+            };
+            drop(zen);
+            if subscribed {
+                broker
+                    .unwatch(use_local, &contract, freq)
+                    .await
+                    .map_err(failed)?;
+            }
+            Ok::<_, ErrorObject>(json!({"subscription": key, "snapshot": snapshot}))
+        })
+        .await
+        .map_err(|e| ErrorObject::new(CALL_EXECUTION_FAILED_CODE, e.to_string()))?
+}
+
+pub(super) async fn unsubscribe(state: WsState, params: Params) -> MethodResult {
+    let key = first_param::<SubscribeRequest>(&params)?.key();
+    let Some(sub) = state.subscriptions.lock().unwrap().remove(&key) else {
+        return Ok(json!(false));
+    };
+    release(&state.broker, &sub)
+        .await
+        .map_err(|e| ErrorObject::new(CALL_EXECUTION_FAILED_CODE, e))?;
+    Ok(json!(true))
+}
+
+// 释放一个订阅，最后一个订阅者退订时停止 IB 的实时推送；任务立即投递，不等待结果也会执行
+pub(super) fn release(
+    broker: &SharedBroker,
+    sub: &Subscription,
+) -> impl Future<Output = Result<(), String>> {
+    let (use_local, contract, freq) = (sub.use_local, sub.contract.clone(), sub.freq);
+    let done = broker.run(move |broker| async move {
+        let rs = broker.unwatch(use_local, &contract, freq).await;
+        if let Err(e) = &rs {
+            error!("unsubscribe {} {:?}: {}", contract.symbol, freq, e);
+        }
+        rs.map_err(|e| e.to_string())
+    });
+    async move { done.await.map_err(|e| e.to_string())? }
+}
+
+// 客户端发现 seq 跳号时重新取全量快照
+pub(super) async fn resync(state: WsState, params: Params) -> MethodResult {
+    let key = first_param::<SubscribeRequest>(&params)?.key();
+    let not_subscribed = |key: &str| {
+        ErrorObject::new(
+            ErrorCode::InvalidParams,
+            format!("{} is not subscribed", key),
+        )
+    };
+    let (contract, freq, use_local) = state
+        .subscriptions
+        .lock()
+        .unwrap()
+        .get(&key)
+        .map(|sub| (sub.contract.clone(), sub.freq, sub.use_local))
+        .ok_or_else(|| not_subscribed(&key))?;
+    let subscriptions = state.subscriptions.clone();
+    state
+        .broker
+        .run(move |broker| async move {
+            let zen = broker.get_czsc(use_local, &contract, freq);
+            let zen = zen.read().await;
+
+            let mut subscriptions = subscriptions.lock().unwrap();
+            let sub = subscriptions
+                .get_mut(&key)
+                .ok_or_else(|| not_subscribed(&key))?;
+            Ok::<_, ErrorObject>(json!({"subscription": key, "snapshot": sub.snapshot(&zen)}))
+        })
+        .await
+        .map_err(|e| ErrorObject::new(CALL_EXECUTION_FAILED_CODE, e.to_string()))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::broker::zen::tests::{bar, pivots, zen, zigzag};
+
+    #[test]
+    fn bar_updates_and_seq() {
+        let mut zen = zen(&[]);
+        let mut sub = Subscription::new(zen.contract.clone(), Freq::D, false, 0);
+        sub.snapshot(&zen);
+        assert!(sub.diff(&zen).is_empty());
+
+        for i in 0..3 {
+            zen.update(bar(i, 10.0 + i as f32));
+        }
+        let events = sub.diff(&zen);
+        assert_eq!(events.len(), 3);
+        assert!(events
+            .iter()
+            .all(|e| matches!(e, ZenEvent::Bar { new: true, .. })));
+        assert_eq!(sub.seq, 1);
+
+        // 没有变化时不推送、seq 不变
+        assert!(sub.diff(&zen).is_empty());
+        assert_eq!(sub.seq, 1);
+
+        // 最后一根K线更新
+        zen.update(bar(2, 13.0));
+        let events = sub.diff(&zen);
+        assert!(matches!(
+            events.as_slice(),
+            [ZenEvent::Bar { new: false, bar }] if bar.c == 13.0
+        ));
+        assert_eq!(sub.seq, 2);
+    }
+
+    #[test]
+    fn truncated_points_are_not_invalidated() {
+        let mut zen = zen(&zigzag(&pivots(), 8));
+        let mut sub = Subscription::new(zen.contract.clone(), Freq::D, false, 0);
+        sub.snapshot(&zen);
+        let tracker = &mut zen.beichi_processor.beichi_tracker;
+        assert!(tracker.len() >= 2);
+
+        // 从头部裁剪，与超过 100 个时一致
+        tracker.remove(0);
+        assert!(sub.diff(&zen).is_empty());
+        assert_eq!(sub.seq, 0);
+
+        // 其余位置的买卖点消失才算撤销
+        let removed = zen.beichi_processor.beichi_tracker.pop().unwrap();
+        let events = sub.diff(&zen);
+        assert!(matches!(
+            events.as_slice(),
+            [ZenEvent::BsPointInvalidated { point }]
+                if point.zs2.left == removed.zs2.left && point.zs2.right == removed.zs2.right
+        ));
+        assert_eq!(sub.seq, 1);
+    }
+}
@@ -0,0 +1,150 @@
+use std::cell::RefCell;
+use std::ops::DerefMut;
+
+use actix_web::web::Json;
+use actix_web::{delete, error, get, put, web, Responder, Result};
+use diesel::prelude::*;
+use diesel_tracing::sqlite::InstrumentedSqliteConnection;
+use serde::Deserialize;
+use serde_json::json;
+use time::OffsetDateTime;
+use tws_rs::contracts::Contract;
+
+use crate::auth::AuthUser;
+use crate::db::models::Watchlist;
+use crate::pkg::stock_scan::MAX_SYMBOLS;
+
+type Conn = web::Data<RefCell<InstrumentedSqliteConnection>>;
+
+#[derive(Deserialize, Debug)]
+pub(super) struct WatchlistRequest {
+    // 图表上使用的代码，如 TSLA、SSE:600000
+    pub symbols: Vec<String>,
+}
+
+fn split(symbols: &str) -> Vec<String> {
+    symbols.lines().map(str::to_string).collect()
+}
+
+// 当前用户的自选列表
+pub(super) fn watchlist_contracts(
+    conn: &mut InstrumentedSqliteConnection,
+    user_: &str,
+    name_: &str,
+) -> Result<Vec<Contract>> {
+    use crate::schema::watchlists::dsl::*;
+
+    let saved = watchlists
+        .filter(user.eq(user_))
+        .filter(name.eq(name_))
+        .select(symbols)
+        .first::<String>(conn)
+        .optional()
+        .map_err(error::ErrorInternalServerError)?
+        .ok_or_else(|| error::ErrorNotFound(format!("watchlist {} not found", name_)))?;
+    Ok(split(&saved)
+        .iter()
+        .map(|s| Contract::auto_stock(s))
+        .collect())
+}
+
+#[get("/watchlists")]
+pub(crate) async fn list_watchlists(
+    auth: web::ReqData<AuthUser>,
+    conn: Conn,
+) -> Result<impl Responder> {
+    use crate::schema::watchlists::dsl::*;
+
+    let rs = watchlists
+        .filter(user.eq(&auth.0))
+        .order(name.asc())
+        .select((name, updated))
+        .load::<(String, i64)>(conn.borrow_mut().deref_mut())
+        .map_err(error::ErrorInternalServerError)?;
+    Ok(Json(
+        rs.into_iter()
+            .map(|(n, u)| json!({"name": n, "updated": u}))
+            .collect::<Vec<_>>(),
+    ))
+}
+
+#[get("/watchlists/{name}")]
+pub(crate) async fn get_watchlist(
+    path: web::Path<String>,
+    auth: web::ReqData<AuthUser>,
+    conn: Conn,
+) -> Result<impl Responder> {
+    use crate::schema::watchlists::dsl::*;
+
+    let saved = watchlists
+        .filter(user.eq(&auth.0))
+        .filter(name.eq(path.as_str()))
+        .select(Watchlist::as_select())
+        .first(conn.borrow_mut().deref_mut())
+        .optional()
+        .map_err(error::ErrorInternalServerError)?
+        .ok_or_else(|| error::ErrorNotFound(format!("watchlist {} not found", path)))?;
+    Ok(Json(json!({
+        "name": saved.name,
+        "symbols": split(&saved.symbols),
+        "updated": saved.updated,
+    })))
+}
+
+// 整体替换，不存在时新建
+#[put("/watchlists/{name}")]
+pub(crate) async fn save_watchlist(
+    path: web::Path<String>,
+    auth: web::ReqData<AuthUser>,
+    Json(req): Json<WatchlistRequest>,
+    conn: Conn,
+) -> Result<impl Responder> {
+    use crate::schema::watchlists::dsl::*;
+
+    if req.symbols.len() > MAX_SYMBOLS {
+        return Err(error::ErrorBadRequest(format!(
+            "too many symbols, at most {}",
+            MAX_SYMBOLS
+        )));
+    }
+    let list: Vec<&str> = req
+        .symbols
+        .iter()
+        .map(|s| s.trim())
+        .filter(|s| !s.is_empty())
+        .collect();
+    diesel::replace_into(watchlists)
+        .values(Watchlist {
+            user: auth.0.clone(),
+            name: path.into_inner(),
+            symbols: list.join("\n"),
+            updated: OffsetDateTime::now_utc().unix_timestamp(),
+        })
+        .execute(conn.borrow_mut().deref_mut())
+        .map_err(error::ErrorInternalServerError)?;
+    Ok(Json(json!({"symbols": list.len()})))
+}
+
+#[delete("/watchlists/{name}")]
+pub(crate) async fn delete_watchlist(
+    path: web::Path<String>,
+    auth: web::ReqData<AuthUser>,
+    conn: Conn,
+) -> Result<impl Responder> {
+    use crate::schema::watchlists::dsl::*;
+
+    let n = diesel::delete(
+        watchlists
+            .filter(user.eq(&auth.0))
+            .filter(name.eq(path.as_str())),
+    )
+    .execute(conn.borrow_mut().deref_mut())
+    .map_err(error::ErrorInternalServerError)?;
+    if n == 0 {
+        return Err(error::ErrorNotFound(format!(
+            "watchlist {} not found",
+            path
+        )));
+    }
+    Ok(Json(json!({"deleted": true})))
+}
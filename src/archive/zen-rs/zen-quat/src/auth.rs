@@ -0,0 +1,210 @@
+use std::collections::HashMap;
+use std::fs;
+
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::{error, web, Error, HttpMessage};
+use anyhow::{bail, Result};
+use serde::Deserialize;
+
+// 未启用认证时所有请求都归到这个用户
+pub const LOCAL_USER: &str = "local";
+
+// API key -> 用户名，为空表示未启用认证
+#[derive(Clone, Debug, Default)]
+pub struct AuthConfig {
+    keys: HashMap<String, String>,
+}
+
+impl AuthConfig {
+    // 每行一个 `用户名 key`，# 开头为注释
+    pub fn from_file(path: &str) -> Result<Self> {
+        let mut keys = HashMap::new();
+        for (idx, line) in fs::read_to_string(path)?.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut parts = line.split_whitespace();
+            let (Some(user), Some(key), None) = (parts.next(), parts.next(), parts.next()) else {
+                bail!("{}:{} expect `user key`", path, idx + 1);
+            };
+            if keys.insert(key.to_string(), user.to_string()).is_some() {
+                bail!("{}:{} duplicated key", path, idx + 1);
+            }
+        }
+        if keys.is_empty() {
+            bail!("{} has no api key", path);
+        }
+        Ok(Self { keys })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.keys.is_empty()
+    }
+
+    fn user(&self, token: &str) -> Option<&str> {
+        // 逐个比较全部 key，不因提前返回泄露匹配位置
+        let mut found = None;
+        for (key, user) in &self.keys {
+            if constant_time_eq(key.as_bytes(), token.as_bytes()) {
+                found = Some(user.as_str());
+            }
+        }
+        found
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+// 当前请求的用户，由 authenticate 放入 request extensions
+#[derive(Clone, Debug)]
+pub struct AuthUser(pub String);
+
+#[derive(Deserialize)]
+struct TokenQuery {
+    token: Option<String>,
+}
+
+// 依次取 Authorization: Bearer、X-API-Key 头及 token 查询参数，
+// websocket 和 TradingView 存储接口无法自定义请求头，只能用查询参数
+fn token(req: &ServiceRequest) -> Option<String> {
+    let header = |name: &str| {
+        req.headers()
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.trim().to_string())
+    };
+    header("Authorization")
+        .and_then(|v| v.strip_prefix("Bearer ").map(|t| t.trim().to_string()))
+        .or_else(|| header("X-API-Key"))
+        .or_else(|| {
+            web::Query::<TokenQuery>::from_query(req.query_string())
+                .ok()
+                .and_then(|q| q.into_inner().token)
+        })
+}
+
+// 访问日志中的请求行，token 查询参数替换为 ***，api key 不落到日志里
+pub fn redacted_request_line(req: &ServiceRequest) -> String {
+    let query = req
+        .query_string()
+        .split('&')
+        .map(|pair| match pair.split_once('=') {
+            Some(("token", _)) => "token=***",
+            _ => pair,
+        })
+        .collect::<Vec<_>>()
+        .join("&");
+    let uri = if query.is_empty() {
+        req.path().to_string()
+    } else {
+        format!("{}?{}", req.path(), query)
+    };
+    format!("{} {} {:?}", req.method(), uri, req.version())
+}
+
+// 探活接口不需要认证
+const PUBLIC_PATHS: [&str; 2] = ["/healthz", "/readyz"];
+
+pub async fn authenticate(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let user = match req.app_data::<web::Data<AuthConfig>>() {
+        _ if PUBLIC_PATHS.contains(&req.path()) => LOCAL_USER.to_string(),
+        Some(config) if config.is_enabled() => token(&req)
+            .and_then(|t| config.user(&t).map(str::to_string))
+            .ok_or_else(|| error::ErrorUnauthorized("invalid or missing api key"))?,
+        _ => LOCAL_USER.to_string(),
+    };
+    req.extensions_mut().insert(AuthUser(user));
+    next.call(req).await
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::dev::Service;
+    use actix_web::http::StatusCode;
+    use actix_web::middleware::from_fn;
+    use actix_web::test::{self, TestRequest};
+    use actix_web::App;
+
+    use super::*;
+
+    async fn whoami(user: web::ReqData<AuthUser>) -> String {
+        user.into_inner().0
+    }
+
+    #[actix_web::test]
+    async fn api_keys() {
+        let config = AuthConfig {
+            keys: HashMap::from(
+                [("key1", "user1"), ("key2", "user2"), ("key3", "user3")]
+                    .map(|(k, u)| (k.to_string(), u.to_string())),
+            ),
+        };
+        let app = test::init_service(
+            App::new()
+                .wrap(from_fn(authenticate))
+                .app_data(web::Data::new(config))
+                .route("/whoami", web::get().to(whoami))
+                .route("/healthz", web::get().to(whoami))
+                .route("/readyz", web::get().to(whoami)),
+        )
+        .await;
+
+        for req in [
+            TestRequest::get().uri("/whoami"),
+            TestRequest::get().uri("/whoami?token=wrong"),
+            TestRequest::get()
+                .uri("/whoami")
+                .insert_header(("Authorization", "Bearer wrong")),
+        ] {
+            let err = app.call(req.to_request()).await.err().expect("rejected");
+            assert_eq!(
+                err.as_response_error().status_code(),
+                StatusCode::UNAUTHORIZED
+            );
+        }
+
+        // 三种方式各自对应 key 的用户，探活接口不需要 key
+        for (req, user) in [
+            (
+                TestRequest::get()
+                    .uri("/whoami")
+                    .insert_header(("Authorization", "Bearer key1")),
+                "user1",
+            ),
+            (
+                TestRequest::get()
+                    .uri("/whoami")
+                    .insert_header(("X-API-Key", "key2")),
+                "user2",
+            ),
+            (TestRequest::get().uri("/whoami?token=key3"), "user3"),
+            (TestRequest::get().uri("/healthz"), LOCAL_USER),
+            (TestRequest::get().uri("/readyz"), LOCAL_USER),
+        ] {
+            let body = test::call_and_read_body(&app, req.to_request()).await;
+            assert_eq!(&body[..], user.as_bytes());
+        }
+    }
+
+    #[test]
+    fn request_line_hides_token() {
+        let req = TestRequest::with_uri("/history?symbol=AAPL&token=secret&to=1").to_srv_request();
+        assert_eq!(
+            redacted_request_line(&req),
+            "GET /history?symbol=AAPL&token=***&to=1 HTTP/1.1"
+        );
+        let req = TestRequest::with_uri("/healthz").to_srv_request();
+        assert_eq!(redacted_request_line(&req), "GET /healthz HTTP/1.1");
+    }
+}
@@ -15,6 +15,7 @@ use tracing::{debug, error, info};
 use crate::broker::r#trait::Broker;
 use crate::broker::zen::{Store, Zen};
 use crate::calculate::r#trait::Processor;
+use crate::metrics;
 use tws_rs::client::market_data::historical;
 use tws_rs::client::market_data::historical::{
     cancel_historical_data, historical_data, BarSize, TWSDuration, WhatToShow,
@@ -25,7 +26,8 @@ use zen_core::objects::enums::Freq;
 use zen_core::Bar;
 
 pub(crate) struct IB {
-    pub client: RwLock<Rc<RefCell<Option<ClientRef>>>>,
+    // 断线后由 blocking_process 所在任务清空，使用方取出 Rc 后即释放锁
+    pub client: Rc<RwLock<Option<Rc<ClientRef>>>>,
     pub store: Rc<RefCell<Store>>,
 }
 
@@ -49,32 +51,52 @@ impl Broker for IB {
 impl IB {
     pub fn new() -> Self {
         Self {
-            client: RwLock::new(Rc::new(RefCell::new(None))),
+            client: Rc::new(RwLock::new(None)),
             store: Rc::new(RefCell::new(Store::new())),
         }
     }
 
     pub async fn connect(&self) -> Result<(), Error> {
-        if self.client.read().await.borrow().is_some() {
+        if self.client.read().await.is_some() {
             return Ok(());
         }
 
-        let cref = self.client.write().await;
+        let mut slot = self.client.write().await;
+        // 等待写锁期间可能已被其它任务连上
+        if slot.is_some() {
+            return Ok(());
+        }
         let mut client = Client::new("127.0.0.1:14001", 4322);
         info!("connecting to TWS");
         let client_ref = client.connect().await?;
         info!("connected");
+        metrics::tws_connection(true);
         let store = self.store.clone();
+        let cref = self.client.clone();
         spawn_local(async move {
             let callback = move |m| {
                 store.borrow_mut().onerror(m);
             };
-            client.blocking_process(callback).await?;
-            Ok::<(), Error>(())
+            let rs = client.blocking_process(callback).await;
+            // 连接断开，清空 client 以便下次 connect 重连
+            error!("TWS connection closed: {:?}", rs);
+            metrics::tws_connection(false);
+            cref.write().await.take();
+            rs
         });
-        *cref.borrow_mut() = Some(client_ref);
+        *slot = Some(Rc::new(client_ref));
         Ok(())
     }
+
+    // 未连接时先连接
+    pub async fn client(&self) -> Result<Rc<ClientRef>, Error> {
+        self.connect().await?;
+        self.client
+            .read()
+            .await
+            .clone()
+            .ok_or_else(|| Error::Simple("TWS not connected".to_string()))
+    }
     pub fn freq_map() -> HashMap<String, Freq> {
         HashMap::from([
             ("1D".to_string(), Freq::D),
@@ -95,18 +117,24 @@ impl IB {
         ])
     }
     pub async fn cancel_historical_data(&self, request_id: i32) -> Result<(), Error> {
-        let _ = self.connect().await;
-        let client = { self.client.read().await.clone() };
-        let client = client.borrow();
-        let client = client.as_ref().unwrap();
-        cancel_historical_data(client, request_id).await?;
+        let client = self.client().await?;
+        cancel_historical_data(&client, request_id).await?;
         Ok(())
     }
     pub fn get_czsc(&self, contract: &Contract, freq: Freq) -> Rc<RwLock<Zen>> {
         { self.store.borrow_mut().get_czsc(contract, freq) }.clone()
     }
+    // 停止实时推送，已加载的K线保留，之后的请求超出已有范围时重新订阅；调用方持有 zen 的写锁
+    pub async fn unsubscribe(&self, zen: &mut Zen) -> Result<(), Error> {
+        zen.token.take().map(|t| t.cancel());
+        if zen.realtime {
+            zen.realtime = false;
+            self.cancel_historical_data(zen.request_id).await?;
+        }
+        Ok(())
+    }
     pub async fn try_subscribe(
-        mgr: Rc<RefCell<Self>>,
+        mgr: Rc<Self>,
         contract: &Contract,
         freq: Freq,
         from: i64,
@@ -115,7 +143,7 @@ impl IB {
     ) -> Result<(), Error> {
         let c = contract.clone();
         let subscribe = {
-            let zen = { mgr.borrow().get_czsc(contract, freq) };
+            let zen = mgr.get_czsc(contract, freq);
             let zen = zen.read().await;
             let x = zen.need_subscribe(from, to, replay);
             x
@@ -123,8 +151,7 @@ impl IB {
         if subscribe {
             let (send, recv) = channel::<()>();
             spawn_local(async move {
-                mgr.borrow()
-                    .subscribe_with(&c, freq, from, to, replay, send)
+                mgr.subscribe_with(&c, freq, from, to, replay, send)
                     .await
                     .expect("TODO: panic message");
             });
@@ -143,14 +170,13 @@ impl IB {
         replay: bool,
         sender: Sender<()>,
     ) -> Result<(), Error> {
-        let e = self.connect().await;
-        if e.is_err() {
-            error!("connect error {:?}", e);
-            return e;
-        }
-        let client = { self.client.read().await.clone() };
-        let client = client.borrow();
-        let client = client.as_ref().unwrap();
+        let client = match self.client().await {
+            Ok(client) => client,
+            Err(e) => {
+                error!("connect error {:?}", e);
+                return Err(e);
+            }
+        };
 
         let token = CancellationToken::new();
         let cloned_token = token.clone();
@@ -202,7 +228,7 @@ impl IB {
                 } - from
             );
             let (bars, stream) = historical_data(
-                client,
+                &client,
                 &contract,
                 if !keep_up {
                     Some(OffsetDateTime::from_unix_timestamp(to).unwrap())
@@ -247,9 +273,7 @@ impl IB {
             stream
         };
 
-        {
-            self.store.borrow().process(contract).await
-        };
+        self.store.borrow().process(contract);
         sender.send(()).unwrap();
 
         loop {
@@ -272,7 +296,7 @@ impl IB {
                     if freq == Freq::D {
                         //debug!("update bar {:?}, {:?}", e, e.to_bar(freq));
                     }
-                    self.store.borrow_mut().process(contract).await;
+                    self.store.borrow().process(contract);
                 }
                 _ = cloned_token.cancelled() => {
                     break;
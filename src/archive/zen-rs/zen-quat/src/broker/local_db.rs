@@ -20,11 +20,19 @@ pub struct LocalDB {
 
 impl LocalDB {
     pub fn new() -> Self {
+        Self::with_conn(establish_connection())
+    }
+
+    pub(crate) fn with_conn(conn: InstrumentedSqliteConnection) -> Self {
         Self {
             store: Rc::new(RefCell::new(Store::new())),
-            conn: RefCell::new((establish_connection())),
+            conn: RefCell::new(conn),
         }
     }
+
+    pub fn zen_count(&self) -> usize {
+        self.store.borrow().zen_count()
+    }
 }
 
 impl Broker for LocalDB {
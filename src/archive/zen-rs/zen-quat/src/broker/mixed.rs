@@ -3,24 +3,40 @@ use crate::broker::local_db::LocalDB;
 use crate::broker::r#trait::Broker;
 use crate::broker::zen::Zen;
 use std::cell::RefCell;
+use std::collections::HashMap;
 use std::rc::Rc;
 use tokio::sync::RwLock;
 use tws_rs::contracts::Contract;
 use tws_rs::Error;
 use zen_core::objects::enums::Freq;
 
+// 同一 (是否本地, 合约, 级别) 的 websocket 订阅
+#[derive(Default, Debug)]
+struct Watch {
+    count: usize,
+    // 实时推送由订阅开启，最后一个订阅者退订时停止；
+    // 历史K线、图表元素、估值等请求不计数，它们开启的推送一直保留
+    owned: bool,
+}
+
 pub struct Mixed {
     local_db: LocalDB,
-    pub ib: Rc<RefCell<IB>>,
+    pub ib: Rc<IB>,
+    watchers: RefCell<HashMap<(bool, Contract, Freq), Watch>>,
 }
 
-pub(crate) type MixedBroker = Rc<RefCell<Mixed>>;
+pub(crate) type MixedBroker = Rc<Mixed>;
 
 impl Mixed {
     pub fn new() -> Self {
+        Self::with_local_db(LocalDB::new())
+    }
+
+    pub(crate) fn with_local_db(local_db: LocalDB) -> Self {
         Self {
-            local_db: LocalDB::new(),
-            ib: Rc::new(RefCell::new(IB::new())),
+            local_db,
+            ib: Rc::new(IB::new()),
+            watchers: Default::default(),
         }
     }
 
@@ -45,11 +61,158 @@ impl Mixed {
         }
     }
 
+    // websocket 订阅计数加一并订阅 [from, to] 的数据，与 unwatch 成对调用，出错时也要 unwatch
+    pub async fn watch(
+        &self,
+        local: bool,
+        contract: &Contract,
+        freq: Freq,
+        from: i64,
+        to: i64,
+    ) -> Result<(), Error> {
+        let key = (local, contract.clone(), freq);
+        let first = {
+            let mut watchers = self.watchers.borrow_mut();
+            let watch = watchers.entry(key.clone()).or_default();
+            watch.count += 1;
+            watch.count == 1
+        };
+        if first && !local {
+            // 进行中的 unwatch 持有写锁，等它取消完再看推送是否还在
+            let realtime = self.ib.get_czsc(contract, freq).read().await.realtime;
+            if let Some(watch) = self.watchers.borrow_mut().get_mut(&key) {
+                watch.owned |= !realtime;
+            }
+        }
+        // 先计数再订阅，推送被交错的 unwatch 取消时在这里重新订阅
+        self.try_subscribe(local, contract, freq, from, to, 0, false)
+            .await
+    }
+
+    // 最后一个订阅者退订时停止由订阅开启的 IB 实时推送，本地数据没有推送
+    pub async fn unwatch(&self, local: bool, contract: &Contract, freq: Freq) -> Result<(), Error> {
+        let key = (local, contract.clone(), freq);
+        {
+            let mut watchers = self.watchers.borrow_mut();
+            let Some(watch) = watchers.get_mut(&key).filter(|w| w.count > 0) else {
+                return Ok(());
+            };
+            watch.count -= 1;
+            if watch.count > 0 {
+                return Ok(());
+            }
+            if local || !watch.owned {
+                watchers.remove(&key);
+                return Ok(());
+            }
+        }
+        let zen = self.ib.get_czsc(contract, freq);
+        let mut zen = zen.write().await;
+        // 等锁期间可能又有订阅者，由它继续使用推送
+        let idle =
+            |watchers: &HashMap<_, Watch>| watchers.get(&key).map_or(false, |w| w.count == 0);
+        let rs = if idle(&self.watchers.borrow()) {
+            self.ib.unsubscribe(&mut zen).await
+        } else {
+            Ok(())
+        };
+        let mut watchers = self.watchers.borrow_mut();
+        if idle(&watchers) {
+            watchers.remove(&key);
+        }
+        rs
+    }
+
+    // 各数据源 Store 中的 Zen 实例数
+    pub fn zen_counts(&self) -> [(&'static str, usize); 2] {
+        [
+            ("ib", self.ib.store.borrow().zen_count()),
+            ("local", self.local_db.zen_count()),
+        ]
+    }
+
     pub fn get_czsc(&self, local: bool, contract: &Contract, freq: Freq) -> Rc<RwLock<Zen>> {
         if local {
             return self.local_db.get_czsc(contract, freq);
         } else {
-            return self.ib.borrow().get_czsc(contract, freq);
+            return self.ib.get_czsc(contract, freq);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio_util::sync::CancellationToken;
+
+    use super::*;
+    use crate::broker::zen::tests::bar;
+    use crate::db::connect;
+
+    // IB 中已加载 10 根日线的 Zen，realtime 表示订阅前是否已有实时推送
+    async fn mixed(realtime: bool) -> (Mixed, Contract, CancellationToken) {
+        let mixed = Mixed::with_local_db(LocalDB::with_conn(connect(":memory:")));
+        let contract = Contract::auto_stock("AAPL");
+        let token = CancellationToken::new();
+        {
+            let zen = mixed.ib.get_czsc(&contract, Freq::D);
+            let mut zen = zen.write().await;
+            for i in 0..10 {
+                zen.update(bar(i, 10.0 + i as f32));
+            }
+            zen.subscribed = true;
+            zen.realtime = realtime;
+            zen.token = Some(token.clone());
         }
+        (mixed, contract, token)
+    }
+
+    // 已加载的范围，订阅时不需要连接 TWS
+    fn loaded() -> (i64, i64) {
+        let ts = |i| bar(i, 0.0).dt.unix_timestamp();
+        (ts(0), ts(9))
+    }
+
+    #[actix_web::test]
+    async fn keep_stream_opened_by_other_requests() {
+        let (mixed, contract, token) = mixed(true).await;
+        let (from, to) = loaded();
+        for _ in 0..2 {
+            mixed
+                .watch(false, &contract, Freq::D, from, to)
+                .await
+                .unwrap();
+        }
+        for _ in 0..2 {
+            mixed.unwatch(false, &contract, Freq::D).await.unwrap();
+        }
+        // 推送由历史K线等请求开启，最后一个订阅者退订后仍在
+        assert!(mixed.watchers.borrow().is_empty());
+        assert!(!token.is_cancelled());
+        assert!(mixed.ib.get_czsc(&contract, Freq::D).read().await.realtime);
+    }
+
+    #[actix_web::test]
+    async fn stop_owned_stream_on_last_unwatch() {
+        let (mixed, contract, token) = mixed(false).await;
+        let (from, to) = loaded();
+        for _ in 0..2 {
+            mixed
+                .watch(false, &contract, Freq::D, from, to)
+                .await
+                .unwrap();
+        }
+        let key = (false, contract.clone(), Freq::D);
+        assert!(mixed.watchers.borrow()[&key].owned);
+
+        mixed.unwatch(false, &contract, Freq::D).await.unwrap();
+        assert_eq!(mixed.watchers.borrow()[&key].count, 1);
+        assert!(!token.is_cancelled());
+        mixed.unwatch(false, &contract, Freq::D).await.unwrap();
+        assert!(mixed.watchers.borrow().is_empty());
+        assert!(token.is_cancelled());
+
+        // 多余的退订不影响计数
+        mixed.unwatch(false, &contract, Freq::D).await.unwrap();
+        assert!(mixed.watchers.borrow().is_empty());
     }
 }
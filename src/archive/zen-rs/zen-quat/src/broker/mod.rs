@@ -3,4 +3,5 @@ mod local_db;
 pub mod mixed;
 mod moomoo;
 mod r#trait;
+pub mod shared;
 pub mod zen;
@@ -0,0 +1,112 @@
+use std::future::Future;
+use std::rc::Rc;
+use std::thread;
+
+use futures_util::future::LocalBoxFuture;
+use futures_util::FutureExt;
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::{spawn_local, LocalSet};
+use tracing::info;
+use tws_rs::Error;
+
+use crate::broker::mixed::{Mixed, MixedBroker};
+
+type Job = Box<dyn FnOnce(MixedBroker) -> LocalBoxFuture<'static, ()> + Send>;
+
+// Mixed 及其中的 Zen 只能在一个线程上使用，放到单独的 broker 线程，
+// 所有 worker 共用同一个 IB 连接和 Store。
+// handler 通过 run 把任务投递过去，各任务在该线程上并发执行，只把 Send 的结果带回
+#[derive(Clone)]
+pub struct SharedBroker {
+    jobs: mpsc::UnboundedSender<Job>,
+}
+
+impl SharedBroker {
+    pub fn start() -> std::io::Result<Self> {
+        Self::start_with(Mixed::new)
+    }
+
+    // make 在 broker 线程上创建 Mixed
+    pub(crate) fn start_with<M>(make: M) -> std::io::Result<Self>
+    where
+        M: FnOnce() -> Mixed + Send + 'static,
+    {
+        let (jobs, mut rx) = mpsc::unbounded_channel::<Job>();
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        thread::Builder::new()
+            .name("broker".to_string())
+            .spawn(move || {
+                let localset = LocalSet::new();
+                localset.block_on(&rt, async move {
+                    let broker = Rc::new(make());
+                    while let Some(job) = rx.recv().await {
+                        spawn_local(job(broker.clone()));
+                    }
+                    info!("broker stopped");
+                });
+            })?;
+        Ok(Self { jobs })
+    }
+
+    // 立即投递任务，返回的 future 不借用 self；任务 panic 或 broker 线程退出时返回错误
+    pub fn run<F, Fut, R>(&self, f: F) -> impl Future<Output = Result<R, Error>> + 'static
+    where
+        F: FnOnce(MixedBroker) -> Fut + Send + 'static,
+        Fut: Future<Output = R> + 'static,
+        R: Send + 'static,
+    {
+        let (tx, rx) = oneshot::channel();
+        let job: Job = Box::new(move |broker| {
+            async move {
+                let _ = tx.send(f(broker).await);
+            }
+            .boxed_local()
+        });
+        let sent = self.jobs.send(job).is_ok();
+        async move {
+            if !sent {
+                return Err(Error::Simple("broker stopped".to_string()));
+            }
+            rx.await
+                .map_err(|_| Error::Simple("broker task failed".to_string()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::broker::local_db::LocalDB;
+    use crate::db::connect;
+
+    #[test]
+    fn jobs_from_threads_survive_panics() {
+        let broker = SharedBroker::start_with(|| {
+            Mixed::with_local_db(LocalDB::with_conn(connect(":memory:")))
+        })
+        .unwrap();
+        let handles: Vec<_> = (0..2)
+            .map(|i| {
+                let broker = broker.clone();
+                thread::spawn(move || {
+                    let rt = tokio::runtime::Builder::new_current_thread()
+                        .build()
+                        .unwrap();
+                    rt.block_on(async {
+                        let failed = broker.run::<_, _, ()>(|_| async { panic!("job failed") });
+                        assert!(failed.await.is_err());
+                        // panic 只影响该任务，之后的任务照常执行
+                        broker
+                            .run(move |broker| async move { (i, broker.zen_counts()[0].1) })
+                            .await
+                            .unwrap()
+                    })
+                })
+            })
+            .collect();
+        let results: Vec<_> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+        assert_eq!(results, vec![(0, 0), (1, 0)]);
+    }
+}
@@ -2,6 +2,7 @@ use crate::calculate::beichi::buy_sell_point::BuySellPoint;
 use crate::calculate::others;
 use crate::calculate::others::sma_tracker::SMATracker;
 use crate::calculate::r#trait::Processor;
+use crate::metrics;
 use crate::utils::notify::Notify;
 use std::collections::HashMap;
 use std::rc::Rc;
@@ -27,8 +28,14 @@ pub(crate) struct Zen {
     pub(crate) token: Option<CancellationToken>,
     pub(crate) request_id: i32,
     pub(crate) beichi_processor: BuySellPoint,
+    // 历史信号，用于图表的 timescale marks
+    pub(crate) signal_history: Vec<Signal>,
 }
 
+const MAX_SIGNAL_HISTORY: usize = 1000;
+// 每个 Zen 跟踪的均线周期
+pub(crate) const SMA_PERIODS: [isize; 5] = [15, 30, 60, 120, 200];
+
 impl Drop for Zen {
     fn drop(&mut self) {
         self.token.take().map(|t| t.cancel());
@@ -47,10 +54,9 @@ impl Zen {
             token: None,
             request_id: 0,
             beichi_processor: BuySellPoint::new(),
+            signal_history: vec![],
         };
-        res.czsc
-            .cache
-            .insert(SMATracker::new(vec![15, 30, 60, 120, 200]));
+        res.czsc.cache.insert(SMATracker::new(SMA_PERIODS.to_vec()));
         res
     }
 
@@ -68,8 +74,9 @@ impl Zen {
         self.token = None;
         self.czsc
             .cache
-            .insert(SMATracker::new(vec![15, 30, 60, 120, 200]));
+            .insert(SMATracker::new(SMA_PERIODS.to_vec()));
         self.beichi_processor.beichi_tracker.clear();
+        self.signal_history.clear();
     }
 
     pub fn update(&mut self, bar: Bar) -> Vec<Signal> {
@@ -77,6 +84,16 @@ impl Zen {
         //let signals = self.bc_processor.process(&self.czsc, is_new);
         let signals = self.beichi_processor.process(&mut self.czsc, is_new, None);
         others::sma_tracker::process(&mut self.czsc, is_new, None);
+        for signal in &signals {
+            let mut signal = signal.clone();
+            signal.dt = signal.dt.or(self.czsc.end());
+            self.signal_history.push(signal);
+        }
+        if self.signal_history.len() > MAX_SIGNAL_HISTORY {
+            let n = self.signal_history.len() - MAX_SIGNAL_HISTORY;
+            self.signal_history.drain(0..n);
+        }
+        metrics::bar_processed(signals.len());
         return signals;
     }
     pub fn need_subscribe(&self, from: i64, to: i64, replay: bool) -> bool {
@@ -124,11 +141,19 @@ impl Store {
         debug!("onerror {:?}", rsp);
         match rsp.fields[3].as_str() {
             "1100"| "2103" | "2106" => {
+                if rsp.fields[3] == "1100" {
+                    metrics::tws_connection(false);
+                }
                 self.store.clear();
             }
+            // 与 TWS 的连接已恢复
+            "1101" | "1102" => metrics::tws_connection(true),
             _ => {}
         }
     }
+    pub fn zen_count(&self) -> usize {
+        self.store.len()
+    }
     pub fn get_czsc(&mut self, sym: &Contract, freq: Freq) -> Rc<RwLock<Zen>> {
         self.store
             .entry((sym.clone(), freq))
@@ -142,7 +167,8 @@ impl Store {
             .clone()
     }
 
-    pub async fn process(&self, sym: &Contract) {
+    // 同步执行，调用方不会在 await 期间持有 Store 的借用
+    pub fn process(&self, sym: &Contract) {
         let mut signals = vec![];
         for x in &self.signal_tracker {
             if x.0 .0.symbol == sym.symbol {
@@ -168,3 +194,60 @@ impl Store {
 
     pub async fn offline_process(&self, sym: &Contract) {}
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use time::Duration as TimeDuration;
+
+    use super::*;
+
+    // 2024-01-01 起每天一根日线
+    pub(crate) fn bar(i: usize, price: f32) -> Bar {
+        Bar {
+            id: i,
+            dt: OffsetDateTime::from_unix_timestamp(1_704_092_400).unwrap()
+                + TimeDuration::days(i as i64),
+            freq: Freq::D,
+            open: price,
+            close: price,
+            high: price + 0.2,
+            low: price - 0.2,
+            vol: 100.0,
+            amount: 0.0,
+            cache: Default::default(),
+            macd_4_9_9: (0.0, 0.0, 0.0),
+        }
+    }
+
+    // 按拐点线性插值，每段 legs 根日线
+    pub(crate) fn zigzag(pivots: &[f32], legs: usize) -> Vec<f32> {
+        let mut prices = vec![pivots[0]];
+        for w in pivots.windows(2) {
+            for i in 1..=legs {
+                prices.push(w[0] + (w[1] - w[0]) * i as f32 / legs as f32);
+            }
+        }
+        prices
+    }
+
+    // 两个中枢之后新低再反弹，按 8 根日线一段插值后有多个买卖点
+    pub(crate) fn pivots() -> Vec<f32> {
+        vec![
+            30.0, 20.0, 24.0, 21.0, 25.0, 22.0, 26.0, 12.0, 16.0, 13.0, 17.0, 14.0, 18.0, 11.0,
+            15.0, 13.0, 19.0,
+        ]
+    }
+
+    // 用给定的收盘价序列跑出的日线 Zen
+    pub(crate) fn zen(prices: &[f32]) -> Zen {
+        let mut zen = Zen::new(
+            Contract::auto_stock("AAPL"),
+            Freq::D,
+            Settings::new().unwrap(),
+        );
+        for (i, price) in prices.iter().enumerate() {
+            zen.update(bar(i, *price));
+        }
+        zen
+    }
+}
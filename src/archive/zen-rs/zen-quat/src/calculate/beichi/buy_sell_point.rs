@@ -6,13 +6,13 @@ use std::rc::Rc;
 use time::macros::offset;
 use time::OffsetDateTime;
 use tracing::debug;
-use zen_core::objects::chan::NewBar;
+use zen_core::objects::chan::{NewBar, BI};
 use zen_core::objects::enums::Direction;
 use zen_core::objects::trade::{Signal, ZS};
 use zen_core::{Bar, CZSC};
 
 #[derive(Eq, PartialEq, Serialize, Debug, Clone)]
-enum PointType {
+pub(crate) enum PointType {
     None,
     FirstBuy,
     SecondBuy,
@@ -23,7 +23,7 @@ enum PointType {
 }
 
 #[derive(Serialize, Debug, Clone, Eq, PartialEq)]
-enum BeichiType {
+pub(crate) enum BeichiType {
     Area,
     Diff,
     ZsZs,
@@ -32,27 +32,81 @@ enum BeichiType {
 
 #[derive(Serialize, Debug, Clone)]
 pub struct ZSInfo {
-    left: i64,
+    pub(crate) left: i64,
     pub(crate) right: i64,
-    high: f32,
-    low: f32,
+    pub(crate) high: f32,
+    pub(crate) low: f32,
     bi_count: u32,
 }
 
 #[derive(Serialize, Debug, Clone)]
 pub struct BSPoint {
-    direction: Direction,
-    r#type: PointType,
-    bc_type: Vec<BeichiType>,
+    pub(crate) direction: Direction,
+    pub(crate) r#type: PointType,
+    pub(crate) bc_type: Vec<BeichiType>,
     pub(crate) zs2: ZSInfo,
-    zs1: Option<ZSInfo>,
-    fake_bi: bool,
+    pub(crate) zs1: Option<ZSInfo>,
+    pub(crate) fake_bi: bool,
     macd_a_dt: i64,
     macd_a_val: f32,
     pub(crate) macd_b_dt: i64,
     macd_b_val: f32,
-    dt: i64,
-    price: f32,
+    pub(crate) dt: i64,
+    pub(crate) price: f32,
+}
+
+impl PointType {
+    // 图表上的简写，如 1B、3S
+    pub(crate) fn label(&self) -> &'static str {
+        match self {
+            PointType::None => "BC",
+            PointType::FirstBuy => "1B",
+            PointType::SecondBuy => "2B",
+            PointType::ThirdBuy => "3B",
+            PointType::FirstSell => "1S",
+            PointType::SecondSell => "2S",
+            PointType::ThirdSell => "3S",
+        }
+    }
+}
+
+impl BeichiType {
+    pub(crate) fn label(&self) -> &'static str {
+        match self {
+            BeichiType::Area => "MACD面积背驰",
+            BeichiType::Diff => "DIFF背驰",
+            BeichiType::ZsZs => "中枢背驰",
+            BeichiType::ZsLzs => "类中枢背驰",
+        }
+    }
+}
+
+// 按笔依次划分中枢：进入段之后连续三笔有重叠即成中枢，之后与中枢区间重叠的笔继续延伸，
+// 离开中枢的笔作为下一个中枢的进入段
+pub(crate) fn zs_list(bis: &[BI]) -> Vec<ZSInfo> {
+    let mut result = vec![];
+    let mut i = 1;
+    while i + 3 <= bis.len() {
+        let zs = ZS::new(&bis[i - 1..i + 3]);
+        let (high, low) = (zs.zg(), zs.zd());
+        if high < low {
+            i += 1;
+            continue;
+        }
+        let mut end = i + 3;
+        while end < bis.len() && bis[end].low() <= high && bis[end].high() >= low {
+            end += 1;
+        }
+        result.push(ZSInfo {
+            left: bis[i].fx_a.dt.unix_timestamp(),
+            right: bis[end - 1].fx_b.dt.unix_timestamp(),
+            high,
+            low,
+            bi_count: (end - i) as u32,
+        });
+        i = end + 1;
+    }
+    result
 }
 
 pub struct BuySellPoint {
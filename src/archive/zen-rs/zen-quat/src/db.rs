@@ -1,10 +1,33 @@
-use diesel::{Connection, SqliteConnection};
+use diesel::connection::SimpleConnection;
+use diesel::sql_types::Integer;
+use diesel::{sql_function, Connection, SqliteConnection};
 use diesel_tracing::sqlite::InstrumentedSqliteConnection;
 
 pub(crate) mod models;
 
+// migrations 目录下各版本的 up.sql，均为 CREATE ... IF NOT EXISTS
+const MIGRATIONS: [&str; 3] = [
+    include_str!("../migrations/2024-06-01-000000_chart_storage/up.sql"),
+    include_str!("../migrations/2024-06-02-000000_dashboards/up.sql"),
+    include_str!("../migrations/2024-06-03-000000_watchlists/up.sql"),
+];
+
+// 当前连接上一次 INSERT 的 rowid
+sql_function! {
+    fn last_insert_rowid() -> Integer;
+}
+
 pub fn establish_connection() -> InstrumentedSqliteConnection {
-    let database_url = "./tradingview.db";
-    InstrumentedSqliteConnection::establish(&database_url)
-        .unwrap_or_else(|e| panic!("Error connecting to {} {}", database_url, e))
+    connect("./tradingview.db")
+}
+
+pub(crate) fn connect(database_url: &str) -> InstrumentedSqliteConnection {
+    let mut conn = InstrumentedSqliteConnection::establish(database_url)
+        .unwrap_or_else(|e| panic!("Error connecting to {} {}", database_url, e));
+    // 新增的表不在原有数据库里，启动时按需创建
+    for migration in MIGRATIONS {
+        conn.batch_execute(migration)
+            .unwrap_or_else(|e| panic!("Error creating tables {}", e));
+    }
+    conn
 }
@@ -3,7 +3,7 @@
 #![allow(unused)]
 #![allow(clippy::all)]
 
-use diesel::{Identifiable, Queryable, Selectable};
+use diesel::{Identifiable, Insertable, Queryable, Selectable};
 
 #[derive(Queryable, Debug, Clone, Selectable, Identifiable)]
 #[diesel(primary_key(exchange, symbol))]
@@ -33,3 +33,88 @@ pub struct BarHistory {
     pub close: Option<f32>,
     pub volume: Option<i32>,
 }
+
+#[derive(Queryable, Debug, Selectable, Identifiable)]
+#[diesel(table_name = crate::schema::charts)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct Chart {
+    pub id: i32,
+    pub client: String,
+    pub user: String,
+    pub name: String,
+    pub symbol: String,
+    pub resolution: String,
+    pub content: String,
+    pub timestamp: i64,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = crate::schema::charts)]
+pub struct NewChart<'a> {
+    pub client: &'a str,
+    pub user: &'a str,
+    pub name: &'a str,
+    pub symbol: &'a str,
+    pub resolution: &'a str,
+    pub content: &'a str,
+    pub timestamp: i64,
+}
+
+#[derive(Queryable, Insertable, Debug, Selectable)]
+#[diesel(table_name = crate::schema::study_templates)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct StudyTemplate {
+    pub client: String,
+    pub user: String,
+    pub name: String,
+    pub content: String,
+}
+
+#[derive(Queryable, Insertable, Debug, Selectable)]
+#[diesel(table_name = crate::schema::drawing_templates)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct DrawingTemplate {
+    pub client: String,
+    pub user: String,
+    pub tool: String,
+    pub name: String,
+    pub content: String,
+}
+
+#[derive(Queryable, Debug, Selectable, Identifiable)]
+#[diesel(table_name = crate::schema::dashboards)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct Dashboard {
+    pub id: i32,
+    pub name: String,
+    pub version: i32,
+    pub updated: i64,
+}
+
+#[derive(Insertable, Debug)]
+#[diesel(table_name = crate::schema::dashboards)]
+pub struct NewDashboard<'a> {
+    pub name: &'a str,
+    pub version: i32,
+    pub updated: i64,
+}
+
+#[derive(Queryable, Insertable, Debug, Selectable)]
+#[diesel(table_name = crate::schema::dashboard_versions)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct DashboardVersion {
+    pub dashboard_id: i32,
+    pub version: i32,
+    pub content: String,
+    pub created: i64,
+}
+
+#[derive(Queryable, Insertable, Debug, Selectable)]
+#[diesel(table_name = crate::schema::watchlists)]
+#[diesel(check_for_backend(diesel::sqlite::Sqlite))]
+pub struct Watchlist {
+    pub user: String,
+    pub name: String,
+    pub symbols: String,
+    pub updated: i64,
+}
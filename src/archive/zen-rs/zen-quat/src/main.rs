@@ -5,18 +5,28 @@ use std::io::Write;
 use clap::{Parser, Subcommand};
 use log::LevelFilter;
 use notify_rust::{get_bundle_identifier_or_default, set_application};
-use tracing::debug;
+use tracing::{debug, error, info, Level};
+use tracing_subscriber::filter::Targets;
 use tracing_subscriber::fmt::time::ChronoLocal;
-use tracing_subscriber::EnvFilter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::{EnvFilter, Layer};
 
+use crate::api::storage::reassign_user;
+use crate::auth::{AuthConfig, LOCAL_USER};
+use crate::db::establish_connection;
+use crate::metrics::QueryTimer;
+use crate::pkg::chart::{ChartFormat, ChartOptions};
 use crate::pkg::load_db::load_local_db;
 use crate::pkg::screenshot::screenshot;
-use crate::serve::serve;
+use crate::pkg::stock_scan::{scan_cli, ScanRequest, DEFAULT_PAGE_SIZE};
+use crate::serve::{serve, ServeConfig};
 
 mod api;
+mod auth;
 mod broker;
 mod calculate;
 mod db;
+mod metrics;
 pub(crate) mod pkg;
 mod schema;
 mod serve;
@@ -31,7 +41,28 @@ struct Cli {
 
 #[derive(Subcommand)]
 enum Commands {
-    Serve {},
+    Serve {
+        #[arg(
+        short,
+        long,
+        value_name = "ADDR",
+        default_value_t = String::from("127.0.0.1:8080")
+        )]
+        bind: String,
+        // 可多次指定，* 表示任意来源
+        #[arg(
+        long,
+        value_name = "ORIGIN",
+        default_values_t = [String::from("http://localhost:3000")]
+        )]
+        cors_origin: Vec<String>,
+        // 每行 `用户名 key`，不指定时只能绑定本机地址
+        #[arg(long, value_name = "FILE")]
+        api_keys_file: Option<String>,
+        // 不指定时按 CPU 核数
+        #[arg(long, value_name = "N")]
+        workers: Option<usize>,
+    },
     Screenshot {
         #[arg(
             short,
@@ -47,6 +78,17 @@ enum Commands {
         default_value_t = String::from("./data")
         )]
         outdir: String,
+        #[arg(
+        short,
+        long,
+        value_name = "RESOLUTION",
+        default_value_t = String::from("1D")
+        )]
+        resolution: String,
+        #[arg(short, long, value_enum, default_value_t = ChartFormat::Png)]
+        format: ChartFormat,
+        #[arg(long, value_name = "BARS", default_value_t = 250)]
+        bars: usize,
     },
     UpdateLocal {
         #[arg(
@@ -67,6 +109,33 @@ enum Commands {
         #[arg(short, long, value_name = "VERIFY", default_value_t = false)]
         check_only: bool,
     },
+    Scan {
+        #[arg(short, long, value_name = "WATCHLIST")]
+        watchlist: Option<String>,
+        #[arg(long, value_name = "SCREENER")]
+        screener: Option<String>,
+        #[arg(short, long, value_name = "EXCHANGE")]
+        exchange: Option<String>,
+        #[arg(
+        short,
+        long,
+        value_name = "RESOLUTION",
+        default_value_t = String::from("1D")
+        )]
+        resolution: String,
+        #[arg(short, long, value_name = "PAGE", default_value_t = 1)]
+        page: usize,
+        #[arg(long, value_name = "PAGE_SIZE", default_value_t = DEFAULT_PAGE_SIZE)]
+        page_size: usize,
+    },
+    // 把图表库 user_id 下保存的图表和模板转给认证用户
+    MigrateStorage {
+        #[arg(long, value_name = "USER_ID", default_value_t = String::from("zen_user_id"))]
+        from_user: String,
+        // 未启用认证时为 local
+        #[arg(long, value_name = "USER", default_value_t = String::from(LOCAL_USER))]
+        to_user: String,
+    },
 }
 
 fn main() {
@@ -89,12 +158,18 @@ fn main() {
         })
         .init();
 
+    // QueryTimer 不受 RUST_LOG 影响，始终统计 sqlite 查询耗时
     tracing::subscriber::set_global_default(
-        tracing_subscriber::fmt()
-            .with_line_number(true)
-            .with_timer(ChronoLocal::rfc_3339())
-            .with_env_filter(EnvFilter::from_default_env())
-            .finish(),
+        tracing_subscriber::registry()
+            .with(
+                tracing_subscriber::fmt::layer()
+                    .with_line_number(true)
+                    .with_timer(ChronoLocal::rfc_3339())
+                    .with_filter(EnvFilter::from_default_env()),
+            )
+            .with(
+                QueryTimer.with_filter(Targets::new().with_target("diesel_tracing", Level::TRACE)),
+            ),
     )
     .expect("logger init");
 
@@ -103,9 +178,20 @@ fn main() {
     // You can check for the existence of subcommands, and if found use their
     // matches just as you would the top level cmd
     match &cli.command {
-        Some(Commands::Screenshot { watchlist, outdir }) => {
+        Some(Commands::Screenshot {
+            watchlist,
+            outdir,
+            resolution,
+            format,
+            bars,
+        }) => {
             debug!("watchlist path {:?}", watchlist);
-            let res = screenshot(watchlist.clone(), outdir.clone());
+            let opts = ChartOptions {
+                format: *format,
+                bars: *bars,
+                ..Default::default()
+            };
+            let res = screenshot(watchlist.clone(), outdir.clone(), resolution.clone(), opts);
             if res.is_err() {
                 debug!("err {:?}", res.err())
             }
@@ -120,12 +206,59 @@ fn main() {
                 debug!("err {:?}", res.err())
             }
         }
-        Some(Commands::Serve {}) => {
-            let res = serve();
+        Some(Commands::Scan {
+            watchlist,
+            screener,
+            exchange,
+            resolution,
+            page,
+            page_size,
+        }) => {
+            let res = scan_cli(ScanRequest {
+                watchlist: watchlist.clone(),
+                screener: screener.clone(),
+                exchange: exchange.clone(),
+                resolution: resolution.clone(),
+                page: *page,
+                page_size: *page_size,
+            });
             if res.is_err() {
                 debug!("err {:?}", res.err())
             }
         }
+        Some(Commands::Serve {
+            bind,
+            cors_origin,
+            api_keys_file,
+            workers,
+        }) => {
+            let auth = match api_keys_file {
+                Some(path) => match AuthConfig::from_file(path) {
+                    Ok(auth) => auth,
+                    Err(e) => {
+                        error!("err {:?}", e);
+                        std::process::exit(1);
+                    }
+                },
+                None => AuthConfig::default(),
+            };
+            // 端口被占用等启动失败时以非零状态退出，便于进程管理器发现
+            if let Err(e) = serve(ServeConfig {
+                bind: bind.clone(),
+                cors_origins: cors_origin.clone(),
+                auth,
+                workers: *workers,
+            }) {
+                error!("serve: {:?}", e);
+                std::process::exit(1);
+            }
+        }
+        Some(Commands::MigrateStorage { from_user, to_user }) => {
+            match reassign_user(&mut establish_connection(), from_user, to_user) {
+                Ok(n) => info!("moved {} rows from {} to {}", n, from_user, to_user),
+                Err(e) => error!("err {:?}", e),
+            }
+        }
         None => {}
     }
 }
@@ -0,0 +1,263 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Instant;
+
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::Error;
+use tracing::span::{Attributes, Id};
+use tracing::Subscriber;
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+// Prometheus 文本格式的指标，进程内全局计数，/metrics 时渲染
+
+// 秒
+const BUCKETS: [f64; 11] = [
+    0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 10.0,
+];
+
+struct Histogram {
+    counts: [u64; BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn new() -> Self {
+        Self {
+            counts: [0; BUCKETS.len()],
+            sum: 0.0,
+            count: 0,
+        }
+    }
+
+    fn observe(&mut self, secs: f64) {
+        for (i, le) in BUCKETS.iter().enumerate() {
+            if secs <= *le {
+                self.counts[i] += 1;
+            }
+        }
+        self.sum += secs;
+        self.count += 1;
+    }
+
+    fn render(&self, out: &mut String, name: &str, labels: &str) {
+        let sep = if labels.is_empty() { "" } else { "," };
+        for (le, count) in BUCKETS.iter().zip(self.counts) {
+            let _ = writeln!(out, "{name}_bucket{{{labels}{sep}le=\"{le}\"}} {count}");
+        }
+        let _ = writeln!(
+            out,
+            "{name}_bucket{{{labels}{sep}le=\"+Inf\"}} {}",
+            self.count
+        );
+        let _ = writeln!(out, "{name}_sum{{{labels}}} {}", self.sum);
+        let _ = writeln!(out, "{name}_count{{{labels}}} {}", self.count);
+    }
+}
+
+// (method, route, status)
+static REQUESTS: Mutex<BTreeMap<(String, String, u16), Histogram>> = Mutex::new(BTreeMap::new());
+// 按 diesel-tracing 的 span 名区分，如 load、execute
+static QUERIES: Mutex<BTreeMap<String, Histogram>> = Mutex::new(BTreeMap::new());
+static BARS_PROCESSED: AtomicU64 = AtomicU64::new(0);
+static SIGNALS_EMITTED: AtomicU64 = AtomicU64::new(0);
+static TWS_CONNECTED: AtomicBool = AtomicBool::new(false);
+static TWS_CONNECTS: AtomicU64 = AtomicU64::new(0);
+
+pub fn bar_processed(signals: usize) {
+    BARS_PROCESSED.fetch_add(1, Ordering::Relaxed);
+    SIGNALS_EMITTED.fetch_add(signals as u64, Ordering::Relaxed);
+}
+
+// 连接成功或断线恢复时 connected 为 true
+pub fn tws_connection(connected: bool) {
+    if connected && !TWS_CONNECTED.swap(true, Ordering::Relaxed) {
+        TWS_CONNECTS.fetch_add(1, Ordering::Relaxed);
+    }
+    if !connected {
+        TWS_CONNECTED.store(false, Ordering::Relaxed);
+    }
+}
+
+pub fn tws_connected() -> bool {
+    TWS_CONNECTED.load(Ordering::Relaxed)
+}
+
+// 记录每个路由的请求耗时，路由取匹配的模式，避免路径参数撑爆标签；
+// 中间件返回的错误（如认证失败的 401）也计入，状态码取错误对应的响应
+pub async fn track(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let start = Instant::now();
+    let method = req.method().to_string();
+    let route = req
+        .match_pattern()
+        .unwrap_or_else(|| "unmatched".to_string());
+    let res = next.call(req).await;
+    let status = match &res {
+        Ok(res) => res.status(),
+        Err(e) => e.as_response_error().status_code(),
+    };
+    REQUESTS
+        .lock()
+        .unwrap()
+        .entry((method, route, status.as_u16()))
+        .or_insert_with(Histogram::new)
+        .observe(start.elapsed().as_secs_f64());
+    res
+}
+
+// 统计 diesel-tracing 的 span 耗时作为 sqlite 查询耗时
+pub struct QueryTimer;
+
+struct SpanStart(Instant);
+
+impl<S> Layer<S> for QueryTimer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, _: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            span.extensions_mut().insert(SpanStart(Instant::now()));
+        }
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(&id) else {
+            return;
+        };
+        let Some(start) = span.extensions().get::<SpanStart>().map(|s| s.0) else {
+            return;
+        };
+        QUERIES
+            .lock()
+            .unwrap()
+            .entry(span.name().to_string())
+            .or_insert_with(Histogram::new)
+            .observe(start.elapsed().as_secs_f64());
+    }
+}
+
+fn escape(v: &str) -> String {
+    v.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+// zens 为各数据源当前的 Zen 实例数
+pub fn render(zens: &[(&str, usize)]) -> String {
+    let mut out = String::new();
+
+    out.push_str("# HELP zen_http_request_duration_seconds HTTP request latency by route.\n");
+    out.push_str("# TYPE zen_http_request_duration_seconds histogram\n");
+    for ((method, route, status), h) in REQUESTS.lock().unwrap().iter() {
+        let labels = format!(
+            "method=\"{}\",route=\"{}\",status=\"{}\"",
+            method,
+            escape(route),
+            status
+        );
+        h.render(&mut out, "zen_http_request_duration_seconds", &labels);
+    }
+
+    out.push_str("# HELP zen_sqlite_query_duration_seconds Sqlite query latency by operation.\n");
+    out.push_str("# TYPE zen_sqlite_query_duration_seconds histogram\n");
+    for (op, h) in QUERIES.lock().unwrap().iter() {
+        let labels = format!("op=\"{}\"", escape(op));
+        h.render(&mut out, "zen_sqlite_query_duration_seconds", &labels);
+    }
+
+    out.push_str("# HELP zen_store_zen_instances Live Zen instances in the store.\n");
+    out.push_str("# TYPE zen_store_zen_instances gauge\n");
+    for (source, n) in zens {
+        let _ = writeln!(
+            out,
+            "zen_store_zen_instances{{source=\"{}\"}} {}",
+            source, n
+        );
+    }
+
+    let counters = [
+        (
+            "zen_bars_processed_total",
+            "Bars fed into Zen.",
+            BARS_PROCESSED.load(Ordering::Relaxed),
+        ),
+        (
+            "zen_signals_emitted_total",
+            "Signals emitted by Zen.",
+            SIGNALS_EMITTED.load(Ordering::Relaxed),
+        ),
+        (
+            "zen_tws_reconnects_total",
+            "TWS reconnects after the first connection.",
+            TWS_CONNECTS.load(Ordering::Relaxed).saturating_sub(1),
+        ),
+    ];
+    for (name, help, value) in counters {
+        let _ = writeln!(
+            out,
+            "# HELP {} {}\n# TYPE {} counter\n{} {}",
+            name, help, name, name, value
+        );
+    }
+
+    let _ = writeln!(
+        out,
+        "# HELP zen_tws_connected Whether the TWS client is connected.\n# TYPE zen_tws_connected gauge\nzen_tws_connected {}",
+        tws_connected() as u8
+    );
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::dev::Service;
+    use actix_web::middleware::from_fn;
+    use actix_web::{error, test, web, App, HttpResponse};
+
+    use super::*;
+
+    async fn reject(
+        _: ServiceRequest,
+        _: Next<impl MessageBody>,
+    ) -> Result<ServiceResponse<impl MessageBody>, Error> {
+        Err::<ServiceResponse, _>(error::ErrorUnauthorized("invalid or missing api key"))
+    }
+
+    #[actix_web::test]
+    async fn render_tracked_errors() {
+        let app = test::init_service(
+            App::new()
+                .wrap(from_fn(reject))
+                .wrap(from_fn(track))
+                .route("/metrics-test/{id}", web::get().to(HttpResponse::Ok)),
+        )
+        .await;
+        let req = test::TestRequest::get().uri("/metrics-test/1").to_request();
+        assert!(app.call(req).await.is_err());
+
+        let out = render(&[("ib", 2)]);
+        let labels = "method=\"GET\",route=\"/metrics-test/{id}\",status=\"401\"";
+        for line in [
+            "# TYPE zen_http_request_duration_seconds histogram".to_string(),
+            format!("zen_http_request_duration_seconds_bucket{{{labels},le=\"10\"}} 1"),
+            format!("zen_http_request_duration_seconds_bucket{{{labels},le=\"+Inf\"}} 1"),
+            format!("zen_http_request_duration_seconds_count{{{labels}}} 1"),
+            "zen_store_zen_instances{source=\"ib\"} 2".to_string(),
+            "# TYPE zen_bars_processed_total counter".to_string(),
+        ] {
+            assert!(out.lines().any(|l| l == line), "missing {}", line);
+        }
+        // 样本行都是 `名称{标签} 数值`
+        for line in out.lines().filter(|l| !l.starts_with('#')) {
+            let (_, value) = line.rsplit_once(' ').unwrap();
+            assert!(value.parse::<f64>().is_ok(), "bad sample {}", line);
+        }
+    }
+}
@@ -0,0 +1,283 @@
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use clap::ValueEnum;
+use plotters::coord::Shift;
+use plotters::prelude::*;
+use time::macros::format_description;
+use time::OffsetDateTime;
+use zen_core::objects::enums::{Direction, Freq};
+
+use crate::broker::zen::Zen;
+use crate::calculate::beichi::buy_sell_point::zs_list;
+use crate::pkg::screenshot::ticker;
+
+// 不依赖浏览器和前端，直接从 Zen 画出K线、笔、中枢、MACD 和买卖点
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, ValueEnum)]
+pub enum ChartFormat {
+    Png,
+    Svg,
+}
+
+impl ChartFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ChartFormat::Png => "png",
+            ChartFormat::Svg => "svg",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ChartOptions {
+    pub format: ChartFormat,
+    pub width: u32,
+    pub height: u32,
+    // 只画最近的 K 线数
+    pub bars: usize,
+}
+
+impl Default for ChartOptions {
+    fn default() -> Self {
+        Self {
+            format: ChartFormat::Png,
+            width: 1920,
+            height: 1080,
+            bars: 250,
+        }
+    }
+}
+
+const UP: RGBColor = RGBColor(38, 166, 154);
+const DOWN: RGBColor = RGBColor(239, 83, 80);
+const BI: RGBColor = RGBColor(41, 98, 255);
+const ZS: RGBColor = RGBColor(255, 152, 0);
+const DIF: RGBColor = RGBColor(33, 150, 243);
+const DEA: RGBColor = RGBColor(255, 109, 0);
+const BUY: RGBColor = RGBColor(0, 137, 123);
+const SELL: RGBColor = RGBColor(213, 0, 0);
+
+struct Candle {
+    dt: OffsetDateTime,
+    open: f32,
+    high: f32,
+    low: f32,
+    close: f32,
+    macd: (f32, f32, f32),
+}
+
+// K 线在图上的横坐标，早于画图区间的返回 None
+fn x_of(candles: &[Candle], ts: i64) -> Option<f32> {
+    if candles.first()?.dt.unix_timestamp() > ts {
+        return None;
+    }
+    let idx = candles.partition_point(|c| c.dt.unix_timestamp() < ts);
+    Some(idx.min(candles.len() - 1) as f32)
+}
+
+fn padded(low: f32, high: f32) -> std::ops::Range<f32> {
+    let pad = ((high - low) * 0.05)
+        .max(high.abs() * 0.001)
+        .max(f32::EPSILON);
+    (low - pad)..(high + pad)
+}
+
+pub fn render(zen: &Zen, path: &Path, opts: &ChartOptions) -> Result<()> {
+    let size = (opts.width, opts.height);
+    match opts.format {
+        ChartFormat::Png => draw(
+            zen,
+            BitMapBackend::new(path, size).into_drawing_area(),
+            opts,
+        ),
+        ChartFormat::Svg => draw(zen, SVGBackend::new(path, size).into_drawing_area(), opts),
+    }
+}
+
+fn draw<DB: DrawingBackend>(
+    zen: &Zen,
+    root: DrawingArea<DB, Shift>,
+    opts: &ChartOptions,
+) -> Result<()>
+where
+    DB::ErrorType: 'static,
+{
+    let bars = &zen.czsc.bars_raw;
+    let candles: Vec<Candle> = bars[bars.len().saturating_sub(opts.bars)..]
+        .iter()
+        .map(|b| {
+            let b = b.borrow();
+            Candle {
+                dt: b.dt,
+                open: b.open,
+                high: b.high,
+                low: b.low,
+                close: b.close,
+                macd: b.macd_4_9_9,
+            }
+        })
+        .collect();
+    if candles.is_empty() {
+        return Err(anyhow!("{} has no bars", ticker(&zen.contract)));
+    }
+    let n = candles.len() as f32;
+    let xs = -0.5f32..(n - 0.5);
+
+    root.fill(&WHITE)?;
+    let (upper, lower) = root.split_vertically(opts.height * 3 / 4);
+
+    // 主图
+    let low = candles.iter().map(|c| c.low).fold(f32::MAX, f32::min);
+    let high = candles.iter().map(|c| c.high).fold(f32::MIN, f32::max);
+    let mut chart = ChartBuilder::on(&upper)
+        .caption(
+            format!("{} {:?}", ticker(&zen.contract), zen.freq),
+            ("sans-serif", 24),
+        )
+        .margin(10)
+        .x_label_area_size(0)
+        .y_label_area_size(70)
+        .build_cartesian_2d(xs.clone(), padded(low, high))?;
+    chart.configure_mesh().disable_x_mesh().x_labels(0).draw()?;
+
+    let width = ((opts.width as f32 - 90.0) / n * 0.7).max(1.0) as u32;
+    chart.draw_series(candles.iter().enumerate().map(|(i, c)| {
+        CandleStick::new(
+            i as f32,
+            c.open,
+            c.high,
+            c.low,
+            c.close,
+            UP.filled(),
+            DOWN.filled(),
+            width,
+        )
+    }))?;
+
+    // 中枢
+    for z in zs_list(&zen.czsc.bi_list) {
+        let Some(right) = x_of(&candles, z.right) else {
+            continue;
+        };
+        let left = x_of(&candles, z.left).unwrap_or(0.0);
+        chart.draw_series([
+            Rectangle::new([(left, z.high), (right, z.low)], ZS.mix(0.15).filled()),
+            Rectangle::new([(left, z.high), (right, z.low)], ZS.stroke_width(1)),
+        ])?;
+    }
+
+    // 笔
+    let points: Vec<(f32, f32)> = zen
+        .czsc
+        .bi_list
+        .iter()
+        .filter_map(|bi| {
+            let (start, end) = match bi.direction {
+                Direction::Up => (bi.low(), bi.high()),
+                Direction::Down => (bi.high(), bi.low()),
+            };
+            let a = x_of(&candles, bi.fx_a.dt.unix_timestamp())?;
+            let b = x_of(&candles, bi.fx_b.dt.unix_timestamp())?;
+            Some([(a, start), (b, end)])
+        })
+        .flatten()
+        .collect();
+    chart.draw_series(LineSeries::new(points, BI.stroke_width(2)))?;
+
+    // 买卖点，卖点标在上方，买点标在下方
+    let font = ("sans-serif", 16).into_font();
+    for bc in &zen.beichi_processor.beichi_tracker {
+        let Some(x) = x_of(&candles, bc.dt) else {
+            continue;
+        };
+        let (color, offset) = match bc.direction {
+            Direction::Up => (SELL, -24),
+            Direction::Down => (BUY, 8),
+        };
+        let label = bc.r#type.label();
+        chart.draw_series([EmptyElement::at((x, bc.price))
+            + Circle::new((0, 0), 4, color.filled())
+            + Text::new(label, (-8, offset), font.color(&color))])?;
+    }
+
+    // MACD 副图
+    let max = candles
+        .iter()
+        .map(|c| c.macd.0.abs().max(c.macd.1.abs()).max(c.macd.2.abs()))
+        .fold(0.0, f32::max);
+    let label_format = match zen.freq {
+        Freq::D | Freq::W | Freq::M => format_description!("[year]-[month]-[day]"),
+        _ => format_description!("[month]-[day] [hour]:[minute]"),
+    };
+    let x_label = |x: &f32| {
+        candles
+            .get(x.round().max(0.0) as usize)
+            .and_then(|c| c.dt.format(label_format).ok())
+            .unwrap_or_default()
+    };
+    let mut macd = ChartBuilder::on(&lower)
+        .margin(10)
+        .x_label_area_size(30)
+        .y_label_area_size(70)
+        .build_cartesian_2d(xs, padded(-max, max))?;
+    macd.configure_mesh()
+        .disable_x_mesh()
+        .x_labels(10)
+        .x_label_formatter(&x_label)
+        .draw()?;
+    macd.draw_series(candles.iter().enumerate().map(|(i, c)| {
+        let x = i as f32;
+        let color = if c.macd.2 >= 0.0 { UP } else { DOWN };
+        Rectangle::new([(x - 0.35, 0.0), (x + 0.35, c.macd.2)], color.filled())
+    }))?;
+    macd.draw_series(LineSeries::new(
+        candles
+            .iter()
+            .enumerate()
+            .map(|(i, c)| (i as f32, c.macd.0)),
+        DIF.stroke_width(1),
+    ))?;
+    macd.draw_series(LineSeries::new(
+        candles
+            .iter()
+            .enumerate()
+            .map(|(i, c)| (i as f32, c.macd.1)),
+        DEA.stroke_width(1),
+    ))?;
+
+    root.present()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::broker::zen::tests::{pivots, zen, zigzag};
+
+    #[test]
+    fn render_svg() {
+        let zen = zen(&zigzag(&pivots(), 8));
+        let zs = zs_list(&zen.czsc.bi_list);
+        assert!(!zs.is_empty());
+
+        let mut svg = String::new();
+        let opts = ChartOptions {
+            format: ChartFormat::Svg,
+            width: 800,
+            height: 600,
+            ..Default::default()
+        };
+        draw(
+            &zen,
+            SVGBackend::with_string(&mut svg, (opts.width, opts.height)).into_drawing_area(),
+            &opts,
+        )
+        .unwrap();
+        // 每个中枢一个填充框和一个边框
+        assert_eq!(svg.matches("#FF9800").count(), zs.len() * 2);
+        for bc in &zen.beichi_processor.beichi_tracker {
+            assert!(svg.contains(&format!(">{}<", bc.r#type.label())));
+        }
+    }
+}
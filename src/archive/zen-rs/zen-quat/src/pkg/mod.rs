@@ -1,3 +1,4 @@
+pub mod chart;
 pub mod load_db;
 pub mod screenshot;
-mod stock_scan;
+pub mod stock_scan;
@@ -1,9 +1,15 @@
-use anyhow::Result;
-use headless_chrome::{protocol::cdp::Page::CaptureScreenshotFormatOption, Browser, LaunchOptions};
+use anyhow::{bail, Result};
 use std::fs;
 use std::path::Path;
+use time::OffsetDateTime;
+use tokio::task::LocalSet;
 use tracing::{debug, error};
 use tws_rs::contracts::Contract;
+use zen_core::objects::enums::Freq;
+
+use crate::broker::ib::IB;
+use crate::broker::mixed::Mixed;
+use crate::pkg::chart::{render, ChartOptions};
 
 pub fn parse_contract(line: &str) -> Option<Contract> {
     let symbol = line.split_whitespace().collect::<Vec<_>>()[0];
@@ -26,68 +32,84 @@ pub fn parse_contract(line: &str) -> Option<Contract> {
 
     None
 }
-pub fn screenshot(path: String, outdir: String) -> Result<()> {
-    let ct = fs::read_to_string(path)?;
-    let options = LaunchOptions::default_builder()
-        .enable_gpu(true)
-        .sandbox(false)
-        .window_size(Some((1920, 1080)))
-        .build()
-        .expect("Couldn't find appropriate Chrome binary.");
-    let mut browser = Browser::new(options.clone())?;
 
-    for (idx, line) in ct.lines().enumerate() {
-        let contract = parse_contract(line);
-        if contract.is_none() {
-            continue;
+// 图表上使用的代码，A 股带交易所前缀
+pub fn ticker(contract: &Contract) -> String {
+    if contract.currency == "CNH" {
+        if contract.symbol.starts_with("6") {
+            format!("SSE:{}", contract.symbol)
+        } else {
+            format!("SZSE:{}", contract.symbol)
         }
+    } else {
+        contract.symbol.clone()
+    }
+}
 
-        let contract = contract.unwrap();
-
-        let file = format!(
-            "./{}/{:03}-{}.jpg",
-            outdir.trim_end_matches("/"),
-            idx,
-            contract.symbol.clone()
-        );
-        if Path::new(file.as_str()).exists() {
-            continue;
-        }
+pub fn chart_url(contract: &Contract) -> String {
+    format!(
+        "http://localhost:3000/local?symbolState=\"{}\"",
+        ticker(contract)
+    )
+}
 
-        if idx % 10 == 0 {
-            browser = Browser::new(options.clone())?;
-        }
+// 用本地K线跑 Zen，逐个品种画图输出到 outdir，已存在的文件跳过
+pub fn screenshot(
+    path: String,
+    outdir: String,
+    resolution: String,
+    opts: ChartOptions,
+) -> Result<()> {
+    let ct = fs::read_to_string(path)?;
+    let Some(freq) = IB::freq_map().get(&resolution).cloned() else {
+        bail!("unknown resolution {}", resolution);
+    };
+    fs::create_dir_all(&outdir)?;
 
-        let res = || -> Result<()> {
-            debug!("crawling {} {}-{}", idx, contract.exchange, contract.symbol);
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    let localset = LocalSet::new();
+    localset.block_on(&rt, async {
+        let broker = Mixed::new();
+        for (idx, line) in ct.lines().enumerate() {
+            let Some(contract) = parse_contract(line) else {
+                continue;
+            };
+            let file = Path::new(&outdir).join(format!(
+                "{:03}-{}.{}",
+                idx,
+                contract.symbol,
+                opts.format.extension()
+            ));
+            if file.exists() {
+                continue;
+            }
 
-            let tab = browser.new_tab()?;
-            let jpeg_data = tab
-                .navigate_to(
-                    format!(
-                        "http://localhost:3000/local?symbolState=\"{}\"",
-                        if contract.currency == "CNH" {
-                            if contract.symbol.starts_with("6") {
-                                format!("SSE:{}", contract.symbol)
-                            } else {
-                                format!("SZSE:{}", contract.symbol)
-                            }
-                        } else {
-                            contract.symbol.clone()
-                        }
-                    )
-                    .as_str(),
-                )?
-                .wait_until_navigated()?
-                .capture_screenshot(CaptureScreenshotFormatOption::Jpeg, Some(75), None, true)?;
-            fs::write(file, jpeg_data)?;
-            tab.close(true)?;
-            Ok(())
-        }();
-        if res.is_err() {
-            error!("{} error {}", line, res.unwrap_err())
+            debug!(
+                "rendering {} {}-{}",
+                idx, contract.exchange, contract.symbol
+            );
+            if let Err(e) = render_contract(&broker, &contract, freq, &file, &opts).await {
+                error!("{} error {}", line, e)
+            }
         }
-    }
-
+    });
     Ok(())
 }
+
+async fn render_contract(
+    broker: &Mixed,
+    contract: &Contract,
+    freq: Freq,
+    file: &Path,
+    opts: &ChartOptions,
+) -> Result<()> {
+    let to = OffsetDateTime::now_utc().unix_timestamp();
+    broker
+        .try_subscribe(true, contract, freq, 0, to, 0, true)
+        .await?;
+    let zen = broker.get_czsc(true, contract, freq);
+    let zen = zen.read().await;
+    render(&zen, file, opts)
+}
@@ -1 +1,237 @@
-mod score;
+use std::fs;
+
+use anyhow::{bail, Result};
+use diesel::prelude::*;
+use diesel_tracing::sqlite::InstrumentedSqliteConnection;
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+use tokio::task::{self, LocalSet};
+use tracing::{debug, info};
+use tws_rs::contracts::Contract;
+use zen_core::objects::enums::Freq;
+
+use crate::broker::ib::IB;
+use crate::broker::mixed::Mixed;
+use crate::calculate::beichi::buy_sell_point::BSPoint;
+use crate::db::establish_connection;
+use crate::pkg::screenshot::{chart_url, parse_contract, ticker};
+use crate::pkg::stock_scan::score::{recent_point, score, Score};
+
+pub mod score;
+
+// 单次扫描最多的品种数
+pub const MAX_SYMBOLS: usize = 500;
+// HTTP 扫描在共用的 broker 线程上执行，每个请求最多扫描的品种数
+pub const MAX_REQUEST_SYMBOLS: usize = 100;
+// 打分用到 MA200，多取一些K线让笔和买卖点稳定下来
+const LOOKBACK_BARS: i64 = 600;
+pub const DEFAULT_PAGE_SIZE: usize = 20;
+const MAX_PAGE_SIZE: usize = 200;
+
+// 品种来源二选一：watchlist 文件，或 symbols 表中按 screener/exchange 过滤
+#[derive(Deserialize, Debug, Clone)]
+pub struct ScanRequest {
+    pub watchlist: Option<String>,
+    pub screener: Option<String>,
+    pub exchange: Option<String>,
+    #[serde(default = "ScanRequest::default_resolution")]
+    pub resolution: String,
+    // 从 1 开始
+    #[serde(default = "ScanRequest::default_page")]
+    pub page: usize,
+    #[serde(default = "ScanRequest::default_page_size")]
+    pub page_size: usize,
+}
+
+impl ScanRequest {
+    fn default_resolution() -> String {
+        "1D".to_string()
+    }
+
+    fn default_page() -> usize {
+        1
+    }
+
+    fn default_page_size() -> usize {
+        DEFAULT_PAGE_SIZE
+    }
+}
+
+#[derive(Serialize, Debug)]
+pub struct ScanItem {
+    pub rank: usize,
+    pub symbol: String,
+    pub resolution: String,
+    pub score: Score,
+    pub last_price: Option<f32>,
+    pub last_point: Option<BSPoint>,
+    pub chart: String,
+}
+
+#[derive(Serialize, Debug)]
+pub struct ScanResponse {
+    pub total: usize,
+    pub page: usize,
+    pub page_size: usize,
+    pub items: Vec<ScanItem>,
+    // 没有本地数据或加载失败的品种
+    pub skipped: Vec<String>,
+    // 超出 max_symbols 未扫描的品种数
+    pub truncated: usize,
+}
+
+// LOOKBACK_BARS 根K线大约覆盖的自然日，日内级别按每天 4 小时交易估算
+fn lookback_days(freq: Freq) -> i64 {
+    let trading_days = match freq {
+        Freq::D => LOOKBACK_BARS,
+        Freq::W => LOOKBACK_BARS * 5,
+        Freq::M | Freq::S | Freq::Y => LOOKBACK_BARS * 21,
+        _ => {
+            let minutes = freq
+                .as_str()
+                .trim_start_matches('F')
+                .parse::<i64>()
+                .unwrap_or(1);
+            (LOOKBACK_BARS * minutes + 239) / 240
+        }
+    };
+    trading_days * 7 / 5 + 1
+}
+
+pub fn watchlist_contracts(path: &str) -> Result<Vec<Contract>> {
+    let ct = fs::read_to_string(path)?;
+    Ok(ct
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(parse_contract)
+        .take(MAX_SYMBOLS)
+        .collect())
+}
+
+pub fn screener_contracts(
+    conn: &mut InstrumentedSqliteConnection,
+    screener_: Option<&str>,
+    exchange_: Option<&str>,
+) -> Result<Vec<Contract>> {
+    use crate::schema::symbols::dsl::*;
+
+    if screener_.is_none() && exchange_.is_none() {
+        bail!("either watchlist, screener or exchange is required");
+    }
+    let mut query = symbols.select((symbol, exchange)).into_boxed();
+    if let Some(s) = screener_ {
+        query = query.filter(screener.eq(s));
+    }
+    if let Some(e) = exchange_ {
+        query = query.filter(exchange.eq(e));
+    }
+    let rows = query
+        .limit(MAX_SYMBOLS as i64)
+        .load::<(Option<String>, Option<String>)>(conn)?;
+    Ok(rows
+        .into_iter()
+        .filter_map(|(s, e)| match (s, e.as_deref()) {
+            (Some(s), Some(e @ ("SSE" | "SZSE"))) => {
+                Some(Contract::auto_stock(format!("{}:{}", e, s).as_str()))
+            }
+            (Some(s), _) => Some(Contract::auto_stock(s.as_str())),
+            _ => None,
+        })
+        .collect())
+}
+
+// 用本地K线跑 Zen 并打分，按总分从高到低排序后分页，只扫描前 max_symbols 个品种
+pub async fn scan(
+    broker: &Mixed,
+    contracts: &[Contract],
+    req: &ScanRequest,
+    max_symbols: usize,
+) -> Result<ScanResponse> {
+    let Some(freq) = IB::freq_map().get(&req.resolution).cloned() else {
+        bail!("unknown resolution {}", req.resolution);
+    };
+    let to = OffsetDateTime::now_utc().unix_timestamp();
+    let from = to - lookback_days(freq) * 24 * 60 * 60;
+
+    let mut scored = vec![];
+    let mut skipped = vec![];
+    for contract in contracts.iter().take(max_symbols) {
+        // 每个品种之间让出 broker 线程，不阻塞其它请求
+        task::yield_now().await;
+        if let Err(e) = broker
+            .try_subscribe(true, contract, freq, from, to, 0, true)
+            .await
+        {
+            debug!("scan {} error {}", contract.symbol, e);
+            skipped.push(ticker(contract));
+            continue;
+        }
+        let zen = broker.get_czsc(true, contract, freq);
+        let zen = zen.read().await;
+        let Some(last_price) = zen.czsc.bars_raw.last().map(|b| b.borrow().close) else {
+            skipped.push(ticker(contract));
+            continue;
+        };
+        scored.push(ScanItem {
+            rank: 0,
+            symbol: ticker(contract),
+            resolution: req.resolution.clone(),
+            score: score(&zen),
+            last_price: Some(last_price),
+            last_point: recent_point(&zen).map(|(bc, _)| bc.clone()),
+            chart: chart_url(contract),
+        });
+    }
+    scored.sort_by(|a, b| {
+        b.score
+            .total
+            .total_cmp(&a.score.total)
+            .then_with(|| a.symbol.cmp(&b.symbol))
+    });
+
+    let total = scored.len();
+    let page = req.page.max(1);
+    let page_size = req.page_size.clamp(1, MAX_PAGE_SIZE);
+    let items = scored
+        .into_iter()
+        .enumerate()
+        .skip((page - 1) * page_size)
+        .take(page_size)
+        .map(|(i, item)| ScanItem {
+            rank: i + 1,
+            ..item
+        })
+        .collect();
+    Ok(ScanResponse {
+        total,
+        page,
+        page_size,
+        items,
+        skipped,
+        truncated: contracts.len().saturating_sub(max_symbols),
+    })
+}
+
+// Scan 子命令，结果以 JSON 打印到标准输出
+pub fn scan_cli(req: ScanRequest) -> Result<()> {
+    let contracts = match &req.watchlist {
+        Some(path) => watchlist_contracts(path)?,
+        None => screener_contracts(
+            &mut establish_connection(),
+            req.screener.as_deref(),
+            req.exchange.as_deref(),
+        )?,
+    };
+    info!("scanning {} symbols", contracts.len());
+
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    let localset = LocalSet::new();
+    let rsp = localset.block_on(&rt, async {
+        let broker = Mixed::new();
+        scan(&broker, &contracts, &req, MAX_SYMBOLS).await
+    })?;
+    println!("{}", serde_json::to_string_pretty(&rsp)?);
+    Ok(())
+}
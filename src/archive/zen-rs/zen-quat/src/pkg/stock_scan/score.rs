@@ -1 +1,194 @@
+use serde::Serialize;
+use zen_core::objects::enums::Direction;
 
+use crate::broker::zen::Zen;
+use crate::calculate::beichi::buy_sell_point::{BSPoint, BeichiType, PointType};
+use crate::calculate::zen_cache::SMATrackerCache;
+
+// 买卖点在最近多少根K线内才计分，越早分越低
+const RECENT_BARS: usize = 20;
+// 参与计分的均线
+const MA_PERIODS: [isize; 3] = [60, 120, 200];
+// 距均线多近算回踩
+const MA_NEAR: f32 = 0.03;
+
+// 各项得分，正分偏多，负分偏空
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct Score {
+    pub total: f32,
+    pub point: f32,
+    pub ma: f32,
+    pub structure: f32,
+}
+
+pub fn score(zen: &Zen) -> Score {
+    let point = point_score(zen);
+    let ma = ma_score(zen);
+    let structure = structure_score(zen);
+    Score {
+        total: point + ma + structure,
+        point,
+        ma,
+        structure,
+    }
+}
+
+// 最近的买卖点及其后的K线数
+pub fn recent_point(zen: &Zen) -> Option<(&BSPoint, usize)> {
+    let bc = zen.beichi_processor.beichi_tracker.last()?;
+    let age = zen
+        .czsc
+        .bars_raw
+        .iter()
+        .rev()
+        .take_while(|b| b.borrow().dt.unix_timestamp() > bc.dt)
+        .count();
+    (age < RECENT_BARS).then_some((bc, age))
+}
+
+fn point_score(zen: &Zen) -> f32 {
+    let Some((bc, age)) = recent_point(zen) else {
+        return 0.0;
+    };
+    let mut s = match bc.r#type {
+        PointType::FirstBuy | PointType::FirstSell => 40.0,
+        PointType::SecondBuy | PointType::SecondSell => 30.0,
+        PointType::ThirdBuy | PointType::ThirdSell => 25.0,
+        PointType::None => 15.0,
+    };
+    if bc.bc_type.contains(&BeichiType::Diff) {
+        s += 10.0;
+    }
+    if bc.fake_bi {
+        s *= 0.5;
+    }
+    s *= 1.0 - age as f32 / RECENT_BARS as f32;
+    // 顶背驰为卖点
+    if bc.direction == Direction::Up {
+        -s
+    } else {
+        s
+    }
+}
+
+fn ma_score(zen: &Zen) -> f32 {
+    let bars = &zen.czsc.bars_raw;
+    let Some(close) = bars.last().map(|b| b.borrow().close) else {
+        return 0.0;
+    };
+    let Some(smas) = zen.czsc.cache.get::<SMATrackerCache>() else {
+        return 0.0;
+    };
+    let mut s = 0.0;
+    for p in MA_PERIODS {
+        if bars.len() < p as usize {
+            continue;
+        }
+        let Some(ma) = smas.store.get(&p).map(|x| x.ma()).filter(|ma| *ma > 0.0) else {
+            continue;
+        };
+        // 站上均线偏多，贴近均线时支撑或压力更强
+        let distance = (close - ma) / ma;
+        let near = (1.0 - distance.abs() / MA_NEAR).max(0.0);
+        s += distance.signum() * (5.0 + 5.0 * near);
+    }
+    s
+}
+
+// 最近两个同向笔的端点抬高偏多，降低偏空
+fn structure_score(zen: &Zen) -> f32 {
+    let bis = &zen.czsc.bi_list;
+    let lows: Vec<f32> = bis
+        .iter()
+        .rev()
+        .filter(|bi| bi.direction == Direction::Down)
+        .take(2)
+        .map(|bi| bi.low())
+        .collect();
+    let highs: Vec<f32> = bis
+        .iter()
+        .rev()
+        .filter(|bi| bi.direction == Direction::Up)
+        .take(2)
+        .map(|bi| bi.high())
+        .collect();
+    let mut s = 0.0;
+    for pair in [lows, highs] {
+        if let [last, prev] = pair[..] {
+            s += if last > prev { 10.0 } else { -10.0 };
+        }
+    }
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::broker::zen::tests::{pivots, zen, zigzag};
+
+    // 最后一根往前数第 back 根K线的时间
+    fn bar_dt(zen: &Zen, back: usize) -> i64 {
+        let bars = &zen.czsc.bars_raw;
+        bars[bars.len() - 1 - back].borrow().dt.unix_timestamp()
+    }
+
+    fn with_point(zen: &mut Zen, bc: BSPoint) -> f32 {
+        zen.beichi_processor.beichi_tracker.push(bc);
+        point_score(zen)
+    }
+
+    #[test]
+    fn point_score_by_type_and_age() {
+        let mut zen = zen(&zigzag(&pivots(), 8));
+        let mut bc = zen.beichi_processor.beichi_tracker.last().cloned().unwrap();
+        bc.direction = Direction::Down;
+        bc.r#type = PointType::FirstBuy;
+        bc.bc_type = vec![BeichiType::Area];
+        bc.fake_bi = false;
+        bc.dt = bar_dt(&zen, 0);
+        assert_eq!(with_point(&mut zen, bc.clone()), 40.0);
+
+        let mut diff = bc.clone();
+        diff.bc_type.push(BeichiType::Diff);
+        assert_eq!(with_point(&mut zen, diff), 50.0);
+        let mut fake = bc.clone();
+        fake.fake_bi = true;
+        assert_eq!(with_point(&mut zen, fake), 20.0);
+        let mut third = bc.clone();
+        third.r#type = PointType::ThirdBuy;
+        assert_eq!(with_point(&mut zen, third), 25.0);
+        // 卖点为负分
+        let mut sell = bc.clone();
+        sell.direction = Direction::Up;
+        sell.r#type = PointType::FirstSell;
+        assert_eq!(with_point(&mut zen, sell), -40.0);
+
+        // 越早分越低，超过 RECENT_BARS 不计分
+        let mut older = bc.clone();
+        older.dt = bar_dt(&zen, 10);
+        assert_eq!(with_point(&mut zen, older), 20.0);
+        bc.dt = bar_dt(&zen, RECENT_BARS);
+        assert_eq!(with_point(&mut zen, bc), 0.0);
+    }
+
+    #[test]
+    fn ma_score_above_and_below() {
+        let rising: Vec<f32> = (0..250).map(|i| 10.0 + 0.1 * i as f32).collect();
+        assert_eq!(ma_score(&zen(&rising)), 15.0);
+        let falling: Vec<f32> = rising.iter().rev().cloned().collect();
+        assert_eq!(ma_score(&zen(&falling)), -15.0);
+        // K线不足最短的均线周期
+        assert_eq!(ma_score(&zen(&rising[..50])), 0.0);
+    }
+
+    #[test]
+    fn structure_score_by_pivots() {
+        let up = [10.0, 20.0, 12.0, 22.0, 14.0, 24.0, 16.0, 26.0, 18.0, 28.0];
+        assert_eq!(structure_score(&zen(&zigzag(&up, 8))), 20.0);
+        let down: Vec<f32> = up.iter().map(|p| 40.0 - p).collect();
+        assert_eq!(structure_score(&zen(&zigzag(&down, 8))), -20.0);
+        // 低点抬高、高点降低的收敛三角形
+        let triangle = [10.0, 30.0, 12.0, 28.0, 14.0, 26.0, 16.0, 24.0, 18.0];
+        assert_eq!(structure_score(&zen(&zigzag(&triangle, 8))), 0.0);
+    }
+}
@@ -13,6 +13,56 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    charts (id) {
+        id -> Integer,
+        client -> Text,
+        user -> Text,
+        name -> Text,
+        symbol -> Text,
+        resolution -> Text,
+        content -> Text,
+        timestamp -> BigInt,
+    }
+}
+
+diesel::table! {
+    dashboard_versions (dashboard_id, version) {
+        dashboard_id -> Integer,
+        version -> Integer,
+        content -> Text,
+        created -> BigInt,
+    }
+}
+
+diesel::table! {
+    dashboards (id) {
+        id -> Integer,
+        name -> Text,
+        version -> Integer,
+        updated -> BigInt,
+    }
+}
+
+diesel::table! {
+    drawing_templates (client, user, tool, name) {
+        client -> Text,
+        user -> Text,
+        tool -> Text,
+        name -> Text,
+        content -> Text,
+    }
+}
+
+diesel::table! {
+    study_templates (client, user, name) {
+        client -> Text,
+        user -> Text,
+        name -> Text,
+        content -> Text,
+    }
+}
+
 diesel::table! {
     symbols (exchange, symbol) {
         screener -> Nullable<Text>,
@@ -26,4 +76,22 @@ diesel::table! {
     }
 }
 
-diesel::allow_tables_to_appear_in_same_query!(bar_history, symbols,);
+diesel::table! {
+    watchlists (user, name) {
+        user -> Text,
+        name -> Text,
+        symbols -> Text,
+        updated -> BigInt,
+    }
+}
+
+diesel::allow_tables_to_appear_in_same_query!(
+    bar_history,
+    charts,
+    dashboard_versions,
+    dashboards,
+    drawing_templates,
+    study_templates,
+    symbols,
+    watchlists,
+);
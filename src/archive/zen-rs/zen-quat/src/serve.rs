@@ -1,43 +1,125 @@
 use std::cell::RefCell;
-use std::rc::Rc;
+use std::io;
+use std::net::ToSocketAddrs;
 
 use actix_cors::Cors;
-use actix_web::middleware::Logger;
-use actix_web::{rt, App, HttpServer};
+use actix_web::middleware::{from_fn, Logger};
+use actix_web::{rt, web, App, HttpServer};
+use tracing::warn;
 
 use crate::api;
-use crate::broker::mixed::Mixed;
+use crate::api::{dashboard, health, storage, watchlist};
+use crate::auth::{authenticate, redacted_request_line, AuthConfig, LOCAL_USER};
+use crate::broker::shared::SharedBroker;
+use crate::metrics;
 use tws_rs::Error;
 
 use crate::db::establish_connection;
 
-pub fn serve() -> std::io::Result<()> {
-    rt::System::new().block_on(
-        HttpServer::new(|| {
-            let cors = Cors::default()
-                .allow_any_origin()
-                .allow_any_header()
-                .allow_any_method()
-                .max_age(3600);
+// Serve 子命令的配置
+#[derive(Clone, Debug)]
+pub struct ServeConfig {
+    pub bind: String,
+    // 为空时只允许同源请求，"*" 允许任意来源
+    pub cors_origins: Vec<String>,
+    pub auth: AuthConfig,
+    // 为空时使用 actix 默认的 worker 数
+    pub workers: Option<usize>,
+}
+
+fn cors(origins: &[String]) -> Cors {
+    let mut cors = Cors::default()
+        .allow_any_header()
+        .allow_any_method()
+        .max_age(3600);
+    for origin in origins {
+        cors = if origin == "*" {
+            cors.allow_any_origin()
+        } else {
+            cors.allowed_origin(origin)
+        };
+    }
+    cors
+}
+
+pub fn serve(config: ServeConfig) -> std::io::Result<()> {
+    // 没有 api key 时只允许本机访问
+    if !config.auth.is_enabled() {
+        if !config
+            .bind
+            .to_socket_addrs()?
+            .all(|addr| addr.ip().is_loopback())
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!(
+                    "refusing to serve {} without api keys, use --api-keys-file or a loopback address",
+                    config.bind
+                ),
+            ));
+        }
+        warn!("authentication disabled, requests run as {}", LOCAL_USER);
+    }
+
+    let bind = config.bind.clone();
+    let workers = config.workers;
+    // 所有 worker 共用一个 broker
+    let broker = web::Data::new(SharedBroker::start()?);
+    rt::System::new().block_on(async move {
+        let mut server = HttpServer::new(move || {
             App::new()
-                .wrap(Logger::new("%a  %r %s %b  %T"))
-                .wrap(cors)
-                .data_factory(|| async { Ok::<_, Error>(Rc::new(RefCell::new(Mixed::new()))) })
+                .wrap(from_fn(authenticate))
+                .wrap(
+                    Logger::new("%a  %{r}xi %s %b  %T")
+                        .custom_request_replace("r", redacted_request_line),
+                )
+                .wrap(from_fn(metrics::track))
+                .wrap(cors(&config.cors_origins))
+                .app_data(web::Data::new(config.auth.clone()))
+                .app_data(broker.clone())
                 .data_factory(|| async {
                     let conn = establish_connection();
 
                     Ok::<_, Error>(RefCell::new(conn))
                 })
+                .app_data(web::FormConfig::default().limit(storage::MAX_CONTENT_SIZE))
+                .service(health::scrape)
+                .service(health::healthz)
+                .service(health::readyz)
                 .service(api::history)
                 .service(api::search_symbol)
                 .service(api::resolve_symbol)
                 .service(api::zen_element)
                 .service(api::config)
+                .service(api::marks)
+                .service(api::timescale_marks)
                 .service(api::option_price)
+                .service(api::scan)
                 .service(api::websocket)
-        })
-        .workers(1)
-        .bind(("0.0.0.0", 8080))?
-        .run(),
-    )
+                .service(storage::get_charts)
+                .service(storage::save_chart)
+                .service(storage::delete_chart)
+                .service(storage::get_study_templates)
+                .service(storage::save_study_template)
+                .service(storage::delete_study_template)
+                .service(storage::get_drawing_templates)
+                .service(storage::save_drawing_template)
+                .service(storage::delete_drawing_template)
+                .service(dashboard::list_dashboards)
+                .service(dashboard::create_dashboard)
+                .service(dashboard::dashboard_versions)
+                .service(dashboard::evaluate_dashboard)
+                .service(dashboard::get_dashboard)
+                .service(dashboard::update_dashboard)
+                .service(dashboard::delete_dashboard)
+                .service(watchlist::list_watchlists)
+                .service(watchlist::get_watchlist)
+                .service(watchlist::save_watchlist)
+                .service(watchlist::delete_watchlist)
+        });
+        if let Some(workers) = workers {
+            server = server.workers(workers);
+        }
+        server.bind(bind)?.run().await
+    })
 }
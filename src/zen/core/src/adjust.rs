@@ -0,0 +1,177 @@
+use pyo3::exceptions::PyValueError;
+use pyo3::{pyclass, pyfunction, pymethods, PyResult};
+
+use crate::element::chan::{Bar, DT};
+
+// 复权方式
+#[pyclass(eq, eq_int)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AdjustMode {
+    // 不复权
+    None,
+    // 前复权，除权前的价格换算到最新价格体系
+    Forward,
+    // 后复权，除权后的价格换算到最早价格体系
+    Backward,
+}
+
+// 公司行动：拆股、送转股、合股及现金分红
+#[pyclass(frozen, get_all)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct CorporateAction {
+    // 除权除息日，该时间及之后的K线为除权后价格
+    pub ex_dt: DT,
+    // 每股变为多少股，如 1 拆 5 为 5，10 送 3 为 1.3，50 合 1 为 0.02
+    pub split_ratio: f64,
+    // 每股现金分红（除权前股本）
+    pub dividend: f64,
+}
+
+impl CorporateAction {
+    pub fn new(ex_dt: DT, split_ratio: f64, dividend: f64) -> Result<Self, String> {
+        if !(split_ratio.is_finite() && split_ratio > 0.0) {
+            return Err(format!("split_ratio must be positive, got {}", split_ratio));
+        }
+        if !(dividend.is_finite() && dividend >= 0.0) {
+            return Err(format!("dividend must be non-negative, got {}", dividend));
+        }
+        Ok(Self {
+            ex_dt,
+            split_ratio,
+            dividend,
+        })
+    }
+
+    // 除权前价格 -> 除权后价格
+    fn forward(&self, p: f64) -> f64 {
+        (p - self.dividend) / self.split_ratio
+    }
+
+    // 除权后价格 -> 除权前价格
+    fn backward(&self, p: f64) -> f64 {
+        p * self.split_ratio + self.dividend
+    }
+}
+
+fn map_bar(bar: &mut Bar, price: impl Fn(f64) -> f64, vol_ratio: f64) {
+    bar.open = price(bar.open as f64) as f32;
+    bar.close = price(bar.close as f64) as f32;
+    bar.high = price(bar.high as f64) as f32;
+    bar.low = price(bar.low as f64) as f32;
+    bar.vol = (bar.vol as f64 * vol_ratio) as f32;
+}
+
+// 对一根不复权K线复权，actions 须按 ex_dt 升序
+pub fn apply(bar: &mut Bar, actions: &[CorporateAction], mode: AdjustMode) {
+    let dt = bar.dt;
+    match mode {
+        AdjustMode::None => {}
+        AdjustMode::Forward => {
+            for a in actions.iter().filter(|a| dt < a.ex_dt) {
+                map_bar(bar, |p| a.forward(p), a.split_ratio);
+            }
+        }
+        AdjustMode::Backward => {
+            for a in actions.iter().rev().filter(|a| dt >= a.ex_dt) {
+                map_bar(bar, |p| a.backward(p), 1.0 / a.split_ratio);
+            }
+        }
+    }
+}
+
+// apply 的逆运算，把复权K线还原为不复权K线
+pub fn restore(bar: &mut Bar, actions: &[CorporateAction], mode: AdjustMode) {
+    let dt = bar.dt;
+    match mode {
+        AdjustMode::None => {}
+        AdjustMode::Forward => {
+            for a in actions.iter().rev().filter(|a| dt < a.ex_dt) {
+                map_bar(bar, |p| a.backward(p), 1.0 / a.split_ratio);
+            }
+        }
+        AdjustMode::Backward => {
+            for a in actions.iter().filter(|a| dt >= a.ex_dt) {
+                map_bar(bar, |p| a.forward(p), a.split_ratio);
+            }
+        }
+    }
+}
+
+#[pymethods]
+impl CorporateAction {
+    #[new]
+    #[pyo3(signature = (ex_dt, split_ratio=1.0, dividend=0.0))]
+    fn py_new(ex_dt: DT, split_ratio: f64, dividend: f64) -> PyResult<Self> {
+        CorporateAction::new(ex_dt, split_ratio, dividend).map_err(PyValueError::new_err)
+    }
+
+    fn __repr__(&self) -> String {
+        format!("{:?}", self)
+    }
+}
+
+// 在K线进入 CZSC 之前对历史K线复权
+#[pyfunction]
+pub fn adjust_bars(
+    bars: Vec<Bar>,
+    mut actions: Vec<CorporateAction>,
+    mode: AdjustMode,
+) -> Vec<Bar> {
+    actions.sort_by_key(|a| a.ex_dt);
+    bars.into_iter()
+        .map(|mut bar| {
+            apply(&mut bar, &actions, mode);
+            bar
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+
+    use super::*;
+    use crate::element::enums::Freq;
+
+    fn bar(day: u32, price: f32) -> Bar {
+        Bar {
+            dt: Utc
+                .with_ymd_and_hms(2024, 6, day, 7, 0, 0)
+                .unwrap()
+                .fixed_offset(),
+            freq: Freq::D,
+            open: price,
+            close: price,
+            high: price,
+            low: price,
+            vol: 100.0,
+            amount: 0.0,
+            cache: Default::default(),
+            macd_4_9_9: (0.0, 0.0, 0.0),
+        }
+    }
+
+    #[test]
+    fn forward_backward_roundtrip() {
+        // 6 月 3 日 10 送 10 并每股派 1 元，6 月 5 日 1 拆 2
+        let actions = vec![
+            CorporateAction::new(bar(3, 0.0).dt, 2.0, 1.0).unwrap(),
+            CorporateAction::new(bar(5, 0.0).dt, 2.0, 0.0).unwrap(),
+        ];
+        let raw = vec![bar(2, 21.0), bar(3, 10.0), bar(4, 10.0), bar(5, 5.0)];
+
+        let fwd = adjust_bars(raw.clone(), actions.clone(), AdjustMode::Forward);
+        let closes: Vec<f32> = fwd.iter().map(|b| b.close).collect();
+        assert_eq!(closes, vec![5.0, 5.0, 5.0, 5.0]);
+        assert_eq!(fwd[0].vol, 400.0);
+
+        let bwd = adjust_bars(raw.clone(), actions.clone(), AdjustMode::Backward);
+        let closes: Vec<f32> = bwd.iter().map(|b| b.close).collect();
+        assert_eq!(closes, vec![21.0, 21.0, 21.0, 21.0]);
+
+        for (mut b, r) in fwd.into_iter().zip(&raw) {
+            restore(&mut b, &actions, AdjustMode::Forward);
+            assert_eq!((b.close, b.vol), (r.close, r.vol));
+        }
+    }
+}
@@ -1,5 +1,7 @@
 use crate::talipp::indicator::macd::MACD;
 use crate::talipp::indicator::Indicator;
+use chrono::{TimeZone, Utc};
+use chrono_tz::Tz;
 use pyo3::pyclass;
 use std::cell::RefCell;
 use std::cmp::max;
@@ -23,7 +25,9 @@ pub struct CZSC {
     pub bi_list: Vec<BI>,
     pub symbol: Symbol,
     pub freq: Freq,
-    settings: Settings,
+    // 交易所时区，K线及信号时间都换算到该时区
+    pub tz: Tz,
+    pub(crate) settings: Settings,
     macd_calc: MACD,
     pub cache: GenericCache,
 }
@@ -46,12 +50,21 @@ impl CZSC {
             bi_list: vec![],
             symbol,
             freq,
+            tz: settings.tz(),
             settings,
             macd_calc: MACD::new(4, 9, 9),
             cache: Default::default(),
         }
     }
 
+    // unix 时间戳换算为交易所时区的时间
+    pub fn localize(&self, ts: i64) -> DT {
+        Utc.timestamp_opt(ts, 0)
+            .unwrap()
+            .with_timezone(&self.tz)
+            .fixed_offset()
+    }
+
     pub fn start(&self) -> Option<DT> {
         return self.bars_raw.first().map(|e| e.borrow().dt);
     }
@@ -98,6 +111,7 @@ impl CZSC {
     }
 
     pub fn update(&mut self, mut bar_: Bar) -> bool {
+        bar_.dt = bar_.dt.with_timezone(&self.tz).fixed_offset();
         let (last_bar, new_bar) =
             if self.bars_raw.len() == 0 || bar_.dt != self.bars_raw.last().unwrap().borrow().dt {
                 self.macd_calc.next(bar_.close);
@@ -357,8 +371,11 @@ fn check_fx(k1: Rc<NewBar>, k2: Rc<NewBar>, k3: Rc<NewBar>) -> Option<FX> {
     return fx;
 }
 
-fn check_fxs(bars: &Vec<Rc<NewBar>>) -> Vec<Rc<FX>> {
+pub(crate) fn check_fxs(bars: &Vec<Rc<NewBar>>) -> Vec<Rc<FX>> {
     let mut fxs: Vec<Rc<FX>> = vec![];
+    if bars.len() < 3 {
+        return fxs;
+    }
     for i in 1..bars.len() - 1 {
         let fx_ = check_fx(bars[i - 1].clone(), bars[i].clone(), bars[i + 1].clone());
         if let Some(fx) = fx_ {
@@ -467,3 +484,27 @@ pub fn check_bi(
     }
     return None;
 }
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Timelike, Utc};
+
+    use super::*;
+
+    #[test]
+    fn localize_with_dst() {
+        let settings = Settings {
+            timezone: "America/New_York".to_string(),
+            ..Default::default()
+        };
+        let czsc = CZSC::new("AAPL".to_string(), Freq::F30, settings);
+        // 美股开盘 9:30，夏令时为 UTC 13:30，冬令时为 UTC 14:30
+        let summer = Utc.with_ymd_and_hms(2024, 7, 1, 13, 30, 0).unwrap();
+        let winter = Utc.with_ymd_and_hms(2024, 12, 2, 14, 30, 0).unwrap();
+        for dt in [summer, winter] {
+            let local = czsc.localize(dt.timestamp());
+            assert_eq!((local.hour(), local.minute()), (9, 30));
+            assert_eq!(local, dt);
+        }
+    }
+}
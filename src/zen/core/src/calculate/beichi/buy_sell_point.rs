@@ -1,12 +1,18 @@
+// #[pymethods] 生成的包装函数误报 useless_conversion，原因见 store.rs
+#![allow(clippy::useless_conversion)]
+
 use crate::analyze::CZSC;
 use crate::element::chan::Bar;
-use crate::element::chan::{NewBar, DT};
+use crate::element::chan::{NewBar, BI, DT};
 use crate::element::enums::Direction;
 use crate::element::event::{Signal, ZS};
+use crate::utils::clock;
 use crate::utils::notify::Notify;
-use chrono::{FixedOffset, Local, TimeZone, Utc};
-use chrono_tz::Tz;
+use chrono::{TimeZone, Utc};
 use dict_derive::{FromPyObject, IntoPyObject};
+use pyo3::basic::CompareOp;
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
 use pyo3::{pyclass, pymethods};
 use serde::Serialize;
 use std::cmp::PartialEq;
@@ -15,7 +21,10 @@ use std::rc::Rc;
 use tracing::debug;
 
 #[derive(Eq, PartialEq, Serialize, Debug, Clone)]
-enum PointType {
+#[pyclass(eq, eq_int)]
+pub(crate) enum PointType {
+    // 盘整背驰，没有前一个中枢
+    #[pyo3(name = "Other")]
     None,
     FirstBuy,
     SecondBuy,
@@ -26,60 +35,236 @@ enum PointType {
 }
 
 #[derive(Serialize, Debug, Clone, Eq, PartialEq)]
-#[pyclass]
-enum BeichiType {
+#[pyclass(eq, eq_int)]
+pub(crate) enum BeichiType {
     Area,
     Diff,
     ZsZs,
     ZsLzs,
 }
 
-#[derive(Serialize, Debug, Clone)]
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[pyclass(frozen)]
 pub struct ZSInfo {
-    left: i64,
+    pub(crate) left: i64,
     pub(crate) right: i64,
-    high: f32,
-    low: f32,
-    bi_count: u32,
+    pub(crate) high: f32,
+    pub(crate) low: f32,
+    pub(crate) bi_count: u32,
 }
 
-#[derive(Serialize, Debug, Clone)]
+#[derive(Serialize, Debug, Clone, PartialEq)]
 #[pyclass]
 pub struct BSPoint {
-    direction: Direction,
-    r#type: PointType,
-    bc_type: Vec<BeichiType>,
+    pub(crate) direction: Direction,
+    pub(crate) r#type: PointType,
+    pub(crate) bc_type: Vec<BeichiType>,
     pub(crate) zs2: ZSInfo,
-    zs1: Option<ZSInfo>,
-    fake_bi: bool,
-    macd_a_dt: i64,
-    macd_a_val: f32,
+    pub(crate) zs1: Option<ZSInfo>,
+    pub(crate) fake_bi: bool,
+    pub(crate) macd_a_dt: i64,
+    pub(crate) macd_a_val: f32,
     pub(crate) macd_b_dt: i64,
-    macd_b_val: f32,
-    dt: i64,
-    price: f32,
-    bi_count: i32,
+    pub(crate) macd_b_val: f32,
+    pub(crate) dt: i64,
+    pub(crate) price: f32,
+    pub(crate) bi_count: i32,
+}
+
+pub(crate) fn ts_to_dt(ts: i64) -> DT {
+    Utc.timestamp_opt(ts, 0).unwrap().fixed_offset()
 }
 
 #[pymethods]
+impl ZSInfo {
+    // 中枢开始时间
+    #[getter]
+    fn left(&self) -> DT {
+        ts_to_dt(self.left)
+    }
+    // 中枢结束时间
+    #[getter]
+    fn right(&self) -> DT {
+        ts_to_dt(self.right)
+    }
+    // 中枢上沿
+    #[getter]
+    fn high(&self) -> f32 {
+        self.high
+    }
+    // 中枢下沿
+    #[getter]
+    fn low(&self) -> f32 {
+        self.low
+    }
+    #[getter]
+    fn bi_count(&self) -> u32 {
+        self.bi_count
+    }
+
+    fn to_dict<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let dict = PyDict::new_bound(py);
+        dict.set_item("left", self.left())?;
+        dict.set_item("right", self.right())?;
+        dict.set_item("high", self.high)?;
+        dict.set_item("low", self.low)?;
+        dict.set_item("bi_count", self.bi_count)?;
+        Ok(dict)
+    }
+
+    fn __eq__(&self, other: &Self) -> bool {
+        self == other
+    }
+
+    fn __repr__(&self) -> String {
+        format!("{:?}", self)
+    }
+}
+
+// 按笔依次划分中枢：进入段之后连续三笔有重叠即成中枢，之后与中枢区间重叠的笔继续延伸，
+// 离开中枢的笔作为下一个中枢的进入段
+pub(crate) fn zs_list(bis: &[BI]) -> Vec<ZSInfo> {
+    let mut result = vec![];
+    let mut i = 1;
+    while i + 3 <= bis.len() {
+        let zs = ZS::new(&bis[i - 1..i + 3]);
+        let (high, low) = (zs.zg(), zs.zd());
+        if high < low {
+            i += 1;
+            continue;
+        }
+        let mut end = i + 3;
+        while end < bis.len() && bis[end].low() <= high && bis[end].high() >= low {
+            end += 1;
+        }
+        result.push(ZSInfo {
+            left: bis[i].fx_a.dt.timestamp(),
+            right: bis[end - 1].fx_b.dt.timestamp(),
+            high,
+            low,
+            bi_count: (end - i) as u32,
+        });
+        i = end + 1;
+    }
+    result
+}
+
 impl BSPoint {
+    // 按时间排序，同一时间按中枢位置排序
+    fn sort_key(&self) -> (i64, i64, i64) {
+        (self.dt, self.zs2.left, self.zs2.right)
+    }
+}
+
+#[pymethods]
+impl BSPoint {
+    #[getter]
+    fn direction(&self) -> Direction {
+        self.direction.clone()
+    }
+    #[getter]
+    fn point_type(&self) -> PointType {
+        self.r#type.clone()
+    }
+    #[getter]
+    fn bc_type(&self) -> Vec<BeichiType> {
+        self.bc_type.clone()
+    }
+    // 背驰段所在中枢
+    #[getter]
+    fn zs2(&self) -> ZSInfo {
+        self.zs2.clone()
+    }
+    // 前一个中枢，盘整背驰时为 None
+    #[getter]
+    fn zs1(&self) -> Option<ZSInfo> {
+        self.zs1.clone()
+    }
+    #[getter]
+    fn fake_bi(&self) -> bool {
+        self.fake_bi
+    }
+    // 进入段 MACD 柱极值所在K线 (dt, macd)
+    #[getter]
+    fn macd_a(&self) -> (DT, f32) {
+        (ts_to_dt(self.macd_a_dt), self.macd_a_val)
+    }
+    // 离开段 MACD 柱极值所在K线 (dt, macd)，推笔时为 0
+    #[getter]
+    fn macd_b(&self) -> (DT, f32) {
+        (ts_to_dt(self.macd_b_dt), self.macd_b_val)
+    }
+    #[getter]
+    fn dt(&self) -> DT {
+        ts_to_dt(self.dt)
+    }
+    #[getter]
+    fn price(&self) -> f32 {
+        self.price
+    }
+    #[getter]
+    fn bi_count(&self) -> i32 {
+        self.bi_count
+    }
+
+    fn to_dict<'py>(&self, py: Python<'py>) -> PyResult<Bound<'py, PyDict>> {
+        let dict = PyDict::new_bound(py);
+        dict.set_item("direction", self.direction.clone().into_py(py))?;
+        dict.set_item("point_type", self.r#type.clone().into_py(py))?;
+        dict.set_item("bc_type", self.bc_type.clone().into_py(py))?;
+        dict.set_item("zs2", self.zs2.to_dict(py)?)?;
+        dict.set_item(
+            "zs1",
+            self.zs1.as_ref().map(|z| z.to_dict(py)).transpose()?,
+        )?;
+        dict.set_item("fake_bi", self.fake_bi)?;
+        dict.set_item("macd_a", self.macd_a())?;
+        dict.set_item("macd_b", self.macd_b())?;
+        dict.set_item("dt", self.dt())?;
+        dict.set_item("price", self.price)?;
+        dict.set_item("bi_count", self.bi_count)?;
+        Ok(dict)
+    }
+
+    // 比较与排序使用同一个 key，同一时间、同一中枢的买卖点视为相等
+    fn __richcmp__(&self, other: &Self, op: CompareOp) -> bool {
+        op.matches(self.sort_key().cmp(&other.sort_key()))
+    }
+
     fn __str__(&self) -> String {
         serde_json::to_string(&self).unwrap()
     }
+
+    fn __repr__(&self) -> String {
+        format!("{:?}", self)
+    }
 }
 pub struct BuySellPoint {
     pub beichi_tracker: Vec<BSPoint>,
     last_bi_start_dt: DT,
+    // 自上次 take_changes 以来新增及被撤销的买卖点，供信号日志记录
+    added: Vec<BSPoint>,
+    removed: Vec<BSPoint>,
 }
 
 impl BuySellPoint {
     pub fn new() -> Self {
         Self {
             beichi_tracker: vec![],
-            last_bi_start_dt: Local::now().fixed_offset(),
+            last_bi_start_dt: clock::now(),
+            added: vec![],
+            removed: vec![],
         }
     }
 
+    // 取出新增及被撤销的买卖点
+    pub fn take_changes(&mut self) -> (Vec<BSPoint>, Vec<BSPoint>) {
+        (
+            std::mem::take(&mut self.added),
+            std::mem::take(&mut self.removed),
+        )
+    }
+
     pub fn process(
         &mut self,
         czsc: &mut CZSC,
@@ -91,11 +276,15 @@ impl BuySellPoint {
             .bi_list
             .last()
             .map(|b| b.fx_b.dt)
-            .unwrap_or(Local::now().fixed_offset())
+            .unwrap_or(clock::now())
             == self.last_bi_start_dt
         {
-            self.beichi_tracker
-                .retain(|bc| bc.zs2.right != self.last_bi_start_dt.timestamp());
+            let last = self.last_bi_start_dt.timestamp();
+            let (removed, kept): (Vec<_>, Vec<_>) = std::mem::take(&mut self.beichi_tracker)
+                .into_iter()
+                .partition(|bc| bc.zs2.right == last);
+            self.beichi_tracker = kept;
+            self.removed.extend(removed);
         }
 
         let bs = self.calculate(czsc, 0);
@@ -121,12 +310,7 @@ impl BuySellPoint {
                     format!("{}笔", bs.zs2.bi_count + 2),
                     "other".to_string(),
                 ),
-                dt: Some(
-                    Utc.timestamp_opt(bs.dt, 0)
-                        .unwrap()
-                        .fixed_offset()
-                        .with_timezone(&FixedOffset::east_opt(8 * 3600).unwrap()),
-                ),
+                dt: Some(czsc.localize(bs.dt)),
                 figure: if bs.bc_type.contains(&BeichiType::Diff) {
                     100.0
                 } else {
@@ -138,6 +322,7 @@ impl BuySellPoint {
                 //Notify::notify_signal(&czsc.symbol, signal.dt.unwrap(), signal.clone());
             }
             result.push(signal);
+            self.added.push(bs.clone());
             self.beichi_tracker.push(bs);
         }
         czsc.bi_list
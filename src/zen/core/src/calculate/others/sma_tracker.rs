@@ -1,5 +1,5 @@
 use crate::calculate::r#trait::Processor;
-use crate::calculate::zen_cache::SMATrackerCache;
+use crate::calculate::zen_cache::{SMATrackerCache, SMAValuesCache};
 use std::collections::HashMap;
 use crate::talipp::indicator::sma::SMA;
 use crate::talipp::indicator::Indicator;
@@ -13,6 +13,10 @@ pub struct SMATracker {
     pub store: HashMap<isize, SMA>,
 }
 
+// 某根K线收盘时各周期的均线值，key 为周期，只记录已满周期的均线
+#[derive(Debug, Clone, Default)]
+pub struct SMAValues(pub HashMap<isize, f32>);
+
 impl SMATracker {
     pub fn new(periods: Vec<isize>) -> Self {
         let mut tracker = SMATracker {
@@ -24,6 +28,27 @@ impl SMATracker {
         }
         tracker
     }
+
+    pub fn periods(&self) -> &[isize] {
+        &self.periods
+    }
+
+    // 当前均线值，未满周期时返回 None
+    pub fn ma(&self, period: isize) -> Option<f32> {
+        self.store
+            .get(&period)
+            .filter(|sma| sma.is_ready())
+            .map(|sma| sma.ma())
+    }
+
+    pub fn values(&self) -> SMAValues {
+        SMAValues(
+            self.periods
+                .iter()
+                .filter_map(|p| self.ma(*p).map(|v| (*p, v)))
+                .collect(),
+        )
+    }
 }
 
 pub fn process(czsc: &mut CZSC, is_new: bool, start: Option<(Bar, Direction)>) -> Vec<Signal> {
@@ -39,6 +64,12 @@ pub fn process(czsc: &mut CZSC, is_new: bool, start: Option<(Bar, Direction)>) -
             Some(())
         });
     }
+    czsc.bars_raw
+        .last()
+        .unwrap()
+        .borrow_mut()
+        .cache
+        .insert::<SMAValuesCache>(smas.values());
 
     vec![]
 }
@@ -1,3 +1,5 @@
-use crate::calculate::others::sma_tracker::SMATracker;
+use crate::calculate::others::sma_tracker::{SMATracker, SMAValues};
 
 pub type SMATrackerCache = SMATracker;
+// 缓存在原始K线上的均线值
+pub type SMAValuesCache = SMAValues;
@@ -0,0 +1,489 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+
+use chrono::{Datelike, Days, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Weekday};
+use chrono_tz::Tz;
+use pyo3::exceptions::PyValueError;
+use pyo3::{pyclass, pymethods, PyResult};
+use serde::Deserialize;
+
+use crate::element::chan::{Bar, DT};
+use crate::element::enums::Freq;
+
+// 内置的休市日数据
+const BUILTIN: &str = include_str!("../data/calendar.yaml");
+
+// 向后查找交易日的最大天数，覆盖最长的长假
+const MAX_LOOKAHEAD_DAYS: u64 = 30;
+
+#[pyclass(eq, eq_int)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+pub enum Market {
+    // A 股
+    CN,
+    // 港股
+    HK,
+    // 美股
+    US,
+}
+
+impl Market {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Market::CN => "CN",
+            Market::HK => "HK",
+            Market::US => "US",
+        }
+    }
+
+    // 按交易所时区推断市场，如 Zen 的时区
+    pub fn from_tz(tz: Tz) -> Option<Self> {
+        match tz {
+            Tz::Asia__Shanghai => Some(Market::CN),
+            Tz::Asia__Hong_Kong => Some(Market::HK),
+            Tz::America__New_York => Some(Market::US),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct MarketData {
+    holidays: Vec<String>,
+    early_closes: HashMap<String, String>,
+}
+
+fn time(s: &str) -> NaiveTime {
+    NaiveTime::parse_from_str(s, "%H:%M").unwrap()
+}
+
+// 交易日历：常规交易时段（含午休）、休市日、提前收市及美股盘前盘后
+#[pyclass(frozen, name = "Calendar")]
+#[derive(Debug, Clone)]
+pub struct Calendar {
+    market: Market,
+    tz: Tz,
+    // 常规交易时段，时段之间的间隔即午休
+    sessions: Vec<(NaiveTime, NaiveTime)>,
+    // 盘前、盘后，仅美股
+    pre_market: Option<(NaiveTime, NaiveTime)>,
+    post_market: Option<(NaiveTime, NaiveTime)>,
+    holidays: HashSet<NaiveDate>,
+    // 提前收市日的收市时间
+    early_closes: HashMap<NaiveDate, NaiveTime>,
+}
+
+impl Calendar {
+    // 使用内置休市日数据
+    pub fn new(market: Market) -> Self {
+        Self::from_yaml(market, BUILTIN).expect("builtin calendar data is valid")
+    }
+
+    pub fn from_file(market: Market, path: &str) -> Result<Self, String> {
+        let content = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        Self::from_yaml(market, &content)
+    }
+
+    fn from_yaml(market: Market, content: &str) -> Result<Self, String> {
+        let mut all: HashMap<String, MarketData> =
+            serde_yaml::from_str(content).map_err(|e| e.to_string())?;
+        let data = all.remove(market.as_str()).unwrap_or_default();
+        let date = |s: &str| {
+            NaiveDate::parse_from_str(s, "%Y-%m-%d")
+                .map_err(|e| format!("invalid date `{}`: {}", s, e))
+        };
+        let holidays = data
+            .holidays
+            .iter()
+            .map(|d| date(d))
+            .collect::<Result<_, _>>()?;
+        let early_closes = data
+            .early_closes
+            .iter()
+            .map(|(d, t)| {
+                let t = NaiveTime::parse_from_str(t, "%H:%M")
+                    .map_err(|e| format!("invalid time `{}`: {}", t, e))?;
+                Ok((date(d)?, t))
+            })
+            .collect::<Result<_, String>>()?;
+
+        let (tz, sessions, pre_market, post_market) = match market {
+            Market::CN => (
+                Tz::Asia__Shanghai,
+                vec![
+                    (time("09:30"), time("11:30")),
+                    (time("13:00"), time("15:00")),
+                ],
+                None,
+                None,
+            ),
+            Market::HK => (
+                Tz::Asia__Hong_Kong,
+                vec![
+                    (time("09:30"), time("12:00")),
+                    (time("13:00"), time("16:00")),
+                ],
+                None,
+                None,
+            ),
+            Market::US => (
+                Tz::America__New_York,
+                vec![(time("09:30"), time("16:00"))],
+                Some((time("04:00"), time("09:30"))),
+                Some((time("16:00"), time("20:00"))),
+            ),
+        };
+        Ok(Self {
+            market,
+            tz,
+            sessions,
+            pre_market,
+            post_market,
+            holidays,
+            early_closes,
+        })
+    }
+
+    pub fn tz(&self) -> Tz {
+        self.tz
+    }
+
+    pub fn is_trading_day(&self, date: NaiveDate) -> bool {
+        !matches!(date.weekday(), Weekday::Sat | Weekday::Sun) && !self.holidays.contains(&date)
+    }
+
+    // 某个交易日的常规交易时段，已按提前收市截断
+    pub fn sessions_on(&self, date: NaiveDate) -> Vec<(NaiveTime, NaiveTime)> {
+        if !self.is_trading_day(date) {
+            return vec![];
+        }
+        let close = self.early_closes.get(&date).copied();
+        self.sessions
+            .iter()
+            .filter(|(start, _)| close.map(|c| *start < c).unwrap_or(true))
+            .map(|(start, end)| (*start, close.map(|c| c.min(*end)).unwrap_or(*end)))
+            .collect()
+    }
+
+    fn local(&self, date: NaiveDate, t: NaiveTime) -> DT {
+        self.tz
+            .from_local_datetime(&NaiveDateTime::new(date, t))
+            .earliest()
+            .unwrap()
+            .fixed_offset()
+    }
+
+    // extended 为 true 时包含盘前盘后
+    pub fn is_open(&self, dt: DT, extended: bool) -> bool {
+        let local = dt.with_timezone(&self.tz).naive_local();
+        let (date, t) = (local.date(), local.time());
+        let sessions = self.sessions_on(date);
+        if sessions.iter().any(|(start, end)| *start <= t && t < *end) {
+            return true;
+        }
+        if !extended || sessions.is_empty() {
+            return false;
+        }
+        let close = sessions.last().unwrap().1;
+        let pre = self
+            .pre_market
+            .map(|(s, e)| s <= t && t < e)
+            .unwrap_or(false);
+        // 提前收市日盘后从收市开始
+        let post = self
+            .post_market
+            .map(|(_, e)| close <= t && t < e)
+            .unwrap_or(false);
+        pre || post
+    }
+
+    // dt 时刻或之后最近的开盘时间，正在交易时返回 dt
+    pub fn next_open(&self, dt: DT, extended: bool) -> Option<DT> {
+        let local = dt.with_timezone(&self.tz).naive_local();
+        for i in 0..MAX_LOOKAHEAD_DAYS {
+            let date = local.date().checked_add_days(Days::new(i))?;
+            let mut sessions = self.sessions_on(date);
+            if sessions.is_empty() {
+                continue;
+            }
+            if extended {
+                let close = sessions.last().unwrap().1;
+                sessions.extend(self.pre_market);
+                sessions.extend(self.post_market.map(|(_, e)| (close, e)));
+                sessions.sort();
+            }
+            for (start, end) in sessions {
+                let (start, end) = (self.local(date, start), self.local(date, end));
+                if dt < start {
+                    return Some(start);
+                }
+                if dt < end {
+                    return Some(dt);
+                }
+            }
+        }
+        None
+    }
+
+    // dt 所属K线的结束时间（K线以结束时间标记），不在交易时段内时为 None；
+    // 分钟级别按交易分钟切分并跳过午休，日线为当日收市，周、月、季、年线为周期内最后一个交易日收市
+    pub fn session_bucket(&self, dt: DT, freq: Freq) -> Option<DT> {
+        let local = dt.with_timezone(&self.tz).naive_local();
+        let (date, t) = (local.date(), local.time());
+        let sessions = self.sessions_on(date);
+        if let Some(minutes) = freq.minutes() {
+            let span = minutes as i64 * 60;
+            let mut elapsed = None;
+            let mut total = 0;
+            for (start, end) in &sessions {
+                if elapsed.is_none() && *start <= t && t <= *end {
+                    // 开盘时刻（含午后开盘）的K线归入该时段的第一根，收盘时刻归入最后一根
+                    elapsed = Some(total + (t - *start).num_seconds().max(1));
+                }
+                total += (*end - *start).num_seconds();
+            }
+            let elapsed = elapsed?;
+            let index = (elapsed + span - 1) / span;
+            let mut target = (index * span).min(total);
+            for (start, end) in &sessions {
+                let duration = (*end - *start).num_seconds();
+                if target <= duration {
+                    return Some(self.local(date, *start + chrono::Duration::seconds(target)));
+                }
+                target -= duration;
+            }
+            return None;
+        }
+
+        let close_on = |d: NaiveDate| self.sessions_on(d).last().map(|(_, e)| self.local(d, *e));
+        if !matches!(freq, Freq::D | Freq::W | Freq::M | Freq::S | Freq::Y)
+            || !self.is_trading_day(date)
+        {
+            return None;
+        }
+        let same_period = |a: NaiveDate, b: NaiveDate| match freq {
+            Freq::D => a == b,
+            Freq::W => a.iso_week() == b.iso_week(),
+            Freq::M => (a.year(), a.month()) == (b.year(), b.month()),
+            Freq::S => (a.year(), a.month0() / 3) == (b.year(), b.month0() / 3),
+            Freq::Y => a.year() == b.year(),
+            _ => false,
+        };
+        // 逐日向后查找周期内最后一个交易日
+        let mut last = date;
+        let mut d = date;
+        while let Some(next) = d.succ_opt() {
+            if !same_period(date, next) {
+                break;
+            }
+            if self.is_trading_day(next) {
+                last = next;
+            }
+            d = next;
+        }
+        close_on(last)
+    }
+
+    // 按交易时段把低级别K线合成 freq 级别，K线时间为所属时段的结束时间，最后一根可能尚未走完；
+    // 交易时段外的K线丢弃
+    pub fn aggregate(&self, bars: &[Bar], freq: Freq) -> Vec<Bar> {
+        let mut result: Vec<Bar> = vec![];
+        for bar in bars {
+            let Some(end) = self.session_bucket(bar.dt, freq) else {
+                continue;
+            };
+            match result.last_mut() {
+                Some(last) if last.dt == end => {
+                    last.high = last.high.max(bar.high);
+                    last.low = last.low.min(bar.low);
+                    last.close = bar.close;
+                    last.vol += bar.vol;
+                    last.amount += bar.amount;
+                }
+                _ => result.push(Bar {
+                    dt: end,
+                    freq,
+                    ..bar.clone()
+                }),
+            }
+        }
+        result
+    }
+}
+
+#[pymethods]
+impl Calendar {
+    // path 为休市日数据文件，缺省时使用内置数据
+    #[new]
+    #[pyo3(signature = (market, path=None))]
+    fn py_new(market: Market, path: Option<&str>) -> PyResult<Self> {
+        match path {
+            Some(path) => Calendar::from_file(market, path).map_err(PyValueError::new_err),
+            None => Ok(Calendar::new(market)),
+        }
+    }
+
+    #[getter]
+    fn market(&self) -> Market {
+        self.market
+    }
+
+    #[getter]
+    #[pyo3(name = "tz")]
+    fn py_tz(&self) -> &'static str {
+        self.tz.name()
+    }
+
+    #[pyo3(name = "is_trading_day")]
+    fn py_is_trading_day(&self, date: NaiveDate) -> bool {
+        self.is_trading_day(date)
+    }
+
+    #[pyo3(name = "is_open", signature = (dt, extended=false))]
+    fn py_is_open(&self, dt: DT, extended: bool) -> bool {
+        self.is_open(dt, extended)
+    }
+
+    #[pyo3(name = "next_open", signature = (dt, extended=false))]
+    fn py_next_open(&self, dt: DT, extended: bool) -> Option<DT> {
+        self.next_open(dt, extended)
+    }
+
+    #[pyo3(name = "session_bucket")]
+    fn py_session_bucket(&self, dt: DT, freq: Freq) -> Option<DT> {
+        self.session_bucket(dt, freq)
+    }
+
+    // bars 须按时间升序
+    #[pyo3(name = "aggregate")]
+    fn py_aggregate(&self, bars: Vec<Bar>, freq: Freq) -> Vec<Bar> {
+        self.aggregate(&bars, freq)
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Calendar({:?}, {})", self.market, self.tz.name())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(cal: &Calendar, s: &str) -> DT {
+        let local = NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M").unwrap();
+        cal.tz.from_local_datetime(&local).unwrap().fixed_offset()
+    }
+
+    fn fmt(dt: Option<DT>) -> String {
+        dt.map(|d| d.format("%Y-%m-%d %H:%M %z").to_string())
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn cn_lunch_break_and_holiday() {
+        let cal = Calendar::new(Market::CN);
+        assert!(cal.is_open(at(&cal, "2024-09-18 10:00"), false));
+        assert!(!cal.is_open(at(&cal, "2024-09-18 12:00"), false));
+        // 中秋休市
+        assert!(!cal.is_open(at(&cal, "2024-09-17 10:00"), false));
+        assert_eq!(
+            fmt(cal.next_open(at(&cal, "2024-09-18 12:00"), false)),
+            "2024-09-18 13:00 +0800"
+        );
+        assert_eq!(
+            fmt(cal.next_open(at(&cal, "2024-09-13 15:30"), false)),
+            "2024-09-18 09:30 +0800"
+        );
+        // 60 分钟线：10:30、11:30、14:00、15:00
+        for (t, bucket) in [
+            ("09:30", "10:30"),
+            ("10:31", "11:30"),
+            ("11:30", "11:30"),
+            ("13:00", "14:00"),
+            ("13:01", "14:00"),
+            ("14:59", "15:00"),
+        ] {
+            assert_eq!(
+                fmt(cal.session_bucket(at(&cal, &format!("2024-09-18 {}", t)), Freq::F60)),
+                format!("2024-09-18 {} +0800", bucket)
+            );
+        }
+        assert_eq!(
+            cal.session_bucket(at(&cal, "2024-09-18 12:00"), Freq::F5),
+            None
+        );
+        // 周线为本周最后一个交易日收市
+        assert_eq!(
+            fmt(cal.session_bucket(at(&cal, "2024-09-30 10:00"), Freq::W)),
+            "2024-09-30 15:00 +0800"
+        );
+    }
+
+    #[test]
+    fn aggregate_by_session() {
+        let cal = Calendar::new(Market::CN);
+        // 午休中的 12:00 不在交易时段内，丢弃
+        let times = [
+            "09:31", "09:45", "11:30", "12:00", "13:01", "13:05", "15:00",
+        ];
+        let bars: Vec<Bar> = times
+            .iter()
+            .zip(crate::tests::zigzag(&[10.0, 16.0], times.len() - 1))
+            .map(|(t, bar)| Bar {
+                dt: at(&cal, &format!("2024-09-18 {}", t)),
+                freq: Freq::F1,
+                ..bar
+            })
+            .collect();
+        let merged = cal.aggregate(&bars, Freq::F30);
+        let ends: Vec<_> = merged.iter().map(|b| fmt(Some(b.dt))).collect();
+        assert_eq!(
+            ends,
+            ["10:00", "11:30", "13:30", "15:00"].map(|t| format!("2024-09-18 {} +0800", t))
+        );
+        assert!(merged.iter().all(|b| b.freq == Freq::F30));
+        // 09:31 与 09:45 合成 10:00 的K线
+        let (minutes, bar) = (&bars[..2], &merged[0]);
+        assert_eq!((bar.open, bar.close), (minutes[0].open, minutes[1].close));
+        assert_eq!(bar.high, minutes[0].high.max(minutes[1].high));
+        assert_eq!(bar.vol, minutes[0].vol + minutes[1].vol);
+    }
+
+    #[test]
+    fn us_dst_early_close_and_extended() {
+        let cal = Calendar::new(Market::US);
+        // 夏令时与冬令时的开盘
+        assert_eq!(
+            fmt(cal.next_open(at(&cal, "2024-07-01 08:00"), false)),
+            "2024-07-01 09:30 -0400"
+        );
+        assert_eq!(
+            fmt(cal.next_open(at(&cal, "2024-12-02 08:00"), false)),
+            "2024-12-02 09:30 -0500"
+        );
+        // 感恩节次日 13:00 收市，之后为盘后
+        assert!(!cal.is_open(at(&cal, "2024-11-29 14:00"), false));
+        assert!(cal.is_open(at(&cal, "2024-11-29 14:00"), true));
+        assert!(cal.is_open(at(&cal, "2024-07-01 05:00"), true));
+        assert_eq!(
+            fmt(cal.session_bucket(at(&cal, "2024-11-29 12:10"), Freq::F120)),
+            "2024-11-29 13:00 -0500"
+        );
+        assert_eq!(
+            fmt(cal.next_open(at(&cal, "2024-07-03 20:00"), false)),
+            "2024-07-05 09:30 -0400"
+        );
+    }
+
+    #[test]
+    fn hk_half_day() {
+        let cal = Calendar::new(Market::HK);
+        assert!(cal.is_open(at(&cal, "2024-12-24 11:00"), false));
+        assert!(!cal.is_open(at(&cal, "2024-12-24 13:30"), false));
+        assert_eq!(
+            fmt(cal.session_bucket(at(&cal, "2024-12-24 11:00"), Freq::D)),
+            "2024-12-24 12:00 +0800"
+        );
+    }
+}
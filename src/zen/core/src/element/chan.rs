@@ -3,9 +3,10 @@ use std::cell::RefCell;
 use std::rc::Rc;
 use anymap3::{Map};
 
-use chrono::{DateTime, FixedOffset, Local};
+use chrono::{DateTime, FixedOffset};
 use pyo3::prelude::*;
 use super::enums::{Direction, Freq, Mark};
+use crate::utils::clock;
 
 pub type GenericCache = Map<dyn Any+Send>;
 pub type DT = DateTime<FixedOffset>;
@@ -90,7 +91,7 @@ pub struct NewBar {
 impl Default for NewBar {
     fn default() -> Self {
         Self {
-            dt: Local::now().fixed_offset(),
+            dt: clock::now(),
             freq: Freq::Tick,
             open: 0.0,
             close: 0.0,
@@ -1,6 +1,7 @@
 use pyo3::pyclass;
 use serde::{Serialize, Serializer};
 
+#[pyclass(eq, eq_int)]
 #[derive(PartialEq, Debug, Clone)]
 pub enum Direction {
     Up,
@@ -27,6 +28,7 @@ impl Serialize for Direction {
     }
 }
 
+#[pyclass(eq, eq_int)]
 #[derive(Debug, Clone, PartialEq)]
 pub enum Mark {
     D,
@@ -94,4 +96,26 @@ impl Freq {
             Freq::Y => "Y",
         }
     }
+
+    // 分钟级别的分钟数，其余为 None
+    pub fn minutes(&self) -> Option<u32> {
+        match self {
+            Freq::F1 => Some(1),
+            Freq::F2 => Some(2),
+            Freq::F3 => Some(3),
+            Freq::F4 => Some(4),
+            Freq::F5 => Some(5),
+            Freq::F6 => Some(6),
+            Freq::F10 => Some(10),
+            Freq::F12 => Some(12),
+            Freq::F15 => Some(15),
+            Freq::F20 => Some(20),
+            Freq::F30 => Some(30),
+            Freq::F60 => Some(60),
+            Freq::F120 => Some(120),
+            Freq::F240 => Some(240),
+            Freq::F480 => Some(480),
+            _ => None,
+        }
+    }
 }
@@ -1,11 +1,12 @@
 use std::collections::HashMap;
 use std::fmt::{Debug, Formatter, Write};
-use chrono::{Local, Utc};
+use chrono::Utc;
 use pyo3::pyclass;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use serde_yaml::Error;
 
 use crate::element::chan::{GenericCache, BI, DT};
+use crate::utils::clock;
 
 #[pyclass]
 #[derive(Deserialize, Serialize, Clone)]
@@ -331,7 +332,7 @@ impl Matcher {
                 continue;
             }
 
-            let mut dt = Local::now().fixed_offset();
+            let mut dt = clock::now();
             let mut br = false;
             if let Some(signals_all) = &event.signals_all {
                 for s in signals_all {
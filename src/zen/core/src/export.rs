@@ -0,0 +1,339 @@
+use std::rc::Rc;
+use std::sync::Arc;
+
+use arrow::array::{
+    ArrayRef, BooleanArray, Float32Array, RecordBatch, StringArray, TimestampSecondArray,
+    UInt32Array,
+};
+use arrow::datatypes::{DataType, Field, Schema, TimeUnit};
+
+use crate::analyze::CZSC;
+use crate::calculate::beichi::buy_sell_point::{zs_list, BSPoint};
+use crate::element::chan::{NewBar, FX};
+use crate::element::enums::Direction;
+
+// 列式导出缠论元素，供 pandas / polars 零拷贝使用，时间列统一为 UTC 秒级时间戳
+
+fn ts_field(name: &str, nullable: bool) -> Field {
+    Field::new(
+        name,
+        DataType::Timestamp(TimeUnit::Second, Some("UTC".into())),
+        nullable,
+    )
+}
+
+fn ts_array(values: Vec<i64>) -> ArrayRef {
+    Arc::new(TimestampSecondArray::from(values).with_timezone("UTC"))
+}
+
+fn f32_array(values: Vec<f32>) -> ArrayRef {
+    Arc::new(Float32Array::from(values))
+}
+
+fn str_array(values: Vec<&str>) -> ArrayRef {
+    Arc::new(StringArray::from(values))
+}
+
+fn direction_str(direction: &Direction) -> &'static str {
+    match direction {
+        Direction::Up => "up",
+        Direction::Down => "down",
+    }
+}
+
+fn batch(fields: Vec<Field>, columns: Vec<ArrayRef>) -> RecordBatch {
+    RecordBatch::try_new(Arc::new(Schema::new(fields)), columns).expect("schema matches columns")
+}
+
+// 原始K线及 MACD(4, 9, 9)
+pub fn bars(czsc: &CZSC) -> RecordBatch {
+    let bars: Vec<_> = czsc.bars_raw.iter().map(|b| b.borrow()).collect();
+    batch(
+        vec![
+            ts_field("dt", false),
+            Field::new("open", DataType::Float32, false),
+            Field::new("close", DataType::Float32, false),
+            Field::new("high", DataType::Float32, false),
+            Field::new("low", DataType::Float32, false),
+            Field::new("vol", DataType::Float32, false),
+            Field::new("amount", DataType::Float32, false),
+            Field::new("macd_dif", DataType::Float32, false),
+            Field::new("macd_dea", DataType::Float32, false),
+            Field::new("macd", DataType::Float32, false),
+        ],
+        vec![
+            ts_array(bars.iter().map(|b| b.dt.timestamp()).collect()),
+            f32_array(bars.iter().map(|b| b.open).collect()),
+            f32_array(bars.iter().map(|b| b.close).collect()),
+            f32_array(bars.iter().map(|b| b.high).collect()),
+            f32_array(bars.iter().map(|b| b.low).collect()),
+            f32_array(bars.iter().map(|b| b.vol).collect()),
+            f32_array(bars.iter().map(|b| b.amount).collect()),
+            f32_array(bars.iter().map(|b| b.macd_4_9_9.0).collect()),
+            f32_array(bars.iter().map(|b| b.macd_4_9_9.1).collect()),
+            f32_array(bars.iter().map(|b| b.macd_4_9_9.2).collect()),
+        ],
+    )
+}
+
+// 去除包含关系后的K线，bi_index 为所属笔的序号，未完成笔区域为空
+pub fn new_bars(czsc: &CZSC) -> RecordBatch {
+    // 相邻笔共享分型K线，按时间去重
+    let mut rows: Vec<(&Rc<NewBar>, Option<u32>)> = vec![];
+    let sections = czsc
+        .bi_list
+        .iter()
+        .enumerate()
+        .map(|(i, bi)| (&bi.bars, Some(i as u32)))
+        .chain(std::iter::once((&czsc.bars_ubi, None)));
+    for (bars, bi_index) in sections {
+        for bar in bars {
+            if rows.last().map(|(b, _)| bar.dt > b.dt).unwrap_or(true) {
+                rows.push((bar, bi_index));
+            }
+        }
+    }
+
+    batch(
+        vec![
+            ts_field("dt", false),
+            Field::new("open", DataType::Float32, false),
+            Field::new("close", DataType::Float32, false),
+            Field::new("high", DataType::Float32, false),
+            Field::new("low", DataType::Float32, false),
+            Field::new("vol", DataType::Float32, false),
+            Field::new("amount", DataType::Float32, false),
+            Field::new("raw_count", DataType::UInt32, false),
+            Field::new("bi_index", DataType::UInt32, true),
+        ],
+        vec![
+            ts_array(rows.iter().map(|(b, _)| b.dt.timestamp()).collect()),
+            f32_array(rows.iter().map(|(b, _)| b.open).collect()),
+            f32_array(rows.iter().map(|(b, _)| b.close).collect()),
+            f32_array(rows.iter().map(|(b, _)| b.high).collect()),
+            f32_array(rows.iter().map(|(b, _)| b.low).collect()),
+            f32_array(rows.iter().map(|(b, _)| b.vol).collect()),
+            f32_array(rows.iter().map(|(b, _)| b.amount).collect()),
+            Arc::new(UInt32Array::from(
+                rows.iter()
+                    .map(|(b, _)| b.raw_bars.len() as u32)
+                    .collect::<Vec<_>>(),
+            )),
+            Arc::new(UInt32Array::from(
+                rows.iter().map(|(_, i)| *i).collect::<Vec<_>>(),
+            )),
+        ],
+    )
+}
+
+// 已完成笔内的分型
+pub fn fxs(czsc: &CZSC) -> RecordBatch {
+    let mut rows: Vec<(&Rc<FX>, u32)> = vec![];
+    for (i, bi) in czsc.bi_list.iter().enumerate() {
+        for fx in &bi.fxs {
+            if rows.last().map(|(f, _)| fx.dt > f.dt).unwrap_or(true) {
+                rows.push((fx, i as u32));
+            }
+        }
+    }
+
+    batch(
+        vec![
+            ts_field("dt", false),
+            Field::new("mark", DataType::Utf8, false),
+            Field::new("high", DataType::Float32, false),
+            Field::new("low", DataType::Float32, false),
+            Field::new("fx", DataType::Float32, false),
+            Field::new("bi_index", DataType::UInt32, false),
+        ],
+        vec![
+            ts_array(rows.iter().map(|(f, _)| f.dt.timestamp()).collect()),
+            Arc::new(StringArray::from(
+                rows.iter()
+                    .map(|(f, _)| format!("{:?}", f.mark))
+                    .collect::<Vec<_>>(),
+            )),
+            f32_array(rows.iter().map(|(f, _)| f.high).collect()),
+            f32_array(rows.iter().map(|(f, _)| f.low).collect()),
+            f32_array(rows.iter().map(|(f, _)| f.fx).collect()),
+            Arc::new(UInt32Array::from(
+                rows.iter().map(|(_, i)| *i).collect::<Vec<_>>(),
+            )),
+        ],
+    )
+}
+
+// 已完成的笔
+pub fn bis(czsc: &CZSC) -> RecordBatch {
+    let bis = &czsc.bi_list;
+    batch(
+        vec![
+            ts_field("start_dt", false),
+            ts_field("end_dt", false),
+            Field::new("direction", DataType::Utf8, false),
+            Field::new("start", DataType::Float32, false),
+            Field::new("end", DataType::Float32, false),
+            Field::new("high", DataType::Float32, false),
+            Field::new("low", DataType::Float32, false),
+            Field::new("power_price", DataType::Float32, false),
+            Field::new("bar_count", DataType::UInt32, false),
+        ],
+        vec![
+            ts_array(bis.iter().map(|b| b.fx_a.dt.timestamp()).collect()),
+            ts_array(bis.iter().map(|b| b.fx_b.dt.timestamp()).collect()),
+            str_array(bis.iter().map(|b| direction_str(&b.direction)).collect()),
+            f32_array(bis.iter().map(|b| b.fx_a.fx).collect()),
+            f32_array(bis.iter().map(|b| b.fx_b.fx).collect()),
+            f32_array(bis.iter().map(|b| b.high()).collect()),
+            f32_array(bis.iter().map(|b| b.low()).collect()),
+            f32_array(bis.iter().map(|b| b.power_price()).collect()),
+            Arc::new(UInt32Array::from(
+                bis.iter().map(|b| b.bars.len() as u32).collect::<Vec<_>>(),
+            )),
+        ],
+    )
+}
+
+// 按笔划分的全部中枢
+pub fn zs(czsc: &CZSC) -> RecordBatch {
+    let rows = zs_list(&czsc.bi_list);
+
+    batch(
+        vec![
+            ts_field("left", false),
+            ts_field("right", false),
+            Field::new("high", DataType::Float32, false),
+            Field::new("low", DataType::Float32, false),
+            Field::new("bi_count", DataType::UInt32, false),
+        ],
+        vec![
+            ts_array(rows.iter().map(|z| z.left).collect()),
+            ts_array(rows.iter().map(|z| z.right).collect()),
+            f32_array(rows.iter().map(|z| z.high).collect()),
+            f32_array(rows.iter().map(|z| z.low).collect()),
+            Arc::new(UInt32Array::from(
+                rows.iter().map(|z| z.bi_count).collect::<Vec<_>>(),
+            )),
+        ],
+    )
+}
+
+// 买卖点，bc_type 以逗号拼接
+pub fn bs_points(points: &[BSPoint]) -> RecordBatch {
+    batch(
+        vec![
+            ts_field("dt", false),
+            Field::new("direction", DataType::Utf8, false),
+            Field::new("type", DataType::Utf8, false),
+            Field::new("bc_type", DataType::Utf8, false),
+            Field::new("price", DataType::Float32, false),
+            Field::new("fake_bi", DataType::Boolean, false),
+            ts_field("zs2_left", false),
+            ts_field("zs2_right", false),
+            ts_field("zs1_left", true),
+            ts_field("zs1_right", true),
+            ts_field("macd_a_dt", false),
+            Field::new("macd_a_val", DataType::Float32, false),
+            ts_field("macd_b_dt", false),
+            Field::new("macd_b_val", DataType::Float32, false),
+        ],
+        vec![
+            ts_array(points.iter().map(|p| p.dt).collect()),
+            str_array(points.iter().map(|p| direction_str(&p.direction)).collect()),
+            Arc::new(StringArray::from(
+                points
+                    .iter()
+                    .map(|p| format!("{:?}", p.r#type))
+                    .collect::<Vec<_>>(),
+            )),
+            Arc::new(StringArray::from(
+                points
+                    .iter()
+                    .map(|p| {
+                        p.bc_type
+                            .iter()
+                            .map(|t| format!("{:?}", t))
+                            .collect::<Vec<_>>()
+                            .join(",")
+                    })
+                    .collect::<Vec<_>>(),
+            )),
+            f32_array(points.iter().map(|p| p.price).collect()),
+            Arc::new(BooleanArray::from(
+                points.iter().map(|p| p.fake_bi).collect::<Vec<_>>(),
+            )),
+            ts_array(points.iter().map(|p| p.zs2.left).collect()),
+            ts_array(points.iter().map(|p| p.zs2.right).collect()),
+            Arc::new(
+                TimestampSecondArray::from(
+                    points
+                        .iter()
+                        .map(|p| p.zs1.as_ref().map(|z| z.left))
+                        .collect::<Vec<_>>(),
+                )
+                .with_timezone("UTC"),
+            ),
+            Arc::new(
+                TimestampSecondArray::from(
+                    points
+                        .iter()
+                        .map(|p| p.zs1.as_ref().map(|z| z.right))
+                        .collect::<Vec<_>>(),
+                )
+                .with_timezone("UTC"),
+            ),
+            ts_array(points.iter().map(|p| p.macd_a_dt).collect()),
+            f32_array(points.iter().map(|p| p.macd_a_val).collect()),
+            ts_array(points.iter().map(|p| p.macd_b_dt).collect()),
+            f32_array(points.iter().map(|p| p.macd_b_val).collect()),
+        ],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use arrow::array::AsArray;
+    use arrow::datatypes::{TimestampSecondType, UInt32Type};
+
+    use super::*;
+    use crate::element::enums::Freq;
+    use crate::setting::Settings;
+    use crate::tests::zigzag;
+
+    #[test]
+    fn zs_from_bi_list() {
+        let mut czsc = CZSC::new("TEST".to_string(), Freq::D, Settings::default());
+        // 两段震荡各形成一个中枢，中间向上离开
+        let pivots = [
+            10.0, 20.0, 14.0, 18.0, 13.0, 19.0, 15.0, 30.0, 24.0, 28.0, 23.0, 29.0, 25.0, 40.0,
+            35.0,
+        ];
+        for bar in zigzag(&pivots, 8) {
+            czsc.update(bar);
+        }
+        let expected = zs_list(&czsc.bi_list);
+        assert_eq!(
+            expected
+                .iter()
+                .map(|z| (z.high, z.low, z.bi_count))
+                .collect::<Vec<_>>(),
+            vec![(18.2, 13.8, 5), (28.2, 23.8, 5)]
+        );
+        // 第二个中枢从离开第一个中枢之后的笔开始
+        assert!(expected[0].right < expected[1].left);
+
+        let rows = zs(&czsc);
+        assert_eq!(rows.num_rows(), 2);
+        let left = rows.column_by_name("left").unwrap();
+        let right = rows.column_by_name("right").unwrap();
+        let bi_count = rows.column_by_name("bi_count").unwrap();
+        for (i, z) in expected.iter().enumerate() {
+            assert_eq!(left.as_primitive::<TimestampSecondType>().value(i), z.left);
+            assert_eq!(
+                right.as_primitive::<TimestampSecondType>().value(i),
+                z.right
+            );
+            assert_eq!(bi_count.as_primitive::<UInt32Type>().value(i), z.bi_count);
+        }
+    }
+}
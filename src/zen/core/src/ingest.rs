@@ -0,0 +1,250 @@
+use arrow::array::{Array, ArrayRef, AsArray, RecordBatch};
+use arrow::compute::cast;
+use arrow::datatypes::{DataType, Float64Type, Int64Type, TimeUnit};
+use chrono::{TimeZone, Utc};
+
+use crate::element::chan::{Bar, DT};
+use crate::element::enums::Freq;
+
+// 列式K线数据，dt 为秒级 unix 时间戳
+pub struct BarColumns {
+    pub dt: Vec<i64>,
+    pub open: Vec<f64>,
+    pub close: Vec<f64>,
+    pub high: Vec<f64>,
+    pub low: Vec<f64>,
+    pub vol: Vec<f64>,
+    pub amount: Option<Vec<f64>>,
+}
+
+impl BarColumns {
+    // 从 arrow RecordBatch 读取 dt/open/high/low/close/vol/amount 列，amount 可缺省
+    pub fn from_record_batch(batch: &RecordBatch) -> Result<Self, String> {
+        let column = |name: &str| -> Result<&ArrayRef, String> {
+            batch
+                .column_by_name(name)
+                .ok_or_else(|| format!("missing column `{}`", name))
+        };
+        Ok(Self {
+            dt: dt_values(column("dt")?)?,
+            open: float_values("open", column("open")?)?,
+            close: float_values("close", column("close")?)?,
+            high: float_values("high", column("high")?)?,
+            low: float_values("low", column("low")?)?,
+            vol: float_values("vol", column("vol")?)?,
+            amount: batch
+                .column_by_name("amount")
+                .map(|a| float_values("amount", a))
+                .transpose()?,
+        })
+    }
+
+    // 校验并转换为K线，任一行不合法则整体失败
+    // last_dt 为已有最后一根K线的时间，新K线不能早于它
+    pub fn to_bars(&self, freq: Freq, last_dt: Option<DT>) -> Result<Vec<Bar>, String> {
+        let len = self.dt.len();
+        for (name, col_len) in [
+            ("open", self.open.len()),
+            ("close", self.close.len()),
+            ("high", self.high.len()),
+            ("low", self.low.len()),
+            ("vol", self.vol.len()),
+            ("amount", self.amount.as_ref().map(|a| a.len()).unwrap_or(len)),
+        ] {
+            if col_len != len {
+                return Err(format!(
+                    "column `{}` has {} rows, expected {}",
+                    name, col_len, len
+                ));
+            }
+        }
+
+        let mut bars = Vec::with_capacity(len);
+        let mut prev_dt = last_dt;
+        for i in 0..len {
+            let dt = Utc
+                .timestamp_opt(self.dt[i], 0)
+                .single()
+                .ok_or_else(|| format!("row {}: invalid timestamp {}", i, self.dt[i]))?
+                .fixed_offset();
+            if prev_dt.map(|p| dt < p).unwrap_or(false) {
+                return Err(format!(
+                    "row {}: dt {} is earlier than previous bar {}",
+                    i,
+                    dt,
+                    prev_dt.unwrap()
+                ));
+            }
+            prev_dt = Some(dt);
+
+            let amount = self.amount.as_ref().map(|a| a[i]).unwrap_or(0.0);
+            let (open, close, high, low, vol) = (
+                self.open[i],
+                self.close[i],
+                self.high[i],
+                self.low[i],
+                self.vol[i],
+            );
+            if ![open, close, high, low, vol, amount]
+                .iter()
+                .all(|v| v.is_finite())
+            {
+                return Err(format!("row {}: non-finite value", i));
+            }
+            if high < low {
+                return Err(format!("row {}: high {} < low {}", i, high, low));
+            }
+            if [open, close].iter().any(|v| *v < low || *v > high) {
+                return Err(format!(
+                    "row {}: open {} / close {} outside [low {}, high {}]",
+                    i, open, close, low, high
+                ));
+            }
+
+            bars.push(Bar {
+                dt,
+                freq,
+                open: open as f32,
+                close: close as f32,
+                high: high as f32,
+                low: low as f32,
+                vol: vol as f32,
+                amount: amount as f32,
+                cache: Default::default(),
+                macd_4_9_9: (0.0, 0.0, 0.0),
+            });
+        }
+        Ok(bars)
+    }
+}
+
+fn dt_values(array: &ArrayRef) -> Result<Vec<i64>, String> {
+    let divisor = match array.data_type() {
+        DataType::Int64 | DataType::Int32 | DataType::Timestamp(TimeUnit::Second, _) => 1,
+        DataType::Timestamp(TimeUnit::Millisecond, _) => 1_000,
+        DataType::Timestamp(TimeUnit::Microsecond, _) => 1_000_000,
+        DataType::Timestamp(TimeUnit::Nanosecond, _) => 1_000_000_000,
+        other => return Err(format!("column `dt` has unsupported type {}", other)),
+    };
+    let array = cast(array, &DataType::Int64).map_err(|e| e.to_string())?;
+    if array.null_count() > 0 {
+        return Err("column `dt` contains nulls".to_string());
+    }
+    Ok(array
+        .as_primitive::<Int64Type>()
+        .values()
+        .iter()
+        .map(|v| v.div_euclid(divisor))
+        .collect())
+}
+
+fn float_values(name: &str, array: &ArrayRef) -> Result<Vec<f64>, String> {
+    let array = cast(array, &DataType::Float64)
+        .map_err(|e| format!("column `{}`: {}", name, e))?;
+    if array.null_count() > 0 {
+        return Err(format!("column `{}` contains nulls", name));
+    }
+    Ok(array.as_primitive::<Float64Type>().values().to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use arrow::array::{AsArray, Float64Array, Int64Array, TimestampMillisecondArray};
+    use arrow::datatypes::{Field, Float32Type, Schema, TimestampSecondType};
+
+    use super::*;
+    use crate::analyze::CZSC;
+    use crate::export;
+    use crate::setting::Settings;
+
+    fn batch(dt: Vec<i64>, open: Vec<f64>, close: Vec<f64>) -> RecordBatch {
+        let n = dt.len();
+        let f64_field = |name: &str| Field::new(name, DataType::Float64, false);
+        RecordBatch::try_new(
+            Arc::new(Schema::new(vec![
+                Field::new(
+                    "dt",
+                    DataType::Timestamp(TimeUnit::Millisecond, None),
+                    false,
+                ),
+                f64_field("open"),
+                f64_field("close"),
+                f64_field("high"),
+                f64_field("low"),
+                Field::new("vol", DataType::Int64, false),
+            ])),
+            vec![
+                Arc::new(TimestampMillisecondArray::from(dt)),
+                Arc::new(Float64Array::from(open)),
+                Arc::new(Float64Array::from(close)),
+                Arc::new(Float64Array::from(vec![12.0; n])),
+                Arc::new(Float64Array::from(vec![9.0; n])),
+                Arc::new(Int64Array::from(vec![100; n])),
+            ],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn record_batch_roundtrip() {
+        let dt: Vec<i64> = (0..5).map(|i| 1_704_067_200_000 + i * 86_400_000).collect();
+        let open = vec![10.0, 10.5, 11.0, 10.0, 9.5];
+        let close = vec![10.5, 11.0, 10.0, 9.5, 11.5];
+        let columns =
+            BarColumns::from_record_batch(&batch(dt.clone(), open.clone(), close.clone())).unwrap();
+        assert_eq!(columns.amount, None);
+
+        let mut czsc = CZSC::new("TEST".to_string(), Freq::D, Settings::default());
+        for bar in columns.to_bars(Freq::D, None).unwrap() {
+            czsc.update(bar);
+        }
+        let out = export::bars(&czsc);
+        let ts: Vec<i64> = out
+            .column_by_name("dt")
+            .unwrap()
+            .as_primitive::<TimestampSecondType>()
+            .values()
+            .to_vec();
+        assert_eq!(ts, dt.iter().map(|t| t / 1000).collect::<Vec<_>>());
+        for (name, expected) in [("open", &open), ("close", &close)] {
+            let values: Vec<f64> = out
+                .column_by_name(name)
+                .unwrap()
+                .as_primitive::<Float32Type>()
+                .values()
+                .iter()
+                .map(|v| *v as f64)
+                .collect();
+            assert_eq!(&values, expected);
+        }
+    }
+
+    #[test]
+    fn reject_invalid_rows() {
+        let dt = vec![1_704_067_200_000, 1_704_153_600_000];
+        let columns =
+            BarColumns::from_record_batch(&batch(dt.clone(), vec![10.0, 13.0], vec![10.0; 2]))
+                .unwrap();
+        let err = columns.to_bars(Freq::D, None).unwrap_err();
+        assert!(err.starts_with("row 1: open 13"), "{}", err);
+
+        let columns =
+            BarColumns::from_record_batch(&batch(dt.clone(), vec![10.0; 2], vec![10.0, 8.0]))
+                .unwrap();
+        assert!(columns
+            .to_bars(Freq::D, None)
+            .unwrap_err()
+            .starts_with("row 1:"));
+
+        // 早于已有K线
+        let columns =
+            BarColumns::from_record_batch(&batch(dt, vec![10.0; 2], vec![10.0; 2])).unwrap();
+        let last = Utc.timestamp_opt(1_704_153_600, 0).unwrap().fixed_offset();
+        assert!(columns
+            .to_bars(Freq::D, Some(last))
+            .unwrap_err()
+            .starts_with("row 0:"));
+    }
+}
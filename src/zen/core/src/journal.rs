@@ -0,0 +1,551 @@
+// #[pymethods] 生成的包装函数误报 useless_conversion，原因见 store.rs
+#![allow(clippy::useless_conversion)]
+
+use std::rc::Rc;
+
+use pyo3::exceptions::PyValueError;
+use pyo3::{pyclass, pymethods, PyResult};
+use rusqlite::types::Value;
+use rusqlite::{params, params_from_iter, Connection};
+
+use crate::analyze::Symbol;
+use crate::calculate::beichi::buy_sell_point::{ts_to_dt, BSPoint};
+use crate::element::chan::DT;
+use crate::element::enums::Freq;
+use crate::element::event::Signal;
+use crate::utils::clock;
+
+// 信号日志：把每个信号、买卖点连同标的、级别、K线时间、发出时间写入 sqlite，
+// 买卖点被撤销时记录撤销时间，弥补 beichi_tracker 只保留最近 100 个的不足
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS signals (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    symbol TEXT NOT NULL,
+    freq TEXT NOT NULL,
+    key TEXT NOT NULL,
+    value TEXT NOT NULL,
+    figure REAL NOT NULL,
+    signal_dt INTEGER,
+    bar_dt INTEGER NOT NULL,
+    emit_dt INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS signals_symbol_freq ON signals (symbol, freq, bar_dt);
+CREATE TABLE IF NOT EXISTS bs_points (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    symbol TEXT NOT NULL,
+    freq TEXT NOT NULL,
+    dt INTEGER NOT NULL,
+    direction TEXT NOT NULL,
+    point_type TEXT NOT NULL,
+    bc_type TEXT NOT NULL,
+    price REAL NOT NULL,
+    fake_bi INTEGER NOT NULL,
+    zs2_left INTEGER NOT NULL,
+    zs2_right INTEGER NOT NULL,
+    detail TEXT NOT NULL,
+    bar_dt INTEGER NOT NULL,
+    emit_dt INTEGER NOT NULL,
+    invalidated_dt INTEGER
+);
+CREATE INDEX IF NOT EXISTS bs_points_symbol_freq ON bs_points (symbol, freq, zs2_left, zs2_right);
+";
+
+pub(crate) struct Journal {
+    conn: Connection,
+}
+
+impl Journal {
+    // path 为 ":memory:" 时使用内存数据库
+    pub fn open(path: &str) -> Result<Self, String> {
+        let conn = Connection::open(path).map_err(|e| e.to_string())?;
+        conn.execute_batch(SCHEMA).map_err(|e| e.to_string())?;
+        Ok(Self { conn })
+    }
+
+    // 多行写入放在同一个事务中，中途出错时整体回滚
+    fn write<F>(&self, f: F) -> Result<(), String>
+    where
+        F: FnOnce(&Connection) -> Result<(), String>,
+    {
+        let tx = self
+            .conn
+            .unchecked_transaction()
+            .map_err(|e| e.to_string())?;
+        f(&tx)?;
+        tx.commit().map_err(|e| e.to_string())
+    }
+
+    // 同一K线上已记录的信号不重复写入，如实时更新同一根K线或回放回退后重放
+    pub fn record_signals(
+        &self,
+        symbol: &Symbol,
+        freq: Freq,
+        bar_dt: DT,
+        signals: &[Signal],
+    ) -> Result<(), String> {
+        self.write(|conn| {
+            let mut stmt = conn
+                .prepare_cached(
+                    "INSERT INTO signals (symbol, freq, key, value, figure, signal_dt, bar_dt, emit_dt)
+                     SELECT ?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8
+                     WHERE NOT EXISTS (
+                        SELECT 1 FROM signals WHERE symbol = ?1 AND freq = ?2 AND bar_dt = ?7
+                            AND key = ?3 AND value = ?4 AND signal_dt IS ?6
+                     )",
+                )
+                .map_err(|e| e.to_string())?;
+            let emit_dt = clock::now().timestamp();
+            for s in signals {
+                stmt.execute(params![
+                    symbol,
+                    freq.as_str(),
+                    s.key(),
+                    s.value(),
+                    s.figure,
+                    s.dt.map(|dt| dt.timestamp()),
+                    bar_dt.timestamp(),
+                    emit_dt,
+                ])
+                .map_err(|e| e.to_string())?;
+            }
+            Ok(())
+        })
+    }
+
+    // 同一中枢的买卖点随K线更新多次发出，只保留一条有效记录，emit_dt 为首次发出时间
+    pub fn record_points(
+        &self,
+        symbol: &Symbol,
+        freq: Freq,
+        bar_dt: DT,
+        points: &[BSPoint],
+    ) -> Result<(), String> {
+        self.write(|conn| {
+            let mut update = conn
+                .prepare_cached(
+                    "UPDATE bs_points SET dt = ?1, direction = ?2, point_type = ?3, bc_type = ?4,
+                        price = ?5, fake_bi = ?6, detail = ?7, bar_dt = ?8
+                     WHERE symbol = ?9 AND freq = ?10 AND zs2_left = ?11 AND zs2_right = ?12
+                        AND invalidated_dt IS NULL",
+                )
+                .map_err(|e| e.to_string())?;
+            let mut insert = conn
+                .prepare_cached(
+                    "INSERT INTO bs_points (symbol, freq, dt, direction, point_type, bc_type, price,
+                        fake_bi, zs2_left, zs2_right, detail, bar_dt, emit_dt)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
+                )
+                .map_err(|e| e.to_string())?;
+            let emit_dt = clock::now().timestamp();
+            for bs in points {
+                let point_type = format!("{:?}", bs.r#type);
+                let bc_type = bs
+                    .bc_type
+                    .iter()
+                    .map(|t| format!("{:?}", t))
+                    .collect::<Vec<_>>()
+                    .join(",");
+                let detail = serde_json::to_string(bs).map_err(|e| e.to_string())?;
+                let updated = update
+                    .execute(params![
+                        bs.dt,
+                        bs.direction.as_str(),
+                        point_type,
+                        bc_type,
+                        bs.price,
+                        bs.fake_bi,
+                        detail,
+                        bar_dt.timestamp(),
+                        symbol,
+                        freq.as_str(),
+                        bs.zs2.left,
+                        bs.zs2.right,
+                    ])
+                    .map_err(|e| e.to_string())?;
+                if updated > 0 {
+                    continue;
+                }
+                insert
+                    .execute(params![
+                        symbol,
+                        freq.as_str(),
+                        bs.dt,
+                        bs.direction.as_str(),
+                        point_type,
+                        bc_type,
+                        bs.price,
+                        bs.fake_bi,
+                        bs.zs2.left,
+                        bs.zs2.right,
+                        detail,
+                        bar_dt.timestamp(),
+                        emit_dt,
+                    ])
+                    .map_err(|e| e.to_string())?;
+            }
+            Ok(())
+        })
+    }
+
+    // 标记被撤销的买卖点
+    pub fn invalidate(
+        &self,
+        symbol: &Symbol,
+        freq: Freq,
+        points: &[BSPoint],
+    ) -> Result<(), String> {
+        self.write(|conn| {
+            let mut stmt = conn
+                .prepare_cached(
+                    "UPDATE bs_points SET invalidated_dt = ?1
+                     WHERE symbol = ?2 AND freq = ?3 AND zs2_left = ?4 AND zs2_right = ?5
+                        AND invalidated_dt IS NULL",
+                )
+                .map_err(|e| e.to_string())?;
+            let now = clock::now().timestamp();
+            for bs in points {
+                stmt.execute(params![
+                    now,
+                    symbol,
+                    freq.as_str(),
+                    bs.zs2.left,
+                    bs.zs2.right
+                ])
+                .map_err(|e| e.to_string())?;
+            }
+            Ok(())
+        })
+    }
+}
+
+// 按标的、级别、K线时间过滤的查询条件
+fn filters(
+    symbol: Option<Symbol>,
+    freq: Option<Freq>,
+    since: Option<DT>,
+    until: Option<DT>,
+) -> (String, Vec<Value>) {
+    let mut sql = String::from(" WHERE 1 = 1");
+    let mut values = vec![];
+    if let Some(symbol) = symbol {
+        sql.push_str(" AND symbol = ?");
+        values.push(Value::Text(symbol));
+    }
+    if let Some(freq) = freq {
+        sql.push_str(" AND freq = ?");
+        values.push(Value::Text(freq.as_str().to_string()));
+    }
+    if let Some(since) = since {
+        sql.push_str(" AND bar_dt >= ?");
+        values.push(Value::Integer(since.timestamp()));
+    }
+    if let Some(until) = until {
+        sql.push_str(" AND bar_dt <= ?");
+        values.push(Value::Integer(until.timestamp()));
+    }
+    (sql, values)
+}
+
+fn limit_clause(limit: Option<usize>) -> String {
+    limit.map(|n| format!(" LIMIT {}", n)).unwrap_or_default()
+}
+
+#[derive(Debug, Clone)]
+#[pyclass(frozen, get_all)]
+pub(crate) struct JournalSignal {
+    symbol: Symbol,
+    freq: String,
+    key: String,
+    value: String,
+    figure: f32,
+    // 信号本身的时间，如背驰点时间
+    signal_dt: Option<DT>,
+    // 发出信号时最后一根K线的时间
+    bar_dt: DT,
+    emit_dt: DT,
+}
+
+#[pymethods]
+impl JournalSignal {
+    fn __repr__(&self) -> String {
+        format!("{:?}", self)
+    }
+}
+
+#[derive(Debug, Clone)]
+#[pyclass(frozen, get_all)]
+pub(crate) struct JournalBSPoint {
+    pub symbol: Symbol,
+    pub freq: String,
+    pub dt: DT,
+    pub direction: String,
+    pub point_type: String,
+    pub bc_type: String,
+    pub price: f32,
+    pub fake_bi: bool,
+    pub zs2_left: DT,
+    pub zs2_right: DT,
+    // 买卖点完整内容的 json
+    pub detail: String,
+    pub bar_dt: DT,
+    pub emit_dt: DT,
+    // 被撤销的时间，仍有效时为 None
+    pub invalidated_dt: Option<DT>,
+}
+
+#[pymethods]
+impl JournalBSPoint {
+    #[getter]
+    fn invalidated(&self) -> bool {
+        self.invalidated_dt.is_some()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("{:?}", self)
+    }
+}
+
+#[pyclass(unsendable, name = "SignalJournal")]
+pub(crate) struct PyJournal {
+    pub(crate) journal: Rc<Journal>,
+}
+
+#[pymethods]
+impl PyJournal {
+    #[new]
+    #[pyo3(signature = (path=":memory:"))]
+    fn new(path: &str) -> PyResult<Self> {
+        Ok(Self {
+            journal: Rc::new(Journal::open(path).map_err(PyValueError::new_err)?),
+        })
+    }
+
+    // 按K线时间过滤的信号记录，按写入顺序返回
+    #[pyo3(signature = (symbol=None, freq=None, since=None, until=None, limit=None))]
+    pub fn signals(
+        &self,
+        symbol: Option<Symbol>,
+        freq: Option<Freq>,
+        since: Option<DT>,
+        until: Option<DT>,
+        limit: Option<usize>,
+    ) -> PyResult<Vec<JournalSignal>> {
+        let (filter, values) = filters(symbol, freq, since, until);
+        let sql = format!(
+            "SELECT symbol, freq, key, value, figure, signal_dt, bar_dt, emit_dt FROM signals{} ORDER BY id{}",
+            filter,
+            limit_clause(limit)
+        );
+        let conn = &self.journal.conn;
+        let mut stmt = conn
+            .prepare(&sql)
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        let rows = stmt
+            .query_map(params_from_iter(values), |row| {
+                Ok(JournalSignal {
+                    symbol: row.get(0)?,
+                    freq: row.get(1)?,
+                    key: row.get(2)?,
+                    value: row.get(3)?,
+                    figure: row.get(4)?,
+                    signal_dt: row.get::<_, Option<i64>>(5)?.map(ts_to_dt),
+                    bar_dt: ts_to_dt(row.get(6)?),
+                    emit_dt: ts_to_dt(row.get(7)?),
+                })
+            })
+            .and_then(|rows| rows.collect::<Result<Vec<_>, _>>())
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        Ok(rows)
+    }
+
+    // 按K线时间过滤的买卖点记录，include_invalidated 为 False 时只返回仍有效的
+    #[pyo3(signature = (symbol=None, freq=None, since=None, until=None, include_invalidated=true, limit=None))]
+    pub fn bs_points(
+        &self,
+        symbol: Option<Symbol>,
+        freq: Option<Freq>,
+        since: Option<DT>,
+        until: Option<DT>,
+        include_invalidated: bool,
+        limit: Option<usize>,
+    ) -> PyResult<Vec<JournalBSPoint>> {
+        let (mut filter, values) = filters(symbol, freq, since, until);
+        if !include_invalidated {
+            filter.push_str(" AND invalidated_dt IS NULL");
+        }
+        let sql = format!(
+            "SELECT symbol, freq, dt, direction, point_type, bc_type, price, fake_bi, zs2_left,
+                zs2_right, detail, bar_dt, emit_dt, invalidated_dt FROM bs_points{} ORDER BY id{}",
+            filter,
+            limit_clause(limit)
+        );
+        let conn = &self.journal.conn;
+        let mut stmt = conn
+            .prepare(&sql)
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        let rows = stmt
+            .query_map(params_from_iter(values), |row| {
+                Ok(JournalBSPoint {
+                    symbol: row.get(0)?,
+                    freq: row.get(1)?,
+                    dt: ts_to_dt(row.get(2)?),
+                    direction: row.get(3)?,
+                    point_type: row.get(4)?,
+                    bc_type: row.get(5)?,
+                    price: row.get(6)?,
+                    fake_bi: row.get(7)?,
+                    zs2_left: ts_to_dt(row.get(8)?),
+                    zs2_right: ts_to_dt(row.get(9)?),
+                    detail: row.get(10)?,
+                    bar_dt: ts_to_dt(row.get(11)?),
+                    emit_dt: ts_to_dt(row.get(12)?),
+                    invalidated_dt: row.get::<_, Option<i64>>(13)?.map(ts_to_dt),
+                })
+            })
+            .and_then(|rows| rows.collect::<Result<Vec<_>, _>>())
+            .map_err(|e| PyValueError::new_err(e.to_string()))?;
+        Ok(rows)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{FixedOffset, TimeZone};
+
+    use super::*;
+    use crate::calculate::beichi::buy_sell_point::{BeichiType, PointType, ZSInfo};
+    use crate::element::enums::Direction;
+
+    fn at(day: u32) -> DT {
+        FixedOffset::east_opt(8 * 3600)
+            .unwrap()
+            .with_ymd_and_hms(2024, 9, day, 15, 0, 0)
+            .unwrap()
+    }
+
+    fn journal() -> PyJournal {
+        PyJournal {
+            journal: Rc::new(Journal::open(":memory:").unwrap()),
+        }
+    }
+
+    fn signal(direction: &str, day: u32) -> Signal {
+        Signal {
+            key: ("D".into(), "MACD面积背驰".into(), "other".into()),
+            value: (direction.into(), "other".into(), "other".into()),
+            dt: Some(at(day)),
+            figure: 1.0,
+            figure_max: None,
+        }
+    }
+
+    // zs_day 为中枢开始的日期，同一中枢的买卖点视为同一条记录
+    fn point(zs_day: u32, price: f32) -> BSPoint {
+        let left = at(zs_day).timestamp();
+        BSPoint {
+            direction: Direction::Down,
+            r#type: PointType::FirstBuy,
+            bc_type: vec![BeichiType::Area],
+            zs2: ZSInfo {
+                left,
+                right: left + 86400,
+                high: 11.0,
+                low: 9.0,
+                bi_count: 3,
+            },
+            zs1: None,
+            fake_bi: false,
+            macd_a_dt: 0,
+            macd_a_val: 0.0,
+            macd_b_dt: 0,
+            macd_b_val: 0.0,
+            dt: left + 2 * 86400,
+            price,
+            bi_count: 5,
+        }
+    }
+
+    #[test]
+    fn record_signals_and_filter() {
+        let j = journal();
+        let a = "A".to_string();
+        let signals = [signal("底", 1), signal("顶", 1)];
+        j.journal
+            .record_signals(&a, Freq::D, at(2), &signals)
+            .unwrap();
+        // 同一K线重复发出的不再写入
+        j.journal
+            .record_signals(&a, Freq::D, at(2), &signals[..1])
+            .unwrap();
+        j.journal
+            .record_signals(&a, Freq::F30, at(3), &signals[..1])
+            .unwrap();
+        j.journal
+            .record_signals(&"B".to_string(), Freq::D, at(4), &signals[..1])
+            .unwrap();
+
+        let query = |symbol: Option<&str>, freq, since, until, limit| {
+            j.signals(symbol.map(String::from), freq, since, until, limit)
+                .unwrap()
+        };
+        let all = query(None, None, None, None, None);
+        assert_eq!(all.len(), 4);
+        assert_eq!(all[0].signal_dt, Some(at(1)));
+        assert_eq!(all[0].bar_dt, at(2));
+        assert_eq!(query(Some("A"), None, None, None, None).len(), 3);
+        assert_eq!(query(None, Some(Freq::D), None, None, None).len(), 3);
+        assert_eq!(query(None, None, Some(at(3)), None, None).len(), 2);
+        assert_eq!(query(None, None, None, Some(at(3)), None).len(), 3);
+        assert_eq!(
+            query(Some("A"), Some(Freq::D), None, None, Some(1)).len(),
+            1
+        );
+    }
+
+    #[test]
+    fn update_active_point_and_invalidate() {
+        let j = journal();
+        let a = "A".to_string();
+        let points = |include_invalidated| {
+            j.bs_points(None, None, None, None, include_invalidated, None)
+                .unwrap()
+        };
+        clock::with_now(at(2), || {
+            j.journal
+                .record_points(&a, Freq::D, at(2), &[point(1, 10.0)])
+        })
+        .unwrap();
+        // 同一中枢的有效记录原地更新，保留首次发出时间
+        clock::with_now(at(3), || {
+            j.journal
+                .record_points(&a, Freq::D, at(3), &[point(1, 9.5), point(5, 12.0)])
+        })
+        .unwrap();
+        let recorded = points(true);
+        assert_eq!(recorded.len(), 2);
+        assert_eq!(recorded[0].price, 9.5);
+        assert_eq!(recorded[0].bc_type, "Area");
+        assert_eq!((recorded[0].bar_dt, recorded[0].emit_dt), (at(3), at(2)));
+
+        // 撤销后再次发出时新增一条有效记录
+        clock::with_now(at(4), || {
+            j.journal.invalidate(&a, Freq::D, &[point(1, 9.5)])
+        })
+        .unwrap();
+        assert_eq!(points(false).len(), 1);
+        assert_eq!(points(true)[0].invalidated_dt, Some(at(4)));
+        j.journal
+            .record_points(&a, Freq::D, at(5), &[point(1, 9.0)])
+            .unwrap();
+        let active = points(false);
+        assert_eq!(active.len(), 2);
+        assert_eq!((active[1].price, active[1].bar_dt), (9.0, at(5)));
+        assert_eq!(
+            j.bs_points(Some(a), None, Some(at(4)), None, true, None)
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+}
@@ -1,37 +1,104 @@
+
+#[cfg(target_os = "macos")]
 use notify_rust::{get_bundle_identifier_or_default, set_application};
 #[allow(dead_code)]
 
 use pyo3::prelude::*;
-use crate::calculate::beichi::buy_sell_point::BSPoint;
+use crate::calculate::beichi::buy_sell_point::{BSPoint, BeichiType, PointType, ZSInfo};
 
+mod adjust;
+mod calendar;
 mod element;
+mod export;
+mod ingest;
+mod journal;
 mod setting;
 mod analyze;
 mod calculate;
 mod talipp;
 mod utils;
+mod pair;
+mod replay;
 mod store;
 
+// 桌面通知以 iTerm 的身份发送，仅 macOS 需要
 #[pyfunction]
 fn init() {
-    let safari_id = get_bundle_identifier_or_default("iTerm 2");
-    set_application(&safari_id).expect("install iterm");
+    #[cfg(target_os = "macos")]
+    {
+        let safari_id = get_bundle_identifier_or_default("iTerm 2");
+        set_application(&safari_id).expect("install iterm");
+    }
 }
 #[pymodule]
 fn zen_core(m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_class::<element::chan::Bar>()?;
     m.add_class::<element::enums::Freq>()?;
+    m.add_class::<element::enums::Mark>()?;
+    m.add_class::<element::enums::Direction>()?;
     m.add_class::<element::event::Signal>()?;
+    m.add_class::<adjust::AdjustMode>()?;
+    m.add_class::<adjust::CorporateAction>()?;
+    m.add_class::<calendar::Market>()?;
+    m.add_class::<calendar::Calendar>()?;
+    m.add_class::<setting::Settings>()?;
+    m.add_class::<setting::BiType>()?;
+    m.add_class::<utils::notify::NotifierConfig>()?;
+    m.add_class::<utils::policy::NotifyPolicy>()?;
+    m.add_class::<utils::policy::NotifyDecision>()?;
     m.add_class::<store::Zen>()?;
+    m.add_class::<store::ZenStore>()?;
+    m.add_class::<pair::ZenPair>()?;
+    m.add_class::<replay::Replayer>()?;
+    m.add_class::<journal::PyJournal>()?;
+    m.add_class::<journal::JournalSignal>()?;
+    m.add_class::<journal::JournalBSPoint>()?;
     m.add_class::<store::ZenBiDetail>()?;
+    m.add_class::<store::ZenNewBar>()?;
+    m.add_class::<store::ZenFX>()?;
     m.add_class::<BSPoint>()?;
+    m.add_class::<ZSInfo>()?;
+    m.add_class::<PointType>()?;
+    m.add_class::<BeichiType>()?;
     m.add_function(wrap_pyfunction!(init, m)?)?;
+    m.add_function(wrap_pyfunction!(adjust::adjust_bars, m)?)?;
     Ok(())
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
+    use chrono::{Duration, TimeZone, Utc};
+
     use super::*;
+    use crate::element::chan::Bar;
+    use crate::element::enums::Freq;
+
+    // 按拐点线性插值生成日线，每段 legs 根K线，2024-01-01 起每天一根
+    pub(crate) fn zigzag(pivots: &[f32], legs: usize) -> Vec<Bar> {
+        let start = Utc.with_ymd_and_hms(2024, 1, 1, 7, 0, 0).unwrap();
+        let mut prices = vec![pivots[0]];
+        for w in pivots.windows(2) {
+            for i in 1..=legs {
+                prices.push(w[0] + (w[1] - w[0]) * i as f32 / legs as f32);
+            }
+        }
+        prices
+            .iter()
+            .enumerate()
+            .map(|(i, p)| Bar {
+                dt: (start + Duration::days(i as i64)).fixed_offset(),
+                freq: Freq::D,
+                open: *p,
+                close: *p,
+                high: p + 0.2,
+                low: p - 0.2,
+                vol: 100.0,
+                amount: 0.0,
+                cache: Default::default(),
+                macd_4_9_9: (0.0, 0.0, 0.0),
+            })
+            .collect()
+    }
 
     #[test]
     fn it_works() {}
@@ -0,0 +1,436 @@
+// #[pymethods] 生成的包装函数误报 useless_conversion，原因见 store.rs
+#![allow(clippy::useless_conversion)]
+
+use std::collections::BTreeMap;
+
+use pyo3::exceptions::PyValueError;
+use pyo3::{pyclass, pymethods, PyResult};
+
+use crate::analyze::Symbol;
+use crate::calculate::beichi::buy_sell_point::BSPoint;
+use crate::element::chan::{Bar, DT};
+use crate::element::enums::Freq;
+use crate::element::event::Signal;
+use crate::setting::Settings;
+use crate::store::{Zen, ZenBiDetail};
+
+// 相对序列的构造方式
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum PairMode {
+    // 目标 / 基准
+    Ratio,
+    // 目标 - 基准
+    Spread,
+}
+
+// 多标的联立：目标与基准（如招商银行与上证指数）按时间对齐，
+// 用比值或价差序列构造一个新的 CZSC，在其上计算相对强弱背驰
+#[pyclass(unsendable)]
+pub(crate) struct ZenPair {
+    target: Zen,
+    benchmark: Zen,
+    relative: Zen,
+    mode: PairMode,
+    // 判断新高新低的回看K线数
+    lookback: usize,
+    // 等待对齐的K线，key 为时间戳
+    pending_target: BTreeMap<i64, Bar>,
+    pending_benchmark: BTreeMap<i64, Bar>,
+    // 目标在该时间的背驰信号，等基准同一时间的K线到达后再判断联立背驰
+    pending_signals: BTreeMap<i64, Vec<Signal>>,
+    // 最后一次对齐的时间，该时间的K线保留以便实时更新
+    last_aligned: Option<i64>,
+    // 已发出相对强弱背离的K线时间，实时更新同一根K线时不重复发出
+    divergence_sent: Option<i64>,
+}
+
+enum Side {
+    Target,
+    Benchmark,
+}
+
+impl ZenPair {
+    fn append_side(&mut self, side: Side, bar: Bar, skip_process: bool) -> PyResult<Vec<Signal>> {
+        let ts = bar.dt.timestamp();
+        if let Some(last) = self.last_aligned.filter(|t| ts < *t) {
+            return Err(PyValueError::new_err(format!(
+                "bar at {} is earlier than the last aligned bar {}",
+                bar.dt,
+                self.relative.czsc.localize(last)
+            )));
+        }
+        let mut result = match side {
+            Side::Target => self.target.append(bar.clone(), skip_process),
+            Side::Benchmark => self.benchmark.append(bar.clone(), skip_process),
+        };
+
+        if let Side::Target = side {
+            // 背驰的目标信号等对齐后再检查基准是否同时创新高新低，实时更新时以最新一次为准
+            let divergences: Vec<Signal> = result
+                .iter()
+                .filter(|s| matches!(s.value.0.as_str(), "底" | "顶"))
+                .cloned()
+                .collect();
+            if !divergences.is_empty() {
+                self.pending_signals.insert(ts, divergences);
+            }
+        }
+        match side {
+            Side::Target => self.pending_target.insert(ts, bar),
+            Side::Benchmark => self.pending_benchmark.insert(ts, bar),
+        };
+
+        let mut signals = vec![];
+        let start = self.last_aligned.unwrap_or(i64::MIN);
+        let aligned: Vec<i64> = self
+            .pending_target
+            .range(start..)
+            .map(|(t, _)| *t)
+            .filter(|t| self.pending_benchmark.contains_key(t))
+            .collect();
+        for t in aligned {
+            let bar = self.relative_bar(&self.pending_target[&t], &self.pending_benchmark[&t]);
+            self.last_aligned = Some(t);
+            for s in self.pending_signals.remove(&t).unwrap_or_default() {
+                signals.extend(self.index_extreme(&s, t));
+            }
+            if let Some(bar) = bar {
+                for s in self.relative.append(bar, skip_process) {
+                    signals.push(self.relative_signal(s));
+                }
+                if !skip_process && self.divergence_sent != Some(t) {
+                    let divergence = self.relative_divergence(t);
+                    if !divergence.is_empty() {
+                        self.divergence_sent = Some(t);
+                    }
+                    signals.extend(divergence);
+                }
+            }
+        }
+        if let Some(t) = self.last_aligned {
+            self.pending_target.retain(|k, _| *k >= t);
+            self.pending_benchmark.retain(|k, _| *k >= t);
+            self.pending_signals.retain(|k, _| *k > t);
+        }
+
+        result.extend(signals);
+        Ok(result)
+    }
+
+    fn relative_bar(&self, target: &Bar, benchmark: &Bar) -> Option<Bar> {
+        let f = |a: f32, b: f32| -> Option<f32> {
+            match self.mode {
+                PairMode::Ratio if b.abs() > f32::EPSILON => Some(a / b),
+                PairMode::Ratio => None,
+                PairMode::Spread => Some(a - b),
+            }
+        };
+        let open = f(target.open, benchmark.open)?;
+        let close = f(target.close, benchmark.close)?;
+        let high = f(target.high, benchmark.high)?;
+        let low = f(target.low, benchmark.low)?;
+        Some(Bar {
+            dt: target.dt,
+            freq: target.freq,
+            open,
+            close,
+            high: high.max(open).max(close),
+            low: low.min(open).min(close),
+            vol: target.vol,
+            amount: target.amount,
+            cache: Default::default(),
+            macd_4_9_9: (0.0, 0.0, 0.0),
+        })
+    }
+
+    // 相对序列上的背驰
+    fn relative_signal(&self, s: Signal) -> Signal {
+        Signal {
+            key: (
+                s.key.0,
+                "相对强弱背驰".to_string(),
+                self.benchmark.czsc.symbol.clone(),
+            ),
+            value: (s.value.0, s.value.1, "other".to_string()),
+            ..s
+        }
+    }
+
+    // ts 这根K线是否为回看区间内的新低（或新高），只看 ts 及之前的K线
+    fn is_extreme(zen: &Zen, ts: i64, lookback: usize, low: bool) -> bool {
+        let bars = &zen.czsc.bars_raw;
+        let Some(i) = bars.iter().rposition(|b| b.borrow().dt.timestamp() == ts) else {
+            return false;
+        };
+        if i == 0 {
+            return false;
+        }
+        let last = bars[i].borrow();
+        let window = bars[..i].iter().rev().take(lookback.max(1));
+        if low {
+            window.map(|b| b.borrow().low).all(|l| last.low <= l)
+        } else {
+            window.map(|b| b.borrow().high).all(|h| last.high >= h)
+        }
+    }
+
+    // 目标背驰同时基准在同一根K线上创新低（新高）
+    fn index_extreme(&self, s: &Signal, ts: i64) -> Option<Signal> {
+        let bottom = match s.value.0.as_str() {
+            "底" => true,
+            "顶" => false,
+            _ => return None,
+        };
+        if !Self::is_extreme(&self.benchmark, ts, self.lookback, bottom) {
+            return None;
+        }
+        Some(Signal {
+            key: (
+                s.key.0.clone(),
+                "联立背驰".to_string(),
+                self.benchmark.czsc.symbol.clone(),
+            ),
+            value: (
+                s.value.0.clone(),
+                if bottom { "指数新低" } else { "指数新高" }.to_string(),
+                "other".to_string(),
+            ),
+            dt: s.dt,
+            figure: s.figure,
+            figure_max: None,
+        })
+    }
+
+    // 对齐的 ts 这根K线上目标创新低而相对强弱未创新低（或反之），即相对强弱背离
+    fn relative_divergence(&self, ts: i64) -> Vec<Signal> {
+        let mut result = vec![];
+        let dt = Some(self.relative.czsc.localize(ts));
+        for bottom in [true, false] {
+            if Self::is_extreme(&self.target, ts, self.lookback, bottom)
+                && !Self::is_extreme(&self.relative, ts, self.lookback, bottom)
+            {
+                result.push(Signal {
+                    key: (
+                        format!("{:?}", self.relative.czsc.freq),
+                        "相对强弱背离".to_string(),
+                        self.benchmark.czsc.symbol.clone(),
+                    ),
+                    value: (
+                        if bottom { "底" } else { "顶" }.to_string(),
+                        if bottom { "价格新低" } else { "价格新高" }.to_string(),
+                        "other".to_string(),
+                    ),
+                    dt,
+                    figure: 0.0,
+                    figure_max: None,
+                });
+            }
+        }
+        result
+    }
+}
+
+#[pymethods]
+impl ZenPair {
+    // mode 为 "ratio"（比值）或 "spread"（价差），tz 为两个标的共同的交易所时区，缺省时使用 settings.timezone
+    #[new]
+    #[pyo3(signature = (target, benchmark, freq, mode="ratio", lookback=20, settings=None, tz=None))]
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        target: Symbol,
+        benchmark: Symbol,
+        freq: Freq,
+        mode: &str,
+        lookback: usize,
+        settings: Option<Settings>,
+        tz: Option<&str>,
+    ) -> PyResult<Self> {
+        let mode = match mode {
+            "ratio" => PairMode::Ratio,
+            "spread" => PairMode::Spread,
+            _ => {
+                return Err(PyValueError::new_err(format!(
+                    "unknown mode `{}`, expected ratio or spread",
+                    mode
+                )))
+            }
+        };
+        let settings = match settings {
+            Some(s) => s,
+            None => Settings::new().map_err(|e| PyValueError::new_err(e.to_string()))?,
+        };
+        let relative = format!("{}/{}", target, benchmark);
+        Ok(Self {
+            target: Zen::new(target, freq, Some(settings.clone()), tz)?,
+            benchmark: Zen::new(benchmark, freq, Some(settings.clone()), tz)?,
+            relative: Zen::new(relative, freq, Some(settings), tz)?,
+            mode,
+            lookback,
+            pending_target: Default::default(),
+            pending_benchmark: Default::default(),
+            pending_signals: Default::default(),
+            last_aligned: None,
+            divergence_sent: None,
+        })
+    }
+
+    // 追加目标K线，返回目标自身信号及本次对齐产生的联立信号；早于最后对齐时间的K线报错
+    #[pyo3(signature = (bar, skip_process=false))]
+    fn append_target(&mut self, bar: Bar, skip_process: bool) -> PyResult<Vec<Signal>> {
+        self.append_side(Side::Target, bar, skip_process)
+    }
+
+    // 追加基准K线，返回基准自身信号及本次对齐产生的联立信号
+    #[pyo3(signature = (bar, skip_process=false))]
+    fn append_benchmark(&mut self, bar: Bar, skip_process: bool) -> PyResult<Vec<Signal>> {
+        self.append_side(Side::Benchmark, bar, skip_process)
+    }
+
+    // 相对序列收盘价
+    fn relative_closes(&self) -> Vec<(DT, f32)> {
+        self.relative
+            .czsc
+            .bars_raw
+            .iter()
+            .map(|b| (b.borrow().dt, b.borrow().close))
+            .collect()
+    }
+
+    fn relative_bi_info(&self) -> Vec<ZenBiDetail> {
+        self.relative.bi_info()
+    }
+
+    fn relative_bc_info(&self) -> Vec<BSPoint> {
+        self.relative.bc_info()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::zigzag;
+
+    fn pair() -> ZenPair {
+        ZenPair::new(
+            "600036".to_string(),
+            "000001".to_string(),
+            Freq::D,
+            "ratio",
+            20,
+            Some(Settings::default()),
+            None,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn exchange_timezone() {
+        let new = |tz| {
+            ZenPair::new(
+                "AAPL".to_string(),
+                "SPY".to_string(),
+                Freq::D,
+                "ratio",
+                20,
+                Some(Settings::default()),
+                tz,
+            )
+        };
+        let pair = new(Some("America/New_York")).unwrap();
+        for zen in [&pair.target, &pair.benchmark, &pair.relative] {
+            assert_eq!(zen.czsc.tz, chrono_tz::Tz::America__New_York);
+        }
+        assert_eq!(
+            new(None).unwrap().relative.czsc.tz,
+            chrono_tz::Tz::Asia__Shanghai
+        );
+        assert!(new(Some("Mars/Olympus")).is_err());
+    }
+
+    #[test]
+    fn align_by_timestamp() {
+        let mut pair = pair();
+        let target = zigzag(&[10.0, 20.0], 4);
+        let benchmark = zigzag(&[5.0, 10.0], 4);
+
+        // 目标先到，基准到达后才对齐
+        pair.append_target(target[0].clone(), false).unwrap();
+        pair.append_target(target[1].clone(), false).unwrap();
+        assert!(pair.relative_closes().is_empty());
+        pair.append_benchmark(benchmark[0].clone(), false).unwrap();
+        pair.append_benchmark(benchmark[1].clone(), false).unwrap();
+        assert_eq!(pair.relative_closes().len(), 2);
+
+        // 基准缺少第 3 根，只对齐两边都有的时间
+        pair.append_target(target[2].clone(), false).unwrap();
+        pair.append_target(target[3].clone(), false).unwrap();
+        pair.append_benchmark(benchmark[3].clone(), false).unwrap();
+        let closes = pair.relative_closes();
+        assert_eq!(
+            closes.iter().map(|(dt, _)| *dt).collect::<Vec<_>>(),
+            vec![target[0].dt, target[1].dt, target[3].dt]
+        );
+        assert!(closes.iter().all(|(_, c)| (c - 2.0).abs() < 1e-6));
+
+        // 对齐时间之前的K线报错，同一时间的K线为实时更新
+        assert!(pair.append_benchmark(benchmark[2].clone(), false).is_err());
+        let mut update = target[3].clone();
+        update.close = 17.0;
+        pair.append_target(update, false).unwrap();
+        let (dt, close) = *pair.relative_closes().last().unwrap();
+        assert_eq!((dt, close), (target[3].dt, 17.0 / benchmark[3].close));
+        assert_eq!(pair.relative_closes().len(), 3);
+    }
+
+    #[test]
+    fn divergence_once_per_bar() {
+        let mut pair = pair();
+        // 目标创新低，基准跌得更多，相对强弱没有新低
+        let target = zigzag(&[10.0, 8.0], 4);
+        let benchmark = zigzag(&[10.0, 5.0], 4);
+        let count =
+            |signals: Vec<Signal>| signals.iter().filter(|s| s.key.1 == "相对强弱背离").count();
+        let mut last = 0;
+        for (t, b) in target.iter().zip(&benchmark) {
+            pair.append_benchmark(b.clone(), false).unwrap();
+            last = count(pair.append_target(t.clone(), false).unwrap());
+        }
+        assert_eq!(last, 1);
+
+        // 最后一根K线的实时更新不再重复发出
+        let mut update = target[4].clone();
+        update.close = 7.9;
+        for _ in 0..2 {
+            assert_eq!(count(pair.append_target(update.clone(), false).unwrap()), 0);
+            assert_eq!(
+                count(pair.append_benchmark(benchmark[4].clone(), false).unwrap()),
+                0
+            );
+        }
+    }
+
+    #[test]
+    fn extreme_at_aligned_bar() {
+        let mut pair = pair();
+        // 基准第 5 根为新低，之后回升
+        let benchmark = zigzag(&[10.0, 6.0, 9.0], 4);
+        for bar in &benchmark {
+            pair.append_benchmark(bar.clone(), false).unwrap();
+        }
+        let at = |i: usize| benchmark[i].dt.timestamp();
+        assert!(ZenPair::is_extreme(&pair.benchmark, at(4), 20, true));
+        // 基准已走到第 9 根，按最后一根判断会得到错误结果
+        assert!(!ZenPair::is_extreme(&pair.benchmark, at(8), 20, true));
+        assert!(ZenPair::is_extreme(&pair.benchmark, at(8), 3, false));
+        assert!(!ZenPair::is_extreme(&pair.benchmark, at(0), 20, true));
+
+        // 目标在第 5 根出现底背驰，基准同一根K线新低
+        let signal = Signal {
+            value: ("底".to_string(), "5笔".to_string(), "other".to_string()),
+            ..Default::default()
+        };
+        let s = pair.index_extreme(&signal, at(4)).unwrap();
+        assert_eq!(s.value.1, "指数新低");
+        assert!(pair.index_extreme(&signal, at(8)).is_none());
+    }
+}
@@ -0,0 +1,341 @@
+use chrono::Duration;
+use pyo3::exceptions::PyValueError;
+use pyo3::{pyclass, pymethods, Py, PyResult, Python};
+
+use crate::calendar::Calendar;
+use crate::element::chan::{Bar, DT};
+use crate::element::event::Signal;
+use crate::store::Zen;
+use crate::utils::clock;
+
+// 行情回放：按虚拟时钟把历史K线逐根喂给 Zen，
+// 每根K线处理时的“当前时间”为该K线的时间，信号与通知的行为与当时实盘一致
+#[pyclass(unsendable)]
+pub(crate) struct Replayer {
+    zen: Py<Zen>,
+    bars: Vec<Bar>,
+    // 下一根待回放K线的下标
+    cursor: usize,
+    // 回放速度，现实 1 秒对应的行情秒数
+    speed: f64,
+    paused: bool,
+    // 虚拟当前时间
+    now: DT,
+    // 设置后休市时段不消耗回放时间，虚拟时钟直接跳到下一次开盘
+    calendar: Option<Calendar>,
+}
+
+impl Replayer {
+    // 回放 [cursor, end) 区间的K线
+    fn feed(&mut self, py: Python<'_>, end: usize) -> Vec<Signal> {
+        let mut result = vec![];
+        let mut zen = self.zen.borrow_mut(py);
+        for bar in &self.bars[self.cursor..end.max(self.cursor)] {
+            self.now = self.now.max(bar.dt);
+            result.extend(clock::with_now(bar.dt, || zen.append(bar.clone(), false)));
+        }
+        self.cursor = self.cursor.max(end);
+        result
+    }
+
+    // 快进到 end，照常计算但不通知、不写信号日志
+    fn skip_to(&mut self, py: Python<'_>, end: usize) {
+        let mut zen = self.zen.borrow_mut(py);
+        for bar in &self.bars[self.cursor..end.max(self.cursor)] {
+            self.now = self.now.max(bar.dt);
+            clock::with_now(bar.dt, || zen.append_silent(bar.clone()));
+        }
+        self.cursor = self.cursor.max(end);
+    }
+
+    // 时间不晚于 dt 的K线个数
+    fn end_of(&self, dt: DT) -> usize {
+        self.bars.partition_point(|b| b.dt <= dt)
+    }
+
+    // 回退到 dt 时清空 Zen 从头重放，复权设置不变，通知状态回退到 dt，已写入的信号日志重放时不重复写入
+    fn reset(&mut self, py: Python<'_>, dt: DT) {
+        let mut zen = self.zen.borrow_mut(py);
+        zen.clear();
+        zen.rewind_notify(dt);
+        self.cursor = 0;
+        self.now = self.bars[0].dt;
+    }
+}
+
+#[pymethods]
+impl Replayer {
+    // bars 须按时间升序，speed 为现实 1 秒对应的行情秒数，calendar 用于跳过休市时段
+    #[new]
+    #[pyo3(signature = (zen, bars, speed=60.0, calendar=None))]
+    fn new(zen: Py<Zen>, bars: Vec<Bar>, speed: f64, calendar: Option<Calendar>) -> PyResult<Self> {
+        let Some(first) = bars.first() else {
+            return Err(PyValueError::new_err("bars is empty"));
+        };
+        if let Some(i) = (1..bars.len()).find(|&i| bars[i].dt < bars[i - 1].dt) {
+            return Err(PyValueError::new_err(format!(
+                "bars[{}] dt {} is earlier than previous bar {}",
+                i,
+                bars[i].dt,
+                bars[i - 1].dt
+            )));
+        }
+        if !(speed.is_finite() && speed > 0.0) {
+            return Err(PyValueError::new_err("speed must be positive"));
+        }
+        Ok(Self {
+            now: first.dt,
+            zen,
+            bars,
+            cursor: 0,
+            speed,
+            paused: false,
+            calendar,
+        })
+    }
+
+    // 现实时间流逝 elapsed 秒，推进虚拟时钟并回放其间的K线，暂停时不推进
+    fn tick(&mut self, py: Python<'_>, elapsed: f64) -> Vec<Signal> {
+        if self.paused || elapsed <= 0.0 {
+            return vec![];
+        }
+        self.now += Duration::milliseconds((elapsed * self.speed * 1000.0) as i64);
+        if let Some(calendar) = &self.calendar {
+            if !calendar.is_open(self.now, true) {
+                self.now = calendar.next_open(self.now, true).unwrap_or(self.now);
+            }
+        }
+        self.feed(py, self.end_of(self.now))
+    }
+
+    // 回放接下来的 n 根K线，暂停时也可使用
+    #[pyo3(signature = (n=1))]
+    fn step(&mut self, py: Python<'_>, n: usize) -> Vec<Signal> {
+        let end = self.cursor.saturating_add(n).min(self.bars.len());
+        self.feed(py, end)
+    }
+
+    // 跳转到 dt，其间的K线照常计算但不返回信号、不通知；早于当前位置时从头重放
+    fn seek(&mut self, py: Python<'_>, dt: DT) {
+        if dt < self.now {
+            self.reset(py, dt);
+        }
+        self.skip_to(py, self.end_of(dt));
+        self.now = dt;
+    }
+
+    fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    #[getter]
+    fn paused(&self) -> bool {
+        self.paused
+    }
+
+    #[getter]
+    fn speed(&self) -> f64 {
+        self.speed
+    }
+
+    #[setter]
+    fn set_speed(&mut self, speed: f64) -> PyResult<()> {
+        if !(speed.is_finite() && speed > 0.0) {
+            return Err(PyValueError::new_err("speed must be positive"));
+        }
+        self.speed = speed;
+        Ok(())
+    }
+
+    // 虚拟当前时间
+    #[getter]
+    fn now(&self) -> DT {
+        self.now
+    }
+
+    // 已回放的K线数
+    #[getter]
+    fn position(&self) -> usize {
+        self.cursor
+    }
+
+    #[getter]
+    fn finished(&self) -> bool {
+        self.cursor >= self.bars.len()
+    }
+
+    #[getter]
+    fn zen(&self, py: Python<'_>) -> Py<Zen> {
+        self.zen.clone_ref(py)
+    }
+
+    fn __len__(&self) -> usize {
+        self.bars.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use chrono::{NaiveDateTime, TimeZone};
+    use pyo3::Py;
+
+    use super::*;
+    use crate::adjust::{AdjustMode, CorporateAction};
+    use crate::calendar::Market;
+    use crate::element::enums::Freq;
+    use crate::journal::{Journal, PyJournal};
+    use crate::setting::Settings;
+    use crate::store::tests::pivots;
+    use crate::tests::zigzag;
+    use crate::utils::notify::NotifierConfig;
+    use crate::utils::policy::NotifyPolicy;
+
+    #[test]
+    fn seek_silently_and_reset_keeps_state() {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let settings = Settings {
+                notifiers: vec![NotifierConfig::File { path: None }],
+                // 日线的信号时间早于当前K线多日
+                notify_policy: NotifyPolicy {
+                    max_age: 365 * 86400,
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            let bars = zigzag(&pivots(), 8);
+            let mut zen = Zen::new("A".to_string(), Freq::D, Some(settings), None).unwrap();
+            let journal = Py::new(
+                py,
+                PyJournal {
+                    journal: Rc::new(Journal::open(":memory:").unwrap()),
+                },
+            )
+            .unwrap();
+            zen.attach_journal(journal.borrow(py));
+            zen.set_adjust_mode(AdjustMode::Forward);
+            let action = CorporateAction::new(bars[60].dt, 1.0, 0.0).unwrap();
+            zen.add_corporate_action(action.clone());
+            let zen = Py::new(py, zen).unwrap();
+            let mut replayer = Replayer::new(zen.clone_ref(py), bars.clone(), 60.0, None).unwrap();
+            let recorded = || {
+                let journal = journal.borrow(py);
+                journal.signals(None, None, None, None, None).unwrap().len()
+            };
+            let decisions = || zen.borrow(py).notify_decisions(None).len();
+
+            // 快进到末尾之前，既不通知也不写信号日志
+            replayer.seek(py, bars[bars.len() - 2].dt);
+            assert_eq!(replayer.position(), bars.len() - 1);
+            assert!(!zen.borrow(py).bc_info().is_empty());
+            assert_eq!((recorded(), decisions()), (0, 0));
+
+            // 回退后重放，日志、通知状态及复权设置仍在
+            replayer.seek(py, bars[0].dt);
+            assert_eq!(replayer.position(), 1);
+            assert!(zen.borrow(py).bc_info().is_empty());
+            assert_eq!(zen.borrow(py).adjust(), AdjustMode::Forward);
+            assert_eq!(zen.borrow(py).corporate_actions(), vec![action]);
+            let signals = replayer.step(py, bars.len());
+            assert!(replayer.finished());
+            assert!(!signals.is_empty());
+            assert!(recorded() > 0 && decisions() > 0);
+        });
+    }
+
+    #[test]
+    fn rewind_resends_without_duplicate_journal() {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let settings = Settings {
+                notifiers: vec![NotifierConfig::File { path: None }],
+                notify_policy: NotifyPolicy {
+                    max_age: 365 * 86400,
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            let bars = zigzag(&pivots(), 8);
+            let mut zen = Zen::new("A".to_string(), Freq::D, Some(settings), None).unwrap();
+            let journal = Py::new(
+                py,
+                PyJournal {
+                    journal: Rc::new(Journal::open(":memory:").unwrap()),
+                },
+            )
+            .unwrap();
+            zen.attach_journal(journal.borrow(py));
+            let zen = Py::new(py, zen).unwrap();
+            let mut replayer = Replayer::new(zen.clone_ref(py), bars.clone(), 60.0, None).unwrap();
+            let recorded = || {
+                let journal = journal.borrow(py);
+                journal.signals(None, None, None, None, None).unwrap().len()
+            };
+            let sent = || {
+                let decisions = zen.borrow(py).notify_decisions(None);
+                decisions.iter().filter(|d| d.sent).count()
+            };
+
+            replayer.step(py, bars.len());
+            let (rows, first) = (recorded(), sent());
+            assert!(rows > 0 && first > 0);
+
+            // 回退后重放，通知照常发出，信号日志不重复
+            replayer.seek(py, bars[0].dt);
+            replayer.step(py, bars.len());
+            assert_eq!(sent(), first * 2);
+            assert_eq!(recorded(), rows);
+        });
+    }
+
+    #[test]
+    fn tick_skips_closed_market() {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let calendar = Calendar::new(Market::CN);
+            let at = |s: &str| {
+                let local = NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M").unwrap();
+                calendar
+                    .tz()
+                    .from_local_datetime(&local)
+                    .unwrap()
+                    .fixed_offset()
+            };
+            let bars: Vec<Bar> = ["2024-09-18 11:30", "2024-09-18 13:01", "2024-09-19 09:31"]
+                .iter()
+                .zip(zigzag(&[10.0, 12.0], 2))
+                .map(|(dt, bar)| Bar {
+                    dt: at(dt),
+                    freq: Freq::F1,
+                    ..bar
+                })
+                .collect();
+            let zen = Zen::new("A".to_string(), Freq::F1, Some(Settings::default()), None).unwrap();
+            let mut replayer = Replayer::new(
+                Py::new(py, zen).unwrap(),
+                bars,
+                60.0,
+                Some(calendar.clone()),
+            )
+            .unwrap();
+            replayer.step(py, 1);
+
+            // 午休跳到 13:00 开盘
+            replayer.tick(py, 30.0);
+            assert_eq!(replayer.now(), at("2024-09-18 13:00"));
+            assert_eq!(replayer.position(), 1);
+            replayer.tick(py, 1.0);
+            assert_eq!(replayer.position(), 2);
+            // 收市后跳到次日开盘
+            replayer.tick(py, 180.0);
+            assert_eq!(replayer.now(), at("2024-09-19 09:30"));
+            assert_eq!(replayer.position(), 2);
+        });
+    }
+}
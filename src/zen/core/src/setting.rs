@@ -1,19 +1,50 @@
+// #[pymethods] 生成的包装函数误报 useless_conversion，原因见 store.rs
+#![allow(clippy::useless_conversion)]
+
 use std::rc::Rc;
+use std::str::FromStr;
 use std::{env, fs};
 
 use crate::element::event::Matcher;
+use crate::utils::notify::NotifierConfig;
+use crate::utils::policy::NotifyPolicy;
+use chrono_tz::Tz;
 use config::{Config, ConfigError, Environment, File};
-use notify_rust::{get_bundle_identifier_or_default, set_application};
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+use pyo3::types::PyDict;
 use serde::Deserialize;
 use tracing::debug;
 
-#[derive(Debug, Deserialize, Copy, Clone)]
+#[pyclass(eq, eq_int)]
+#[derive(Debug, Deserialize, Copy, Clone, PartialEq)]
 pub enum BiType {
     Modern,
     Legacy,
     FourK,
 }
 
+impl BiType {
+    fn parse(s: &str) -> Result<Self, ConfigError> {
+        match s {
+            "Modern" => Ok(BiType::Modern),
+            "Legacy" => Ok(BiType::Legacy),
+            "FourK" => Ok(BiType::FourK),
+            _ => Err(ConfigError::Message(format!(
+                "unknown bi_type `{}`, expected one of Modern, Legacy, FourK",
+                s
+            ))),
+        }
+    }
+}
+
+pub fn parse_tz(name: &str) -> Result<Tz, ConfigError> {
+    Tz::from_str(name)
+        .map_err(|e| ConfigError::Message(format!("invalid timezone `{}`: {}", name, e)))
+}
+
+// 配置文件中前五项必须给出，通知及时区为后加的可选项
+#[pyclass(get_all)]
 #[derive(Debug, Deserialize, Clone)]
 #[allow(unused)]
 pub struct Settings {
@@ -22,9 +53,105 @@ pub struct Settings {
     pub bi_change_threshold: f32,
     pub max_bi_num: usize,
     pub event_matcher_file: String,
+    // 通知渠道，可同时配置多个，为空时不发送通知
+    #[serde(default)]
+    pub notifiers: Vec<NotifierConfig>,
+    #[serde(default)]
+    pub notify_policy: NotifyPolicy,
+    // 默认的交易所时区，如 Asia/Shanghai、America/New_York
+    #[serde(default = "default_timezone")]
+    pub timezone: String,
+}
+
+fn default_timezone() -> String {
+    "Asia/Shanghai".to_string()
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            debug: false,
+            bi_type: BiType::Modern,
+            bi_change_threshold: 1.0,
+            max_bi_num: 500,
+            event_matcher_file: "./config/event_matcher.yaml".to_string(),
+            notifiers: vec![],
+            notify_policy: NotifyPolicy::default(),
+            timezone: default_timezone(),
+        }
+    }
 }
 
 impl Settings {
+    // 从单个配置文件加载，缺少必需项时报错
+    pub fn from_file(path: &str) -> Result<Self, ConfigError> {
+        let s: Settings = Config::builder()
+            .add_source(File::with_name(path))
+            .build()?
+            .try_deserialize()?;
+        s.validate()?;
+        Ok(s)
+    }
+
+    // validate 已保证时区有效
+    pub fn tz(&self) -> Tz {
+        parse_tz(&self.timezone).unwrap_or(Tz::Asia__Shanghai)
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if !self.bi_change_threshold.is_finite() || self.bi_change_threshold < 0.0 {
+            return Err(ConfigError::Message(format!(
+                "bi_change_threshold must be a non-negative number, got {}",
+                self.bi_change_threshold
+            )));
+        }
+        if self.max_bi_num == 0 {
+            return Err(ConfigError::Message(
+                "max_bi_num must be greater than 0".to_string(),
+            ));
+        }
+        self.notify_policy.validate().map_err(ConfigError::Message)?;
+        parse_tz(&self.timezone)?;
+        Ok(())
+    }
+
+    fn update_from_dict(&mut self, dict: &Bound<'_, PyDict>) -> PyResult<()> {
+        for (key, value) in dict.iter() {
+            let key: String = key.extract()?;
+            let invalid = |e: PyErr| PyValueError::new_err(format!("invalid `{}`: {}", key, e));
+            match key.as_str() {
+                "debug" => self.debug = value.extract().map_err(invalid)?,
+                "bi_type" => {
+                    self.bi_type = match value.extract::<BiType>() {
+                        Ok(t) => t,
+                        Err(_) => {
+                            let s: String = value.extract().map_err(invalid)?;
+                            BiType::parse(&s).map_err(|e| PyValueError::new_err(e.to_string()))?
+                        }
+                    }
+                }
+                "bi_change_threshold" => {
+                    self.bi_change_threshold = value.extract().map_err(invalid)?
+                }
+                "max_bi_num" => self.max_bi_num = value.extract().map_err(invalid)?,
+                "event_matcher_file" => {
+                    self.event_matcher_file = value.extract().map_err(invalid)?
+                }
+                "notifiers" => self.notifiers = value.extract().map_err(invalid)?,
+                "notify_policy" => self.notify_policy = value.extract().map_err(invalid)?,
+                "timezone" => self.timezone = value.extract().map_err(invalid)?,
+                _ => {
+                    return Err(PyValueError::new_err(format!(
+                        "unknown setting `{}`",
+                        key
+                    )))
+                }
+            }
+        }
+        self.validate()
+            .map_err(|e| PyValueError::new_err(e.to_string()))
+    }
+
     pub fn new() -> Result<Self, ConfigError> {
         let run_mode = env::var("RUN_MODE").unwrap_or_else(|_| "dev".into());
 
@@ -45,9 +172,71 @@ impl Settings {
             .build()?;
 
         // You can deserialize (and thus freeze) the entire configuration as
-        let mut s: Settings = s.try_deserialize()?;
+        let s: Settings = s.try_deserialize()?;
 
         debug!("settings:\n {:?}", s);
+        s.validate()?;
         Ok(s)
     }
 }
+
+#[pymethods]
+impl Settings {
+    // Settings(bi_type="Legacy", max_bi_num=300)，未给出的项使用默认值
+    #[new]
+    #[pyo3(signature = (**kwargs))]
+    fn py_new(kwargs: Option<&Bound<'_, PyDict>>) -> PyResult<Self> {
+        let mut s = Settings::default();
+        if let Some(kwargs) = kwargs {
+            s.update_from_dict(kwargs)?;
+        }
+        Ok(s)
+    }
+
+    #[staticmethod]
+    fn from_dict(dict: &Bound<'_, PyDict>) -> PyResult<Self> {
+        Self::py_new(Some(dict))
+    }
+
+    #[staticmethod]
+    #[pyo3(name = "from_file")]
+    fn py_from_file(path: &str) -> PyResult<Self> {
+        Settings::from_file(path).map_err(|e| PyValueError::new_err(e.to_string()))
+    }
+
+    // 按 ./config/default、./config/{RUN_MODE}、./config/local 及 ZEN_ 环境变量加载
+    #[staticmethod]
+    fn load() -> PyResult<Self> {
+        Settings::new().map_err(|e| PyValueError::new_err(e.to_string()))
+    }
+
+    fn __repr__(&self) -> String {
+        format!("{:?}", self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(name: &str, yaml: &str) -> Result<Settings, ConfigError> {
+        let path = env::temp_dir().join(format!("zen-settings-{}.yaml", name));
+        fs::write(&path, yaml).unwrap();
+        let s = Settings::from_file(path.to_str().unwrap());
+        fs::remove_file(&path).unwrap();
+        s
+    }
+
+    #[test]
+    fn file_loading_is_strict() {
+        let base =
+            "debug: false\nbi_type: Legacy\nbi_change_threshold: 1\nevent_matcher_file: x.yaml\n";
+        let err = load("missing", base).unwrap_err();
+        assert!(err.to_string().contains("max_bi_num"), "{}", err);
+
+        let s = load("optional", &format!("{}max_bi_num: 300\n", base)).unwrap();
+        assert_eq!((s.bi_type, s.max_bi_num), (BiType::Legacy, 300));
+        assert!(s.notifiers.is_empty());
+        assert_eq!(s.timezone, "Asia/Shanghai");
+    }
+}
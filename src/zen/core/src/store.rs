@@ -1,21 +1,52 @@
-use crate::analyze::{Symbol, CZSC};
+// pyo3 0.22 为 #[pymethods] 中返回 PyResult 的方法生成的包装函数会多做一次 Into<PyErr>，
+// 包装函数与 impl 同级，impl 或方法上的 allow 管不到，只能放在模块上
+#![allow(clippy::useless_conversion)]
+
+use crate::adjust::{self, AdjustMode, CorporateAction};
+use crate::analyze::{check_fxs, Symbol, CZSC};
+use crate::calendar::Market;
 use crate::calculate::beichi::buy_sell_point::{BSPoint, BuySellPoint};
 use crate::calculate::others;
 use crate::calculate::others::sma_tracker::SMATracker;
-use crate::element::chan::{Bar, BI};
-use crate::element::enums::{Direction, Freq};
+use crate::calculate::zen_cache::{SMATrackerCache, SMAValuesCache};
+use crate::element::chan::{Bar, NewBar, BI, DT, FX};
+use crate::element::enums::{Direction, Freq, Mark};
 use crate::element::event::Signal;
-use crate::setting::Settings;
+use crate::export;
+use crate::ingest::BarColumns;
+use crate::journal::{Journal, PyJournal};
+use crate::setting::{parse_tz, Settings};
+use crate::utils::notify::Notify;
+use crate::utils::policy::NotifyDecision;
+use arrow::pyarrow::PyArrowType;
+use arrow::record_batch::RecordBatch;
 use dict_derive::{FromPyObject, IntoPyObject};
-use pyo3::{pyclass, pymethods};
-use serde::Serialize;
+use numpy::{AllowTypeChange, PyArrayLike1};
+use chrono::DateTime;
+use pyo3::exceptions::{PyIndexError, PyValueError};
+use pyo3::{pyclass, pymethods, Py, PyRef, PyResult, Python};
+use serde::{Deserialize, Serialize};
 use serde_json::json;
+use std::collections::HashMap;
 use std::fmt::format;
+use std::fs;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::mem::size_of;
+use std::path::PathBuf;
+use std::rc::Rc;
+use tracing::error;
 
 #[pyclass(unsendable)]
 pub(crate) struct Zen {
     pub czsc: CZSC,
     pub(crate) beichi_processor: BuySellPoint,
+    journal: Option<Rc<Journal>>,
+    notify: Notify,
+    // 复权方式及按除权日升序的公司行动
+    adjust: AdjustMode,
+    actions: Vec<CorporateAction>,
+    // 当前K线已应用的公司行动
+    applied: Vec<CorporateAction>,
 }
 
 #[derive(Serialize, Debug)]
@@ -38,30 +69,350 @@ impl ZenBiDetail {
     }
 }
 
+// 去除包含关系后的K线快照，raw_bars 为被合并的原始K线时间
+#[derive(Debug, Clone)]
+#[pyclass(frozen, get_all, name = "NewBar")]
+pub(super) struct ZenNewBar {
+    pub dt: DT,
+    pub open: f32,
+    pub close: f32,
+    pub high: f32,
+    pub low: f32,
+    pub vol: f32,
+    pub amount: f32,
+    pub raw_bars: Vec<DT>,
+}
+
+impl From<&NewBar> for ZenNewBar {
+    fn from(bar: &NewBar) -> Self {
+        Self {
+            dt: bar.dt,
+            open: bar.open,
+            close: bar.close,
+            high: bar.high,
+            low: bar.low,
+            vol: bar.vol,
+            amount: bar.amount,
+            raw_bars: bar.raw_bars.iter().map(|b| b.borrow().dt).collect(),
+        }
+    }
+}
+
 #[pymethods]
+impl ZenNewBar {
+    fn __repr__(&self) -> String {
+        format!("{:?}", self)
+    }
+}
+
+// 分型快照，elements 为构成分型的三根无包含K线
+#[derive(Debug, Clone)]
+#[pyclass(frozen, get_all, name = "FX")]
+pub(super) struct ZenFX {
+    pub dt: DT,
+    pub mark: Mark,
+    pub high: f32,
+    pub low: f32,
+    pub fx: f32,
+    pub elements: Vec<ZenNewBar>,
+}
+
+impl From<&FX> for ZenFX {
+    fn from(fx: &FX) -> Self {
+        Self {
+            dt: fx.dt,
+            mark: fx.mark.clone(),
+            high: fx.high,
+            low: fx.low,
+            fx: fx.fx,
+            elements: fx.elements.iter().map(|e| e.as_ref().into()).collect(),
+        }
+    }
+}
+
+#[pymethods]
+impl ZenFX {
+    fn __repr__(&self) -> String {
+        format!("{:?}", self)
+    }
+}
+
 impl Zen {
-    #[new]
-    pub fn new(sym: Symbol, freq: Freq) -> Self {
-        let mut ret = Self {
-            czsc: CZSC::new(sym, freq, Settings::new().unwrap()),
-            beichi_processor: BuySellPoint::new(),
+    // 写入信号日志，日志写入失败不影响计算
+    fn record(&mut self, signals: &[Signal]) {
+        let (added, removed) = self.beichi_processor.take_changes();
+        let (Some(journal), Some(bar_dt)) = (&self.journal, self.czsc.end()) else {
+            return;
         };
+        let symbol = &self.czsc.symbol;
+        let freq = self.czsc.freq;
+        let result = journal
+            .invalidate(symbol, freq, &removed)
+            .and_then(|_| journal.record_points(symbol, freq, bar_dt, &added))
+            .and_then(|_| journal.record_signals(symbol, freq, bar_dt, signals));
+        if let Err(e) = result {
+            error!("{} {:?} journal: {}", symbol, freq, e);
+        }
+    }
 
-        ret.czsc
-            .cache
+    // 推笔产生的买卖点尚未确认，不通知
+    fn notify(&self, signals: &[Signal]) {
+        let market = Market::from_tz(self.czsc.tz);
+        for s in signals.iter().filter(|s| s.key.2 != "推笔") {
+            if let Some(dt) = s.dt.or(self.czsc.end()) {
+                self.notify
+                    .notify_signal(&self.czsc.symbol, market, dt, s.clone());
+            }
+        }
+    }
+
+    // 估算占用内存（字节），只统计K线、笔及买卖点
+    pub fn memory_usage(&self) -> usize {
+        let new_bar = |n: &NewBar| size_of::<NewBar>() + n.raw_bars.len() * size_of::<usize>();
+        self.czsc.bars_raw.len() * size_of::<Bar>()
+            + self.czsc.bars_ubi.iter().map(|n| new_bar(n)).sum::<usize>()
+            + self
+                .czsc
+                .bi_list
+                .iter()
+                .map(|bi| size_of::<BI>() + bi.bars.iter().map(|n| new_bar(n)).sum::<usize>())
+                .sum::<usize>()
+            + self.beichi_processor.beichi_tracker.len() * size_of::<BSPoint>()
+    }
+
+    // 支持 python 风格的负数下标
+    fn bi_at(&self, index: isize) -> PyResult<&BI> {
+        let len = self.czsc.bi_list.len() as isize;
+        let i = if index < 0 { len + index } else { index };
+        if i < 0 || i >= len {
+            return Err(PyIndexError::new_err(format!(
+                "bi index {} out of range, {} bi in total",
+                index, len
+            )));
+        }
+        Ok(&self.czsc.bi_list[i as usize])
+    }
+
+    fn new_czsc(sym: Symbol, freq: Freq, settings: Settings) -> CZSC {
+        let mut czsc = CZSC::new(sym, freq, settings);
+        czsc.cache
             .insert(SMATracker::new(vec![15, 30, 60, 120, 200]));
-        ret
+        czsc
     }
 
-    pub fn append(&mut self, bar: Bar, skip_process: bool) -> Vec<Signal> {
+    // 按新的复权方式及公司行动重新复权已有K线，有K线变化时重新计算，返回是否重新计算
+    fn readjust(&mut self, mode: AdjustMode, applied: Vec<CorporateAction>) -> bool {
+        let mut changed = false;
+        let bars: Vec<Bar> = self
+            .czsc
+            .bars_raw
+            .iter()
+            .map(|b| {
+                let old = b.borrow();
+                let mut bar = old.clone();
+                adjust::restore(&mut bar, &self.applied, self.adjust);
+                adjust::apply(&mut bar, &applied, mode);
+                changed |= (bar.open, bar.close, bar.high, bar.low, bar.vol)
+                    != (old.open, old.close, old.high, old.low, old.vol);
+                bar
+            })
+            .collect();
+        self.adjust = mode;
+        self.applied = applied;
+        if changed {
+            self.rebuild(bars);
+        }
+        changed
+    }
+
+    // 用复权后的K线重放，只覆盖仍保留在 bars_raw 中的K线；重放期间不通知，
+    // 重放结束后在信号日志中撤销旧买卖点并写入新买卖点
+    fn rebuild(&mut self, bars: Vec<Bar>) {
+        let old = std::mem::take(&mut self.beichi_processor.beichi_tracker);
+        self.czsc = Self::new_czsc(
+            self.czsc.symbol.clone(),
+            self.czsc.freq,
+            self.czsc.settings.clone(),
+        );
+        self.beichi_processor = BuySellPoint::new();
+        for bar in bars {
+            let is_new = self.czsc.update(bar);
+            self.beichi_processor.process(&mut self.czsc, is_new, None);
+            others::sma_tracker::process(&mut self.czsc, is_new, None);
+        }
+        self.beichi_processor.take_changes();
+
+        let (Some(journal), Some(bar_dt)) = (&self.journal, self.czsc.end()) else {
+            return;
+        };
+        let symbol = &self.czsc.symbol;
+        let freq = self.czsc.freq;
+        let result = journal.invalidate(symbol, freq, &old).and_then(|_| {
+            journal.record_points(symbol, freq, bar_dt, &self.beichi_processor.beichi_tracker)
+        });
+        if let Err(e) = result {
+            error!("{} {:?} journal: {}", symbol, freq, e);
+        }
+    }
+
+    // 清空K线及计算结果，保留信号日志、通知渠道、复权方式及公司行动
+    pub fn clear(&mut self) {
+        self.czsc = Self::new_czsc(
+            self.czsc.symbol.clone(),
+            self.czsc.freq,
+            self.czsc.settings.clone(),
+        );
+        self.beichi_processor = BuySellPoint::new();
+        self.applied = vec![];
+    }
+
+    // 回放回退到 dt，丢弃之后的通知去重及限流状态
+    pub(crate) fn rewind_notify(&self, dt: DT) {
+        self.notify.rewind(dt);
+    }
+
+    // 不复权的原始K线
+    pub fn unadjusted_bars(&self) -> Vec<Bar> {
+        self.czsc
+            .bars_raw
+            .iter()
+            .map(|b| {
+                let mut bar = b.borrow().clone();
+                adjust::restore(&mut bar, &self.applied, self.adjust);
+                bar
+            })
+            .collect()
+    }
+
+    // 加入公司行动，同一除权日的替换旧的
+    pub fn add_action(&mut self, action: CorporateAction) -> bool {
+        self.actions.retain(|a| a.ex_dt != action.ex_dt);
+        let i = self.actions.partition_point(|a| a.ex_dt < action.ex_dt);
+        self.actions.insert(i, action);
+        let applied = match self.czsc.end() {
+            Some(end) => {
+                let n = self.actions.partition_point(|a| a.ex_dt <= end);
+                self.actions[..n].to_vec()
+            }
+            None => vec![],
+        };
+        self.readjust(self.adjust, applied)
+    }
+
+    // 复权并计算，不写信号日志也不通知
+    fn update(&mut self, mut bar: Bar, skip_process: bool) -> Vec<Signal> {
+        if self.adjust != AdjustMode::None {
+            let n = self.actions.partition_point(|a| a.ex_dt <= bar.dt);
+            if self.actions[..n].iter().any(|a| !self.applied.contains(a)) {
+                self.readjust(self.adjust, self.actions[..n].to_vec());
+            }
+            adjust::apply(&mut bar, &self.applied, self.adjust);
+        }
         let is_new = self.czsc.update(bar);
+        if skip_process {
+            return vec![];
+        }
+        let signals = self.beichi_processor.process(&mut self.czsc, is_new, None);
+        others::sma_tracker::process(&mut self.czsc, is_new, None);
+        signals
+    }
+
+    // 照常计算但不通知、不写信号日志，用于快照恢复及回放跳转；期间的买卖点变化直接丢弃
+    pub fn append_silent(&mut self, bar: Bar) {
+        let journal = self.journal.take();
+        self.update(bar, false);
+        self.beichi_processor.take_changes();
+        self.journal = journal;
+    }
+
+    fn append_columns(&mut self, columns: &BarColumns, skip_process: bool) -> PyResult<Vec<Signal>> {
+        let bars = columns
+            .to_bars(self.czsc.freq, self.czsc.end())
+            .map_err(PyValueError::new_err)?;
+        let mut signals = vec![];
+        for bar in bars {
+            signals.extend(self.append(bar, skip_process));
+        }
+        Ok(signals)
+    }
+}
+
+#[pymethods]
+impl Zen {
+    // 未传入 settings 时从 ./config 目录加载，tz 为交易所时区，缺省时使用 settings.timezone
+    #[new]
+    #[pyo3(signature = (sym, freq, settings=None, tz=None))]
+    pub fn new(
+        sym: Symbol,
+        freq: Freq,
+        settings: Option<Settings>,
+        tz: Option<&str>,
+    ) -> PyResult<Self> {
+        let mut settings = match settings {
+            Some(s) => s,
+            None => Settings::new().map_err(|e| PyValueError::new_err(e.to_string()))?,
+        };
+        if let Some(tz) = tz {
+            parse_tz(tz).map_err(|e| PyValueError::new_err(e.to_string()))?;
+            settings.timezone = tz.to_string();
+        }
+        let notify = Notify::new(&settings.notifiers, &settings.notify_policy);
+        Ok(Self {
+            czsc: Self::new_czsc(sym, freq, settings),
+            beichi_processor: BuySellPoint::new(),
+            journal: None,
+            notify,
+            adjust: AdjustMode::None,
+            actions: vec![],
+            applied: vec![],
+        })
+    }
+
+    // bar 为不复权K线，按当前复权方式复权后再进入 CZSC
+    pub fn append(&mut self, bar: Bar, skip_process: bool) -> Vec<Signal> {
+        let signals = self.update(bar, skip_process);
         if !skip_process {
-            let signals = self.beichi_processor.process(&mut self.czsc, is_new, None);
-            others::sma_tracker::process(&mut self.czsc, is_new, None);
-            signals
-        } else {
-            vec![]
+            self.record(&signals);
+            self.notify(&signals);
         }
+        signals
+    }
+
+    // 批量追加K线，dt 为秒级 unix 时间戳；校验与计算都在 rust 中完成，校验失败时不追加任何K线
+    #[pyo3(signature = (dt, open, close, high, low, vol, amount=None, skip_process=false))]
+    #[allow(clippy::too_many_arguments)]
+    pub fn append_many(
+        &mut self,
+        dt: PyArrayLike1<'_, i64, AllowTypeChange>,
+        open: PyArrayLike1<'_, f64, AllowTypeChange>,
+        close: PyArrayLike1<'_, f64, AllowTypeChange>,
+        high: PyArrayLike1<'_, f64, AllowTypeChange>,
+        low: PyArrayLike1<'_, f64, AllowTypeChange>,
+        vol: PyArrayLike1<'_, f64, AllowTypeChange>,
+        amount: Option<PyArrayLike1<'_, f64, AllowTypeChange>>,
+        skip_process: bool,
+    ) -> PyResult<Vec<Signal>> {
+        let columns = BarColumns {
+            dt: dt.as_array().to_vec(),
+            open: open.as_array().to_vec(),
+            close: close.as_array().to_vec(),
+            high: high.as_array().to_vec(),
+            low: low.as_array().to_vec(),
+            vol: vol.as_array().to_vec(),
+            amount: amount.map(|a| a.as_array().to_vec()),
+        };
+        self.append_columns(&columns, skip_process)
+    }
+
+    // 批量追加 arrow RecordBatch 中的K线，列名为 dt/open/high/low/close/vol/amount
+    #[pyo3(signature = (batch, skip_process=false))]
+    pub fn append_record_batch(
+        &mut self,
+        batch: PyArrowType<RecordBatch>,
+        skip_process: bool,
+    ) -> PyResult<Vec<Signal>> {
+        let columns = BarColumns::from_record_batch(&batch.0).map_err(PyValueError::new_err)?;
+        self.append_columns(&columns, skip_process)
     }
 
     pub fn bi_info(&self) -> Vec<ZenBiDetail> {
@@ -87,10 +438,193 @@ impl Zen {
         ret
     }
 
+    // 复权方式
+    #[getter]
+    pub fn adjust(&self) -> AdjustMode {
+        self.adjust
+    }
+
+    // 切换复权方式，已有K线重新复权并重新计算，返回是否重新计算
+    pub fn set_adjust_mode(&mut self, mode: AdjustMode) -> bool {
+        self.readjust(mode, self.applied.clone())
+    }
+
+    // 加入公司行动，影响已加载K线时重新计算，返回是否重新计算
+    pub fn add_corporate_action(&mut self, action: CorporateAction) -> bool {
+        self.add_action(action)
+    }
+
+    pub fn corporate_actions(&self) -> Vec<CorporateAction> {
+        self.actions.clone()
+    }
+
+    // 交易所时区名，如 Asia/Shanghai
+    #[getter]
+    fn tz(&self) -> &'static str {
+        self.czsc.tz.name()
+    }
+
+    // 把之后产生的信号及买卖点写入信号日志
+    pub fn attach_journal(&mut self, journal: PyRef<PyJournal>) {
+        self.journal = Some(journal.journal.clone());
+    }
+
+    fn detach_journal(&mut self) {
+        self.journal = None;
+    }
+
+    // 与 other 共享通知渠道及策略状态，用于判断多级别共振
+    fn share_notify(&mut self, other: PyRef<Zen>) {
+        self.notify = other.notify.clone();
+    }
+
+    // 最近的通知决策，用于排查通知为什么发送或未发送
+    #[pyo3(signature = (limit=None))]
+    pub fn notify_decisions(&self, limit: Option<usize>) -> Vec<NotifyDecision> {
+        let decisions = self.notify.decisions();
+        let skip = limit.map(|n| decisions.len().saturating_sub(n)).unwrap_or(0);
+        decisions.into_iter().skip(skip).collect()
+    }
+
+    // 估算占用内存（字节）
+    #[pyo3(name = "memory_usage")]
+    pub fn py_memory_usage(&self) -> usize {
+        self.memory_usage()
+    }
+
+    // 均线周期
+    pub fn ma_periods(&self) -> Vec<isize> {
+        self.czsc
+            .cache
+            .get::<SMATrackerCache>()
+            .map(|smas| smas.periods().to_vec())
+            .unwrap_or_default()
+    }
+
+    // 当前均线值，未满周期或未跟踪该周期时返回 None
+    pub fn ma(&self, period: isize) -> Option<f32> {
+        self.czsc
+            .cache
+            .get::<SMATrackerCache>()
+            .and_then(|smas| smas.ma(period))
+    }
+
+    // 历史均线值，只覆盖仍保留在 bars_raw 中的K线
+    pub fn ma_history(&self, period: isize) -> Vec<(DT, f32)> {
+        self.czsc
+            .bars_raw
+            .iter()
+            .filter_map(|bar| {
+                let bar = bar.borrow();
+                bar.cache
+                    .get::<SMAValuesCache>()
+                    .and_then(|values| values.0.get(&period))
+                    .map(|v| (bar.dt, *v))
+            })
+            .collect()
+    }
+
+    // 每根原始K线的 MACD(4, 9, 9)：(dif, dea, macd)
+    pub fn macd(&self) -> Vec<(DT, f32, f32, f32)> {
+        self.czsc
+            .bars_raw
+            .iter()
+            .map(|bar| {
+                let bar = bar.borrow();
+                (bar.dt, bar.macd_4_9_9.0, bar.macd_4_9_9.1, bar.macd_4_9_9.2)
+            })
+            .collect()
+    }
+
+    // 最新一根K线上所有已注册指标的值，如 MA15、MACD_DIF
+    pub fn indicators(&self) -> HashMap<String, f32> {
+        let mut ret = HashMap::new();
+        if let Some(bar) = self.czsc.bars_raw.last() {
+            let bar = bar.borrow();
+            ret.insert("MACD_DIF".to_string(), bar.macd_4_9_9.0);
+            ret.insert("MACD_DEA".to_string(), bar.macd_4_9_9.1);
+            ret.insert("MACD".to_string(), bar.macd_4_9_9.2);
+            if let Some(values) = bar.cache.get::<SMAValuesCache>() {
+                for (p, v) in &values.0 {
+                    ret.insert(format!("MA{}", p), *v);
+                }
+            }
+        }
+        ret
+    }
+
+    // 笔内的无包含K线
+    pub fn bi_bars(&self, index: isize) -> PyResult<Vec<ZenNewBar>> {
+        Ok(self
+            .bi_at(index)?
+            .bars
+            .iter()
+            .map(|b| b.as_ref().into())
+            .collect())
+    }
+
+    // 笔内的分型列表，包含起止分型
+    pub fn bi_fxs(&self, index: isize) -> PyResult<Vec<ZenFX>> {
+        Ok(self
+            .bi_at(index)?
+            .fxs
+            .iter()
+            .map(|f| f.as_ref().into())
+            .collect())
+    }
+
+    // 笔的起止分型 (fx_a, fx_b)
+    pub fn bi_endpoints(&self, index: isize) -> PyResult<(ZenFX, ZenFX)> {
+        let bi = self.bi_at(index)?;
+        Ok((bi.fx_a.as_ref().into(), bi.fx_b.as_ref().into()))
+    }
+
+    // 未完成笔区域的无包含K线
+    pub fn ubi_bars(&self) -> Vec<ZenNewBar> {
+        self.czsc
+            .bars_ubi
+            .iter()
+            .map(|b| b.as_ref().into())
+            .collect()
+    }
+
+    // 未完成笔区域的分型
+    pub fn ubi_fxs(&self) -> Vec<ZenFX> {
+        check_fxs(&self.czsc.bars_ubi)
+            .iter()
+            .map(|f| f.as_ref().into())
+            .collect()
+    }
+
     pub fn bc_info(&self) -> Vec<BSPoint> {
         self.beichi_processor.beichi_tracker.clone()
     }
 
+    // 以下 arrow_* 方法返回 pyarrow.RecordBatch，可直接交给 pandas / polars
+    pub fn arrow_bars(&self) -> PyArrowType<RecordBatch> {
+        PyArrowType(export::bars(&self.czsc))
+    }
+
+    pub fn arrow_new_bars(&self) -> PyArrowType<RecordBatch> {
+        PyArrowType(export::new_bars(&self.czsc))
+    }
+
+    pub fn arrow_fxs(&self) -> PyArrowType<RecordBatch> {
+        PyArrowType(export::fxs(&self.czsc))
+    }
+
+    pub fn arrow_bis(&self) -> PyArrowType<RecordBatch> {
+        PyArrowType(export::bis(&self.czsc))
+    }
+
+    pub fn arrow_zs(&self) -> PyArrowType<RecordBatch> {
+        PyArrowType(export::zs(&self.czsc))
+    }
+
+    pub fn arrow_bs_points(&self) -> PyArrowType<RecordBatch> {
+        PyArrowType(export::bs_points(&self.beichi_processor.beichi_tracker))
+    }
+
     pub fn json(&self) -> String {
         let last_dir = self
             .czsc
@@ -106,7 +640,7 @@ impl Zen {
                     .iter()
                     .skip(1)
                     .min_by(|a, b| a.low.partial_cmp(&b.low).unwrap())
-                    .map(|a| a.clone())
+                    .cloned()
                     .unwrap();
                 ZenBiDetail {
                     direction: String::from(Direction::Down.as_str()),
@@ -123,7 +657,7 @@ impl Zen {
                     .iter()
                     .skip(1)
                     .max_by(|a, b| a.high.partial_cmp(&b.high).unwrap())
-                    .map(|a| a.clone())
+                    .cloned()
                     .unwrap();
                 ZenBiDetail {
                     direction: String::from(Direction::Up.as_str()),
@@ -142,3 +676,529 @@ impl Zen {
         .to_string()
     }
 }
+
+// 快照文件中的一根原始K线
+#[derive(Serialize, Deserialize)]
+struct BarRecord {
+    dt: String,
+    open: f32,
+    close: f32,
+    high: f32,
+    low: f32,
+    vol: f32,
+    amount: f32,
+}
+
+type ZenKey = (Symbol, Freq);
+
+// 进程内管理多个标的、多个级别的 Zen，按 LRU 淘汰，淘汰时可选地把原始K线快照到磁盘，
+// 再次访问时从快照重放恢复
+#[pyclass(unsendable)]
+pub(crate) struct ZenStore {
+    store: HashMap<ZenKey, Py<Zen>>,
+    // 最近访问序号
+    last_used: HashMap<ZenKey, u64>,
+    tick: u64,
+    settings: Settings,
+    max_items: Option<usize>,
+    // 内存预算（字节），按 Zen::memory_usage 估算
+    max_memory: Option<usize>,
+    snapshot_dir: Option<PathBuf>,
+    // 所有 Zen 共享的通知渠道及策略状态
+    notify: Notify,
+    // 各标的的交易所时区，未设置的使用 settings.timezone
+    timezones: HashMap<Symbol, String>,
+    // 复权方式及各标的的公司行动
+    adjust: AdjustMode,
+    actions: HashMap<Symbol, Vec<CorporateAction>>,
+}
+
+impl ZenStore {
+    fn snapshot_path(&self, key: &ZenKey) -> Option<PathBuf> {
+        let symbol: String = key
+            .0
+            .chars()
+            .map(|c| if c.is_alphanumeric() { c } else { '_' })
+            .collect();
+        self.snapshot_dir
+            .as_ref()
+            .map(|d| d.join(format!("{}_{}.jsonl", symbol, key.1.as_str())))
+    }
+
+    fn touch(&mut self, key: &ZenKey) {
+        self.tick += 1;
+        self.last_used.insert(key.clone(), self.tick);
+    }
+
+    fn load(&mut self, py: Python<'_>, key: &ZenKey) -> PyResult<Py<Zen>> {
+        self.touch(key);
+        if let Some(zen) = self.store.get(key) {
+            return Ok(zen.clone_ref(py));
+        }
+
+        let tz = self.timezones.get(&key.0).map(|tz| tz.as_str());
+        let mut zen = Zen::new(key.0.clone(), key.1, Some(self.settings.clone()), tz)?;
+        zen.adjust = self.adjust;
+        zen.actions = self.actions.get(&key.0).cloned().unwrap_or_default();
+        zen.notify = self.notify.clone();
+        if let Some(path) = self.snapshot_path(key).filter(|p| p.exists()) {
+            let file = fs::File::open(&path)?;
+            let mut bars = vec![];
+            for line in BufReader::new(file).lines() {
+                let r: BarRecord = serde_json::from_str(&line?)
+                    .map_err(|e| PyValueError::new_err(format!("{}: {}", path.display(), e)))?;
+                let dt = DateTime::parse_from_rfc3339(&r.dt)
+                    .map_err(|e| PyValueError::new_err(format!("{}: {}", path.display(), e)))?;
+                bars.push(Bar {
+                    dt,
+                    freq: key.1,
+                    open: r.open,
+                    close: r.close,
+                    high: r.high,
+                    low: r.low,
+                    vol: r.vol,
+                    amount: r.amount,
+                    cache: Default::default(),
+                    macd_4_9_9: (0.0, 0.0, 0.0),
+                });
+            }
+            // 快照为不复权K线，按快照结束时已生效的公司行动整体复权，避免重放中途反复重新计算
+            if let Some(end) = bars.last().map(|b| b.dt) {
+                let n = zen.actions.partition_point(|a| a.ex_dt <= end);
+                zen.applied = zen.actions[..n].to_vec();
+            }
+            // 快照中的信号淘汰前已经处理过，恢复时不再通知
+            for bar in bars {
+                zen.append_silent(bar);
+            }
+            fs::remove_file(&path)?;
+        }
+        let zen = Py::new(py, zen)?;
+        self.store.insert(key.clone(), zen.clone_ref(py));
+        Ok(zen)
+    }
+
+    // 先写快照再移除，写快照失败时保留内存中的 Zen
+    fn evict(&mut self, py: Python<'_>, key: &ZenKey) -> PyResult<()> {
+        if let (Some(zen), Some(path)) = (self.store.get(key), self.snapshot_path(key)) {
+            fs::create_dir_all(path.parent().unwrap())?;
+            let tmp = path.with_extension("jsonl.tmp");
+            let mut writer = BufWriter::new(fs::File::create(&tmp)?);
+            for bar in zen.borrow(py).unadjusted_bars() {
+                let record = BarRecord {
+                    dt: bar.dt.to_rfc3339(),
+                    open: bar.open,
+                    close: bar.close,
+                    high: bar.high,
+                    low: bar.low,
+                    vol: bar.vol,
+                    amount: bar.amount,
+                };
+                writeln!(writer, "{}", serde_json::to_string(&record).unwrap())?;
+            }
+            writer.flush()?;
+            fs::rename(&tmp, &path)?;
+        }
+        self.last_used.remove(key);
+        self.store.remove(key);
+        Ok(())
+    }
+
+    fn total_memory(&self, py: Python<'_>) -> usize {
+        self.store
+            .values()
+            .map(|z| z.borrow(py).memory_usage())
+            .sum()
+    }
+
+    // 超出数量或内存预算时淘汰最久未使用的 Zen，keep 为刚访问的 key，不会被淘汰
+    fn enforce_budget(&mut self, py: Python<'_>, keep: &ZenKey) -> PyResult<()> {
+        loop {
+            let over_items = self
+                .max_items
+                .map(|m| self.store.len() > m)
+                .unwrap_or(false);
+            let over_memory = self
+                .max_memory
+                .map(|m| self.total_memory(py) > m)
+                .unwrap_or(false);
+            if !over_items && !over_memory {
+                return Ok(());
+            }
+            let victim = self
+                .last_used
+                .iter()
+                .filter(|(k, _)| *k != keep)
+                .min_by_key(|(_, t)| **t)
+                .map(|(k, _)| k.clone());
+            match victim {
+                Some(k) => self.evict(py, &k)?,
+                None => return Ok(()),
+            }
+        }
+    }
+}
+
+#[pymethods]
+impl ZenStore {
+    #[new]
+    #[pyo3(signature = (settings=None, max_items=None, max_memory=None, snapshot_dir=None, adjust=AdjustMode::None))]
+    fn new(
+        settings: Option<Settings>,
+        max_items: Option<usize>,
+        max_memory: Option<usize>,
+        snapshot_dir: Option<PathBuf>,
+        adjust: AdjustMode,
+    ) -> PyResult<Self> {
+        let settings = match settings {
+            Some(s) => s,
+            None => Settings::new().map_err(|e| PyValueError::new_err(e.to_string()))?,
+        };
+        Ok(Self {
+            store: Default::default(),
+            last_used: Default::default(),
+            tick: 0,
+            notify: Notify::new(&settings.notifiers, &settings.notify_policy),
+            timezones: Default::default(),
+            adjust,
+            actions: Default::default(),
+            settings,
+            max_items,
+            max_memory,
+            snapshot_dir,
+        })
+    }
+
+    // 设置标的的交易所时区，对之后创建的 Zen 生效
+    fn set_timezone(&mut self, symbol: Symbol, tz: &str) -> PyResult<()> {
+        parse_tz(tz).map_err(|e| PyValueError::new_err(e.to_string()))?;
+        self.timezones.insert(symbol, tz.to_string());
+        Ok(())
+    }
+
+    // 加入某标的的公司行动，已加载的各级别 Zen 按需重新计算，返回重新计算的级别
+    fn add_corporate_action(
+        &mut self,
+        py: Python<'_>,
+        symbol: Symbol,
+        action: CorporateAction,
+    ) -> Vec<Freq> {
+        let actions = self.actions.entry(symbol.clone()).or_default();
+        actions.retain(|a| a.ex_dt != action.ex_dt);
+        actions.push(action.clone());
+        actions.sort_by_key(|a| a.ex_dt);
+
+        let mut recomputed = vec![];
+        for ((s, freq), zen) in &self.store {
+            if *s == symbol && zen.borrow_mut(py).add_action(action.clone()) {
+                recomputed.push(*freq);
+            }
+        }
+        recomputed
+    }
+
+    // 获取（必要时创建或从快照恢复）某标的某级别的 Zen
+    fn get(&mut self, py: Python<'_>, symbol: Symbol, freq: Freq) -> PyResult<Py<Zen>> {
+        let key = (symbol, freq);
+        let zen = self.load(py, &key)?;
+        self.enforce_budget(py, &key)?;
+        Ok(zen)
+    }
+
+    // 把K线路由到对应的 Zen
+    #[pyo3(signature = (symbol, freq, bar, skip_process=false))]
+    fn append(
+        &mut self,
+        py: Python<'_>,
+        symbol: Symbol,
+        freq: Freq,
+        bar: Bar,
+        skip_process: bool,
+    ) -> PyResult<Vec<Signal>> {
+        let key = (symbol, freq);
+        let zen = self.load(py, &key)?;
+        let signals = zen.borrow_mut(py).append(bar, skip_process);
+        self.enforce_budget(py, &key)?;
+        Ok(signals)
+    }
+
+    // 移除某标的某级别的 Zen，snapshot 为 True 时先写快照
+    #[pyo3(signature = (symbol, freq, snapshot=false))]
+    fn remove(
+        &mut self,
+        py: Python<'_>,
+        symbol: Symbol,
+        freq: Freq,
+        snapshot: bool,
+    ) -> PyResult<()> {
+        let key = (symbol, freq);
+        if snapshot {
+            self.evict(py, &key)
+        } else {
+            self.last_used.remove(&key);
+            self.store.remove(&key);
+            Ok(())
+        }
+    }
+
+    fn keys(&self) -> Vec<ZenKey> {
+        self.store.keys().cloned().collect()
+    }
+
+    fn __len__(&self) -> usize {
+        self.store.len()
+    }
+
+    // 估算的总内存占用（字节）
+    fn memory_usage(&self, py: Python<'_>) -> usize {
+        self.total_memory(py)
+    }
+
+    // 最近 n 根K线内出现买卖点的标的，返回 (symbol, freq, BSPoint)
+    fn with_bs_point(&self, py: Python<'_>, n: usize) -> Vec<(Symbol, Freq, BSPoint)> {
+        let mut result = vec![];
+        for (key, zen) in &self.store {
+            let zen = zen.borrow(py);
+            let bars = &zen.czsc.bars_raw;
+            let Some(since) = bars
+                .iter()
+                .rev()
+                .nth(n.saturating_sub(1))
+                .or(bars.first())
+                .map(|b| b.borrow().dt.timestamp())
+            else {
+                continue;
+            };
+            for bs in &zen.beichi_processor.beichi_tracker {
+                if bs.dt >= since {
+                    result.push((key.0.clone(), key.1, bs.clone()));
+                }
+            }
+        }
+        result.sort_by_key(|r| std::cmp::Reverse(r.2.dt));
+        result
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::tests::zigzag;
+    use crate::utils::clock;
+    use crate::utils::policy::NotifyPolicy;
+
+    fn zen() -> Zen {
+        Zen::new("TEST".to_string(), Freq::D, Some(Settings::default()), None).unwrap()
+    }
+
+    #[test]
+    fn split_rebuilds_and_invalidates_journal() {
+        let journal = Rc::new(Journal::open(":memory:").unwrap());
+        let points = |active: bool| {
+            let journal = PyJournal {
+                journal: journal.clone(),
+            };
+            journal
+                .bs_points(None, None, None, None, !active, None)
+                .unwrap()
+        };
+        let mut zen = zen();
+        zen.journal = Some(journal.clone());
+        zen.set_adjust_mode(AdjustMode::Forward);
+        for bar in zigzag(&pivots(), 8) {
+            zen.append(bar, false);
+        }
+        let before = zen.bi_info();
+        let recorded = points(true);
+        assert!(!recorded.is_empty() && recorded.len() == zen.bc_info().len());
+
+        // 在已加载的K线中间拆股 1 拆 2，除权日之前的价格减半
+        let ex_dt = before[before.len() / 2].end_ts;
+        let ex_dt = DateTime::from_timestamp(ex_dt, 0).unwrap().fixed_offset();
+        let action = CorporateAction::new(ex_dt, 2.0, 0.0).unwrap();
+        assert!(zen.add_corporate_action(action));
+        let after = zen.bi_info();
+        assert_eq!(after.len(), before.len());
+        assert!((after[0].start - before[0].start / 2.0).abs() < 1e-3);
+        let last = before.len() - 1;
+        assert!((after[last].end - before[last].end).abs() < 1e-3);
+
+        // 原有买卖点全部撤销，按重算结果重新记录
+        let all = points(false);
+        assert_eq!(all.len(), recorded.len() + zen.bc_info().len());
+        assert!(all[..recorded.len()]
+            .iter()
+            .all(|p| p.invalidated_dt.is_some()));
+        let active = points(true);
+        assert_eq!(
+            active.iter().map(|p| p.price).collect::<Vec<_>>(),
+            zen.bc_info().iter().map(|p| p.price).collect::<Vec<_>>()
+        );
+        assert!(active.iter().any(|p| p.dt < ex_dt));
+    }
+
+    #[test]
+    fn ma_macd_indicators() {
+        let mut zen = zen();
+        let bars = zigzag(&[10.0, 30.0, 15.0, 25.0, 12.0], 60);
+        let closes: Vec<f32> = bars.iter().map(|b| b.close).collect();
+        for bar in bars {
+            zen.append(bar, false);
+        }
+
+        assert_eq!(zen.ma_periods(), vec![15, 30, 60, 120, 200]);
+        let ma15 = closes[closes.len() - 15..].iter().sum::<f32>() / 15.0;
+        assert!((zen.ma(15).unwrap() - ma15).abs() < 1e-3);
+        assert_eq!(zen.ma(7), None);
+
+        // 只覆盖仍保留的K线，且第 15 根K线起才有 MA15
+        let kept = zen.czsc.bars_raw.len();
+        assert!(kept > 15 && kept < closes.len());
+        let history = zen.ma_history(15);
+        assert_eq!(history.len(), kept);
+        assert_eq!(history.last().unwrap().1, zen.ma(15).unwrap());
+        assert!(zen.ma_history(200).len() < kept);
+
+        let macd = zen.macd();
+        assert_eq!(macd.len(), kept);
+        let (_, dif, dea, hist) = *macd.last().unwrap();
+        let indicators = zen.indicators();
+        assert_eq!(indicators["MACD_DIF"], dif);
+        assert_eq!(indicators["MACD_DEA"], dea);
+        assert_eq!(indicators["MACD"], hist);
+        assert_eq!(indicators["MA15"], zen.ma(15).unwrap());
+        assert_eq!(indicators["MA200"], zen.ma(200).unwrap());
+    }
+
+    #[test]
+    fn new_bars_and_fxs() {
+        let mut zen = zen();
+        let mut bars = zigzag(&[10.0, 20.0, 14.0, 18.0, 13.0], 8);
+        // 第 12 根K线被第 11 根包含，合并为一根无包含K线
+        bars[11].high = bars[10].high - 0.01;
+        bars[11].low = bars[10].low + 0.01;
+        let inside = bars[11].dt;
+        for bar in bars {
+            zen.append(bar, false);
+        }
+
+        let bi = &zen.czsc.bi_list[0];
+        let new_bars = zen.bi_bars(0).unwrap();
+        assert_eq!(new_bars.len(), bi.bars.len());
+        for (snapshot, bar) in new_bars.iter().zip(&bi.bars) {
+            assert_eq!(
+                (snapshot.dt, snapshot.high, snapshot.low),
+                (bar.dt, bar.high, bar.low)
+            );
+            assert_eq!(snapshot.raw_bars.len(), bar.raw_bars.len());
+        }
+        assert!(new_bars
+            .iter()
+            .any(|b| b.raw_bars.len() == 2 && b.raw_bars[1] == inside));
+
+        let (fx_a, fx_b) = zen.bi_endpoints(-1).unwrap();
+        let last = zen.czsc.bi_list.last().unwrap();
+        assert_eq!((fx_a.dt, fx_b.dt), (last.fx_a.dt, last.fx_b.dt));
+        assert_eq!(fx_b.elements.len(), 3);
+        assert_eq!(fx_b.elements[1].dt, fx_b.dt);
+        let fxs = zen.bi_fxs(-1).unwrap();
+        assert_eq!(fxs.first().unwrap().dt, fx_a.dt);
+        assert_eq!(fxs.last().unwrap().dt, fx_b.dt);
+
+        let n = zen.czsc.bi_list.len() as isize;
+        assert!(zen.bi_bars(n).is_err());
+        assert!(zen.bi_fxs(-n - 1).is_err());
+        assert_eq!(zen.ubi_bars().len(), zen.czsc.bars_ubi.len());
+    }
+
+    pub(crate) fn pivots() -> Vec<f32> {
+        vec![
+            30.0, 20.0, 24.0, 21.0, 25.0, 22.0, 26.0, 12.0, 16.0, 13.0, 17.0, 14.0, 18.0, 11.0,
+            15.0, 13.0, 19.0,
+        ]
+    }
+
+    #[test]
+    fn evict_and_restore_silently() {
+        pyo3::prepare_freethreaded_python();
+        Python::with_gil(|py| {
+            let dir = std::env::temp_dir().join(format!("zen-store-{}", std::process::id()));
+            let log = dir.join("notify.log");
+            fs::create_dir_all(&dir).unwrap();
+            let settings = Settings {
+                notifiers: vec![crate::utils::notify::NotifierConfig::File {
+                    path: Some(log.to_string_lossy().to_string()),
+                }],
+                // 日线的信号时间早于当前K线多日
+                notify_policy: NotifyPolicy {
+                    max_age: 365 * 86400,
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            let mut store = ZenStore::new(
+                Some(settings),
+                Some(1),
+                None,
+                Some(dir.clone()),
+                AdjustMode::None,
+            )
+            .unwrap();
+            let bars = zigzag(&pivots(), 8);
+            let a = ("A".to_string(), Freq::D);
+            // 按K线时间通知，否则历史信号因过期而不发送
+            for bar in &bars {
+                clock::with_now(bar.dt, || {
+                    store.append(py, a.0.clone(), a.1, bar.clone(), false)
+                })
+                .unwrap();
+            }
+            // 快照只含仍保留的K线，恢复结果与直接用这些K线计算一致
+            let mut expected = Zen::new(a.0.clone(), a.1, Some(Settings::default()), None).unwrap();
+            let decisions = {
+                let zen = store.get(py, a.0.clone(), a.1).unwrap();
+                let zen = zen.borrow(py);
+                for bar in zen.unadjusted_bars() {
+                    expected.append(bar, false);
+                }
+                zen.notify_decisions(None).len()
+            };
+            let (bis, points) = (expected.bi_info().len(), expected.bc_info());
+            let sent = fs::read_to_string(&log).unwrap().lines().count();
+            assert!(!points.is_empty() && sent > 0);
+
+            // 访问 B 时淘汰 A 并写快照，再访问 A 时从快照恢复
+            store.get(py, "B".to_string(), Freq::D).unwrap();
+            assert_eq!(store.keys(), vec![("B".to_string(), Freq::D)]);
+            let last = bars.last().unwrap().dt;
+            let zen = clock::with_now(last, || store.get(py, a.0.clone(), a.1)).unwrap();
+            let zen = zen.borrow(py);
+            assert_eq!(zen.bi_info().len(), bis);
+            assert_eq!(
+                zen.bc_info().iter().map(|p| p.dt).collect::<Vec<_>>(),
+                points.iter().map(|p| p.dt).collect::<Vec<_>>()
+            );
+            // 恢复时没有重新通知
+            assert_eq!(fs::read_to_string(&log).unwrap().lines().count(), sent);
+            assert_eq!(zen.notify_decisions(None).len(), decisions);
+            drop(zen);
+
+            // 快照目录不可写时保留内存中的 Zen
+            let mut store = ZenStore::new(
+                Some(Settings::default()),
+                None,
+                None,
+                Some(log.clone()),
+                AdjustMode::None,
+            )
+            .unwrap();
+            store
+                .append(py, a.0.clone(), a.1, bars[0].clone(), false)
+                .unwrap();
+            assert!(store.remove(py, a.0.clone(), a.1, true).is_err());
+            assert_eq!(store.keys(), vec![a.clone()]);
+
+            fs::remove_dir_all(&dir).unwrap();
+        });
+    }
+}
@@ -20,6 +20,10 @@ impl SMA {
         self.sum / self.period as f32
     }
 
+    pub fn is_ready(&self) -> bool {
+        self.queue.len() >= self.period as usize
+    }
+
     pub fn last(&self) -> f32 {
         *self.queue.back().unwrap_or(&0.0)
     }
@@ -0,0 +1,31 @@
+use std::cell::Cell;
+
+use chrono::Local;
+
+use crate::element::chan::DT;
+
+// 可注入的时钟：默认取系统时间，行情回放时切换为虚拟时间，
+// 使回放中的信号、通知与实盘当时的行为一致
+
+thread_local! {
+    static VIRTUAL_NOW: Cell<Option<DT>> = const { Cell::new(None) };
+}
+
+pub fn now() -> DT {
+    VIRTUAL_NOW
+        .with(|c| c.get())
+        .unwrap_or_else(|| Local::now().fixed_offset())
+}
+
+// 在虚拟时间 dt 下执行 f，结束后恢复之前的时钟
+pub fn with_now<T>(dt: DT, f: impl FnOnce() -> T) -> T {
+    struct Restore(Option<DT>);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            VIRTUAL_NOW.with(|c| c.set(self.0));
+        }
+    }
+
+    let _restore = Restore(VIRTUAL_NOW.with(|c| c.replace(Some(dt))));
+    f()
+}
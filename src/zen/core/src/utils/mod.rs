@@ -1 +1,3 @@
+pub mod clock;
 pub mod notify;
+pub mod policy;
@@ -1,54 +1,473 @@
-use cached::proc_macro::cached;
-use chrono::{Duration, Local};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::rc::Rc;
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+use std::thread;
+use std::time::Duration as StdDuration;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use chrono::Utc;
+use hmac::{Hmac, Mac};
 use notify_rust::Notification;
+use pyo3::pyclass;
+use serde::Deserialize;
+use serde_json::json;
+use sha2::Sha256;
+use tracing::error;
+
 use crate::analyze::Symbol;
+use crate::calendar::Market;
 use crate::element::chan::DT;
-use crate::element::event::{Event, Factor, Signal};
+use crate::element::event::Signal;
+use crate::utils::clock;
+use crate::utils::policy::{Candidate, NotifyDecision, NotifyPolicy, PolicyState};
+
+#[derive(Clone)]
+pub struct Message {
+    pub title: String,
+    pub subtitle: String,
+    pub body: String,
+    pub dt: DT,
+}
+
+impl Message {
+    fn to_json(&self) -> serde_json::Value {
+        json!({
+            "title": self.title,
+            "subtitle": self.subtitle,
+            "body": self.body,
+            "dt": self.dt.to_rfc3339(),
+        })
+    }
+}
+
+pub trait Notifier {
+    fn notify(&self, msg: &Message) -> Result<(), String>;
+}
+
+// 通知渠道配置，在 Settings.notifiers 中按列表组合，例如
+// notifiers:
+//   - type: feishu
+//     webhook: https://open.feishu.cn/open-apis/bot/v2/hook/xxx
+//   - type: file
+//     path: ./notify.log
+#[pyclass]
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum NotifierConfig {
+    // 系统桌面通知，sound 缺省时 macOS 使用 Submarine
+    #[pyo3(constructor = (sound=None))]
+    Desktop { sound: Option<String> },
+    // 飞书自定义机器人，secret 为签名校验密钥
+    #[pyo3(constructor = (webhook, secret=None))]
+    Feishu {
+        webhook: String,
+        secret: Option<String>,
+    },
+    // 以 json POST 到任意地址
+    #[pyo3(constructor = (url, headers=HashMap::new()))]
+    Webhook {
+        url: String,
+        #[serde(default)]
+        headers: HashMap<String, String>,
+    },
+    // 按行追加 json 到文件，path 缺省时输出到 stdout
+    #[pyo3(constructor = (path=None))]
+    File { path: Option<String> },
+}
+
+impl NotifierConfig {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Desktop { .. } => "desktop",
+            Self::Feishu { .. } => "feishu",
+            Self::Webhook { .. } => "webhook",
+            Self::File { .. } => "file",
+        }
+    }
+
+    pub fn build(&self) -> Box<dyn Notifier> {
+        match self.clone() {
+            Self::Desktop { sound } => Box::new(DesktopNotifier { sound }),
+            Self::Feishu { webhook, secret } => Box::new(BackgroundNotifier::spawn(
+                "feishu",
+                FeishuNotifier { webhook, secret },
+            )),
+            Self::Webhook { url, headers } => Box::new(BackgroundNotifier::spawn(
+                "webhook",
+                WebhookNotifier { url, headers },
+            )),
+            Self::File { path } => Box::new(FileNotifier { path }),
+        }
+    }
+}
+
+// 后台线程排队发送的最大条数
+const QUEUE_SIZE: usize = 256;
 
-pub struct Notify {}
+// 在后台线程中发送网络通知，避免请求超时阻塞K线处理；发送失败只记录日志
+pub struct BackgroundNotifier {
+    tx: SyncSender<Message>,
+}
+
+impl BackgroundNotifier {
+    fn spawn(name: &'static str, inner: impl Notifier + Send + 'static) -> Self {
+        let (tx, rx) = mpsc::sync_channel::<Message>(QUEUE_SIZE);
+        // 所有 Sender 释放后线程退出
+        thread::Builder::new()
+            .name(format!("notify-{}", name))
+            .spawn(move || {
+                for msg in rx {
+                    if let Err(e) = inner.notify(&msg) {
+                        error!("notify {} via {}: {}", msg.title, name, e);
+                    }
+                }
+            })
+            .expect("failed to spawn notifier thread");
+        Self { tx }
+    }
+}
+
+impl Notifier for BackgroundNotifier {
+    fn notify(&self, msg: &Message) -> Result<(), String> {
+        self.tx.try_send(msg.clone()).map_err(|e| match e {
+            TrySendError::Full(_) => "notify queue is full".to_string(),
+            TrySendError::Disconnected(_) => "notifier thread exited".to_string(),
+        })
+    }
+}
+
+pub struct DesktopNotifier {
+    sound: Option<String>,
+}
+
+impl Notifier for DesktopNotifier {
+    fn notify(&self, msg: &Message) -> Result<(), String> {
+        let mut n = Notification::new();
+        n.summary(&msg.title)
+            .subtitle(&msg.subtitle)
+            .body(&msg.body);
+        #[cfg(target_os = "macos")]
+        n.sound_name(self.sound.as_deref().unwrap_or("Submarine"));
+        #[cfg(not(target_os = "macos"))]
+        if let Some(sound) = &self.sound {
+            n.sound_name(sound);
+        }
+        n.show().map(|_| ()).map_err(|e| e.to_string())
+    }
+}
+
+fn post_json(
+    url: &str,
+    headers: &HashMap<String, String>,
+    body: &serde_json::Value,
+) -> Result<String, String> {
+    let mut req = ureq::post(url)
+        .timeout(StdDuration::from_secs(5))
+        .set("Content-Type", "application/json");
+    for (k, v) in headers {
+        req = req.set(k, v);
+    }
+    req.send_string(&body.to_string())
+        .map_err(|e| e.to_string())?
+        .into_string()
+        .map_err(|e| e.to_string())
+}
+
+pub struct FeishuNotifier {
+    webhook: String,
+    secret: Option<String>,
+}
+
+impl FeishuNotifier {
+    // 签名为以 "timestamp\nsecret" 为密钥对空串做 HmacSHA256 后 base64
+    fn sign(timestamp: i64, secret: &str) -> String {
+        let mac = Hmac::<Sha256>::new_from_slice(format!("{}\n{}", timestamp, secret).as_bytes())
+            .expect("hmac accepts any key length");
+        STANDARD.encode(mac.finalize().into_bytes())
+    }
+}
+
+impl Notifier for FeishuNotifier {
+    fn notify(&self, msg: &Message) -> Result<(), String> {
+        let mut body = json!({
+            "msg_type": "text",
+            "content": {"text": format!("{}\n{}\n{}", msg.title, msg.subtitle, msg.body)},
+        });
+        if let Some(secret) = &self.secret {
+            // 飞书校验服务器时间，这里不能用回放的虚拟时间
+            let timestamp = Utc::now().timestamp();
+            body["timestamp"] = json!(timestamp.to_string());
+            body["sign"] = json!(Self::sign(timestamp, secret));
+        }
+        let resp = post_json(&self.webhook, &HashMap::new(), &body)?;
+        let resp: serde_json::Value = serde_json::from_str(&resp).map_err(|e| e.to_string())?;
+        match resp["code"].as_i64() {
+            Some(0) | None => Ok(()),
+            Some(code) => Err(format!("feishu error {}: {}", code, resp["msg"])),
+        }
+    }
+}
+
+pub struct WebhookNotifier {
+    url: String,
+    headers: HashMap<String, String>,
+}
+
+impl Notifier for WebhookNotifier {
+    fn notify(&self, msg: &Message) -> Result<(), String> {
+        post_json(&self.url, &self.headers, &msg.to_json()).map(|_| ())
+    }
+}
+
+pub struct FileNotifier {
+    path: Option<String>,
+}
+
+impl Notifier for FileNotifier {
+    fn notify(&self, msg: &Message) -> Result<(), String> {
+        let line = msg.to_json().to_string();
+        match &self.path {
+            Some(path) => OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .and_then(|mut f| writeln!(f, "{}", line))
+                .map_err(|e| e.to_string()),
+            None => {
+                println!("{}", line);
+                Ok(())
+            }
+        }
+    }
+}
+
+// 按通知策略分发到各渠道，clone 后共享渠道及策略状态，多个级别的 Zen 共享时才能判断共振
+#[derive(Clone)]
+pub struct Notify {
+    // 渠道名为 类型#下标，同类型的多个渠道分别限流
+    channels: Rc<Vec<(String, Box<dyn Notifier>)>>,
+    state: Rc<RefCell<PolicyState>>,
+}
 
 impl Notify {
-    pub fn notify_signal(symbol: &Symbol, dt: DT, signal: Signal) {
-        notify(
+    pub fn new(configs: &[NotifierConfig], policy: &NotifyPolicy) -> Self {
+        Self {
+            channels: Rc::new(
+                configs
+                    .iter()
+                    .enumerate()
+                    .map(|(i, c)| (format!("{}#{}", c.name(), i), c.build()))
+                    .collect(),
+            ),
+            state: Rc::new(RefCell::new(PolicyState::new(policy.clone()))),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.channels.is_empty()
+    }
+
+    // 最近的通知决策，按时间先后
+    pub fn decisions(&self) -> Vec<NotifyDecision> {
+        self.state.borrow().decisions().cloned().collect()
+    }
+
+    // 回放回退时丢弃晚于 to 的策略状态，共享该状态的其他级别一并回退
+    pub fn rewind(&self, to: DT) {
+        self.state.borrow_mut().rewind(to);
+    }
+
+    // market 为标的所在市场，用于按该市场的交易日历判断是否在交易时段
+    pub fn notify_signal(&self, symbol: &Symbol, market: Option<Market>, dt: DT, signal: Signal) {
+        let event = if signal.key.2 == "other" {
+            signal.key.1.clone()
+        } else {
+            format!("{}_{}", signal.key.1, signal.key.2)
+        };
+        self.notify(
+            Candidate {
+                symbol,
+                freq: &signal.key.0,
+                event: &event,
+                direction: &signal.value.0,
+                dt,
+                realtime: true,
+                market,
+            },
             format!("{} - {}", symbol, signal.key()),
-            Some(format!("{}", dt.format("%Y-%m-%d %H:%M"))),
             signal.value(),
-            dt,
-            true,
         );
     }
 
-    pub fn notify_event(symbol: &Symbol, dt: DT, event: &Event, factor: &Factor) {
-        notify(
-            format!("{} - {}", symbol, event.name.clone()),
-            Some(format!("{}", dt.format("%Y-%m-%d %H:%M"))),
-            factor
-                .signals_all
-                .iter()
-                .map(|x| format!("{:?}", x))
-                .collect::<Vec<_>>()
-                .join("\n"),
-            dt,
-            false,
-        );
+    fn notify(&self, c: Candidate, title: String, body: String) {
+        if self.is_empty() {
+            return;
+        }
+        let names: Vec<String> = self.channels.iter().map(|(n, _)| n.clone()).collect();
+        let Some((escalated, allowed)) = self.state.borrow_mut().decide(&c, &names, clock::now())
+        else {
+            return;
+        };
+        let msg = Message {
+            title: if escalated {
+                format!("[多级别共振] {}", title)
+            } else {
+                title
+            },
+            subtitle: format!("{}", c.dt.format("%Y-%m-%d %H:%M")),
+            body,
+            dt: c.dt,
+        };
+        for i in allowed {
+            let (name, n) = &self.channels[i];
+            if let Err(e) = n.notify(&msg) {
+                error!("notify {} via {}: {}", msg.title, name, e);
+            }
+        }
     }
 }
 
-#[cached(size = 1000)]
-fn notify(
-    title: String,
-    subtitle: Option<String>,
-    body: String,
-    dt: DT,
-    realtime: bool,
-) {
-    if !realtime || dt > Local::now() - Duration::hours(2) {
-        Notification::new()
-            .summary(title.as_str())
-            .subtitle(subtitle.unwrap_or("".to_string()).as_str())
-            .body(body.as_str())
-            .sound_name("Submarine")
-            .show()
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Read};
+    use std::net::TcpListener;
+    use std::thread;
+
+    use super::*;
+
+    // 本地 HTTP 替身，接收一个请求并返回 response，得到请求体
+    fn serve_once(response: &'static str) -> (String, thread::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let handle = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut len = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if line == "\r\n" {
+                    break;
+                }
+                if let Some(v) = line.to_lowercase().strip_prefix("content-length:") {
+                    len = v.trim().parse().unwrap();
+                }
+            }
+            let mut body = vec![0; len];
+            reader.read_exact(&mut body).unwrap();
+            write!(
+                reader.get_mut(),
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+                response.len(),
+                response
+            )
             .unwrap();
+            String::from_utf8(body).unwrap()
+        });
+        (url, handle)
+    }
+
+    fn message() -> Message {
+        Message {
+            title: "000001.SH - F5_D1-MACD面积背驰_BS".to_string(),
+            subtitle: "2024-01-02 10:00".to_string(),
+            body: "底_3笔".to_string(),
+            dt: clock::now(),
+        }
+    }
+
+    #[test]
+    fn feishu_signed() {
+        let (url, handle) = serve_once(r#"{"code":0,"msg":"success"}"#);
+        let notifier = FeishuNotifier {
+            webhook: url,
+            secret: Some("secret".to_string()),
+        };
+        notifier.notify(&message()).unwrap();
+        let body: serde_json::Value = serde_json::from_str(&handle.join().unwrap()).unwrap();
+        assert_eq!(body["msg_type"], "text");
+        assert!(body["content"]["text"].as_str().unwrap().contains("底_3笔"));
+        let timestamp: i64 = body["timestamp"].as_str().unwrap().parse().unwrap();
+        assert_eq!(body["sign"], FeishuNotifier::sign(timestamp, "secret"));
+    }
+
+    #[test]
+    fn feishu_error_code() {
+        let (url, handle) = serve_once(r#"{"code":19021,"msg":"sign match fail"}"#);
+        let notifier = FeishuNotifier {
+            webhook: url,
+            secret: None,
+        };
+        assert!(notifier.notify(&message()).is_err());
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn send_in_background() {
+        // 只建立连接不响应，同步发送会等到超时
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let config = NotifierConfig::Webhook {
+            url: format!("http://{}/hook", listener.local_addr().unwrap()),
+            headers: HashMap::new(),
+        };
+        let notifier = config.build();
+        let start = std::time::Instant::now();
+        for _ in 0..3 {
+            notifier.notify(&message()).unwrap();
+        }
+        assert!(start.elapsed() < StdDuration::from_secs(1));
+    }
+
+    #[test]
+    fn webhook_and_dedup() {
+        let (url, handle) = serve_once("ok");
+        let notify = Notify::new(
+            &[NotifierConfig::Webhook {
+                url,
+                headers: HashMap::new(),
+            }],
+            &NotifyPolicy::default(),
+        );
+        let signal = Signal {
+            key: (
+                "F5".to_string(),
+                "D1-MACD面积背驰".to_string(),
+                "BS".to_string(),
+            ),
+            value: ("底".to_string(), "3笔".to_string(), "other".to_string()),
+            ..Default::default()
+        };
+        let dt = clock::now();
+        for _ in 0..2 {
+            notify.notify_signal(&"000001.SH".to_string(), None, dt, signal.clone());
+        }
+        let body: serde_json::Value = serde_json::from_str(&handle.join().unwrap()).unwrap();
+        assert_eq!(body["title"], "000001.SH - F5_D1-MACD面积背驰_BS");
+        assert_eq!(body["body"], "底_3笔");
+        let sent: Vec<_> = notify.decisions().iter().map(|d| d.sent).collect();
+        assert_eq!(sent, vec![true, false]);
+    }
+
+    #[test]
+    fn settings_notifiers() {
+        let configs: Vec<NotifierConfig> = serde_yaml::from_str(
+            "- type: desktop\n- type: feishu\n  webhook: http://localhost/hook\n- type: file\n",
+        )
+        .unwrap();
+        assert_eq!(
+            configs,
+            vec![
+                NotifierConfig::Desktop { sound: None },
+                NotifierConfig::Feishu {
+                    webhook: "http://localhost/hook".to_string(),
+                    secret: None
+                },
+                NotifierConfig::File { path: None },
+            ]
+        );
     }
 }
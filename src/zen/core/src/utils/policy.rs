@@ -0,0 +1,454 @@
+use std::collections::{HashMap, VecDeque};
+
+use chrono::{Duration, NaiveTime};
+use pyo3::exceptions::PyValueError;
+use pyo3::{pyclass, pymethods, PyResult};
+use serde::Deserialize;
+use tracing::info;
+
+use crate::calendar::{Calendar, Market};
+use crate::element::chan::DT;
+
+// 通知策略：同一标的同一事件的去重窗口、多级别共振升级、静默时段、交易时段及各渠道限流
+#[pyclass(get_all)]
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct NotifyPolicy {
+    // 实时信号只通知多少秒以内的
+    pub max_age: i64,
+    // 同一标的、级别、事件在窗口（秒）内只通知一次
+    pub dedup_window: i64,
+    // 窗口（秒）内有 escalate_freqs 个级别出现同向同一事件时升级为共振通知，小于 2 时不升级
+    pub escalate_freqs: usize,
+    pub escalate_window: i64,
+    // 静默时段，如 "22:00-07:00"
+    pub quiet_hours: Vec<String>,
+    // 设置后只在常规交易时段内通知，按标的所在市场的交易日历处理午休、休市日及提前收市，
+    // 标的市场未知时使用该市场；为空时不限制
+    pub market: Option<Market>,
+    // 每个渠道每分钟最多发送条数，0 为不限
+    pub rate_limit: usize,
+}
+
+impl Default for NotifyPolicy {
+    fn default() -> Self {
+        Self {
+            max_age: 2 * 3600,
+            dedup_window: 3600,
+            escalate_freqs: 2,
+            escalate_window: 3600,
+            quiet_hours: vec![],
+            market: None,
+            rate_limit: 0,
+        }
+    }
+}
+
+// "HH:MM-HH:MM"，结束早于开始时表示跨越午夜
+fn parse_range(s: &str) -> Result<(NaiveTime, NaiveTime), String> {
+    let parse = |t: &str| {
+        NaiveTime::parse_from_str(t.trim(), "%H:%M")
+            .map_err(|e| format!("invalid time range `{}`: {}", s, e))
+    };
+    let (start, end) = s
+        .split_once('-')
+        .ok_or_else(|| format!("invalid time range `{}`, expected HH:MM-HH:MM", s))?;
+    Ok((parse(start)?, parse(end)?))
+}
+
+fn in_ranges(ranges: &[String], t: NaiveTime) -> bool {
+    ranges
+        .iter()
+        .filter_map(|r| parse_range(r).ok())
+        .any(|(start, end)| {
+            if start <= end {
+                start <= t && t < end
+            } else {
+                t >= start || t < end
+            }
+        })
+}
+
+impl NotifyPolicy {
+    pub fn validate(&self) -> Result<(), String> {
+        for r in &self.quiet_hours {
+            parse_range(r)?;
+        }
+        if self.max_age < 0 || self.dedup_window < 0 || self.escalate_window < 0 {
+            return Err("notify policy windows must be non-negative".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[pymethods]
+impl NotifyPolicy {
+    #[new]
+    #[pyo3(signature = (max_age=7200, dedup_window=3600, escalate_freqs=2, escalate_window=3600, quiet_hours=vec![], market=None, rate_limit=0))]
+    fn py_new(
+        max_age: i64,
+        dedup_window: i64,
+        escalate_freqs: usize,
+        escalate_window: i64,
+        quiet_hours: Vec<String>,
+        market: Option<Market>,
+        rate_limit: usize,
+    ) -> PyResult<Self> {
+        let policy = Self {
+            max_age,
+            dedup_window,
+            escalate_freqs,
+            escalate_window,
+            quiet_hours,
+            market,
+            rate_limit,
+        };
+        policy.validate().map_err(PyValueError::new_err)?;
+        Ok(policy)
+    }
+
+    fn __repr__(&self) -> String {
+        format!("{:?}", self)
+    }
+}
+
+// 一次通知决策，channel 为空表示在分发到渠道之前被拦截
+#[derive(Debug, Clone)]
+#[pyclass(frozen, get_all)]
+pub struct NotifyDecision {
+    pub dt: DT,
+    pub symbol: String,
+    pub freq: String,
+    pub event: String,
+    pub channel: Option<String>,
+    pub sent: bool,
+    pub escalated: bool,
+    pub reason: String,
+}
+
+#[pymethods]
+impl NotifyDecision {
+    fn __repr__(&self) -> String {
+        format!("{:?}", self)
+    }
+}
+
+// 待决策的通知
+pub struct Candidate<'a> {
+    pub symbol: &'a str,
+    pub freq: &'a str,
+    // 事件名，不含级别
+    pub event: &'a str,
+    // 方向，如 底/顶
+    pub direction: &'a str,
+    // 信号时间
+    pub dt: DT,
+    // 实时信号需要检查时效
+    pub realtime: bool,
+    // 标的所在市场，由 Zen 的时区推断
+    pub market: Option<Market>,
+}
+
+// 策略的运行状态，多个 Zen 共享同一个状态时才能判断多级别共振
+pub struct PolicyState {
+    pub policy: NotifyPolicy,
+    // (标的, 级别, 事件, 方向) 最近一次发送时间
+    last_sent: HashMap<(String, String, String, String), DT>,
+    // (标的, 事件, 方向) 最近出现的级别及时间
+    recent: HashMap<(String, String, String), Vec<(String, DT)>>,
+    // 按渠道下标记录最近一分钟的发送时间，同类型的多个渠道分别限流
+    channel_sent: Vec<VecDeque<DT>>,
+    // 按市场缓存的交易日历，不同市场的标的共享同一个状态
+    calendars: HashMap<Market, Calendar>,
+    decisions: VecDeque<NotifyDecision>,
+}
+
+const MAX_DECISIONS: usize = 1000;
+
+impl PolicyState {
+    pub fn new(policy: NotifyPolicy) -> Self {
+        Self {
+            policy,
+            last_sent: Default::default(),
+            recent: Default::default(),
+            channel_sent: Default::default(),
+            calendars: Default::default(),
+            decisions: Default::default(),
+        }
+    }
+
+    pub fn decisions(&self) -> impl DoubleEndedIterator<Item = &NotifyDecision> {
+        self.decisions.iter()
+    }
+
+    fn log(&mut self, decision: NotifyDecision) {
+        info!(
+            "notify {} {} {} {:?}: {} ({})",
+            decision.symbol,
+            decision.freq,
+            decision.event,
+            decision.channel,
+            if decision.sent { "sent" } else { "suppressed" },
+            decision.reason
+        );
+        if self.decisions.len() >= MAX_DECISIONS {
+            self.decisions.pop_front();
+        }
+        self.decisions.push_back(decision);
+    }
+
+    // 标的所在市场的交易日历，未限制交易时段时为 None
+    fn calendar(&mut self, market: Option<Market>) -> Option<&Calendar> {
+        let default = self.policy.market?;
+        let market = market.unwrap_or(default);
+        Some(
+            self.calendars
+                .entry(market)
+                .or_insert_with(|| Calendar::new(market)),
+        )
+    }
+
+    // 回放回退到 to 时丢弃之后的发送记录，否则重放的通知都会被当作窗口内重复而拦截；决策日志保留
+    pub fn rewind(&mut self, to: DT) {
+        self.last_sent.retain(|_, t| *t <= to);
+        self.recent.retain(|_, seen| {
+            seen.retain(|(_, t)| *t <= to);
+            !seen.is_empty()
+        });
+        for sent in &mut self.channel_sent {
+            sent.retain(|t| *t <= to);
+        }
+    }
+
+    // 返回是否为共振通知及允许发送的渠道下标，channels 为各渠道在决策日志中的名称，now 为当前时间
+    pub fn decide(
+        &mut self,
+        c: &Candidate,
+        channels: &[String],
+        now: DT,
+    ) -> Option<(bool, Vec<usize>)> {
+        let decision =
+            |channel: Option<String>, sent: bool, escalated: bool, reason: &str| NotifyDecision {
+                dt: now,
+                symbol: c.symbol.to_string(),
+                freq: c.freq.to_string(),
+                event: format!("{}_{}", c.event, c.direction),
+                channel,
+                sent,
+                escalated,
+                reason: reason.to_string(),
+            };
+
+        // 静默时段按信号所在时区判断
+        let local = now.with_timezone(c.dt.offset());
+        let reject = if c.realtime && c.dt <= now - Duration::seconds(self.policy.max_age) {
+            Some("stale signal")
+        } else if in_ranges(&self.policy.quiet_hours, local.time()) {
+            Some("quiet hours")
+        } else if self
+            .calendar(c.market)
+            .map(|cal| !cal.is_open(now, false))
+            .unwrap_or(false)
+        {
+            Some("outside trading session")
+        } else {
+            None
+        };
+        if let Some(reason) = reject {
+            self.log(decision(None, false, false, reason));
+            return None;
+        }
+
+        // 多级别共振
+        let window = Duration::seconds(self.policy.escalate_window);
+        let recent = self
+            .recent
+            .entry((
+                c.symbol.to_string(),
+                c.event.to_string(),
+                c.direction.to_string(),
+            ))
+            .or_default();
+        recent.retain(|(freq, t)| *t > now - window && freq != c.freq);
+        recent.push((c.freq.to_string(), now));
+        let escalated =
+            self.policy.escalate_freqs >= 2 && recent.len() >= self.policy.escalate_freqs;
+
+        // 共振通知按标的去重，普通通知按级别去重
+        let dedup_key = (
+            c.symbol.to_string(),
+            if escalated { "*" } else { c.freq }.to_string(),
+            c.event.to_string(),
+            c.direction.to_string(),
+        );
+        if let Some(last) = self.last_sent.get(&dedup_key) {
+            if now - *last < Duration::seconds(self.policy.dedup_window) {
+                self.log(decision(None, false, escalated, "duplicate within window"));
+                return None;
+            }
+        }
+
+        let mut allowed = vec![];
+        if self.channel_sent.len() < channels.len() {
+            self.channel_sent
+                .resize_with(channels.len(), Default::default);
+        }
+        for (i, channel) in channels.iter().enumerate() {
+            let sent = &mut self.channel_sent[i];
+            while sent
+                .front()
+                .map(|t| *t <= now - Duration::minutes(1))
+                .unwrap_or(false)
+            {
+                sent.pop_front();
+            }
+            if self.policy.rate_limit > 0 && sent.len() >= self.policy.rate_limit {
+                self.log(decision(
+                    Some(channel.clone()),
+                    false,
+                    escalated,
+                    "rate limited",
+                ));
+                continue;
+            }
+            sent.push_back(now);
+            allowed.push(i);
+            let reason = if escalated {
+                "multiple freqs agree"
+            } else {
+                "new signal"
+            };
+            self.log(decision(Some(channel.clone()), true, escalated, reason));
+        }
+        if !allowed.is_empty() {
+            self.last_sent.insert(dedup_key, now);
+        }
+        Some((escalated, allowed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{FixedOffset, TimeZone};
+
+    use super::{Candidate, NotifyPolicy, PolicyState};
+    use crate::calendar::Market;
+    use crate::element::chan::DT;
+
+    fn on(month: u32, day: u32, h: u32, m: u32) -> DT {
+        FixedOffset::east_opt(8 * 3600)
+            .unwrap()
+            .with_ymd_and_hms(2024, month, day, h, m, 0)
+            .unwrap()
+    }
+
+    fn at(h: u32, m: u32) -> DT {
+        // 2024-09-18 为周三，中秋节后第一个交易日
+        on(9, 18, h, m)
+    }
+
+    fn candidate(freq: &str, dt: DT) -> Candidate<'_> {
+        Candidate {
+            symbol: "000001.SH",
+            freq,
+            event: "D1-MACD面积背驰",
+            direction: "底",
+            dt,
+            realtime: true,
+            market: None,
+        }
+    }
+
+    #[test]
+    fn dedup_escalate_and_suppress() {
+        let mut state = PolicyState::new(NotifyPolicy {
+            quiet_hours: vec!["22:00-07:00".to_string()],
+            market: Some(Market::CN),
+            ..Default::default()
+        });
+        let channels = vec!["feishu".to_string()];
+
+        let now = at(10, 0);
+        assert_eq!(
+            state.decide(&candidate("F5", now), &channels, now),
+            Some((false, vec![0]))
+        );
+        // 窗口内重复
+        let now = at(10, 5);
+        assert_eq!(state.decide(&candidate("F5", now), &channels, now), None);
+        // 另一个级别同向，升级为共振
+        assert_eq!(
+            state.decide(&candidate("F30", now), &channels, now),
+            Some((true, vec![0]))
+        );
+        // 午休、中秋休市、静默时段、过期信号
+        let now = at(12, 0);
+        assert_eq!(state.decide(&candidate("F1", now), &channels, now), None);
+        let now = on(9, 17, 10, 0);
+        assert_eq!(state.decide(&candidate("F1", now), &channels, now), None);
+        let now = at(23, 0);
+        assert_eq!(state.decide(&candidate("F1", now), &channels, now), None);
+        let now = at(14, 0);
+        assert_eq!(
+            state.decide(&candidate("F1", at(10, 0)), &channels, now),
+            None
+        );
+
+        let reasons: Vec<_> = state.decisions().map(|d| d.reason.as_str()).collect();
+        assert_eq!(
+            reasons,
+            vec![
+                "new signal",
+                "duplicate within window",
+                "multiple freqs agree",
+                "outside trading session",
+                "outside trading session",
+                "quiet hours",
+                "stale signal",
+            ]
+        );
+    }
+
+    #[test]
+    fn calendar_per_market() {
+        let mut state = PolicyState::new(NotifyPolicy {
+            market: Some(Market::CN),
+            ..Default::default()
+        });
+        let channels = vec!["feishu".to_string()];
+        // A 股已收市，港股仍在交易
+        let now = on(9, 19, 15, 30);
+        assert_eq!(state.decide(&candidate("F5", now), &channels, now), None);
+        let hk = Candidate {
+            symbol: "00700.HK",
+            market: Some(Market::HK),
+            ..candidate("F5", now)
+        };
+        assert_eq!(state.decide(&hk, &channels, now), Some((false, vec![0])));
+    }
+
+    #[test]
+    fn rate_limit_per_channel() {
+        let mut state = PolicyState::new(NotifyPolicy {
+            rate_limit: 1,
+            escalate_freqs: 0,
+            ..Default::default()
+        });
+        // 同类型的两个渠道各自限流
+        let channels = vec!["webhook#0".to_string(), "webhook#1".to_string()];
+        let now = at(10, 0);
+        assert_eq!(
+            state.decide(&candidate("F5", now), &channels[..1], now),
+            Some((false, vec![0]))
+        );
+        assert_eq!(
+            state.decide(&candidate("F30", now), &channels, now),
+            Some((false, vec![1]))
+        );
+        let now = at(10, 1);
+        assert_eq!(
+            state.decide(&candidate("F60", now), &channels, now),
+            Some((false, vec![0, 1]))
+        );
+    }
+}